@@ -8,4 +8,4 @@ cd firmware/pico2
 cargo build --release
 cd ../..
 
-picotool uf2 convert firmware/pico2/target/thumbv8m.main-none-eabihf/release/copi-firmware-pico2 -t elf firmware-output/copi-firmware-pico2.uf2
\ No newline at end of file
+cargo run --release -q -p copi-uf2 -- convert firmware/pico2/target/thumbv8m.main-none-eabihf/release/copi-firmware-pico2 firmware-output/copi-firmware-pico2.uf2 --family rp2350-arm-s
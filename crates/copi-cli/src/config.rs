@@ -0,0 +1,546 @@
+//! Daemon configuration file.
+//!
+//! The first file found is used, without merging: `--config`, then the
+//! per-user file, then the system one. Without any, the defaults apply.
+
+use std::{
+    collections::BTreeMap,
+    io::Write as _,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use anyhow::{Context, Result, bail};
+use copi_core::{
+    DEFAULT_API_ADDR, DeviceSelector, ServiceOptions,
+    board::{BUILTIN_BOARDS, BoardProfile, PinRef},
+    generated::{request_body::Message, *},
+};
+use serde::Deserialize;
+
+use crate::{
+    peripheral::pwm_init,
+    utils::units::{parse_frequency, pwm_timing},
+};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub daemon: DaemonConfig,
+    pub device: DeviceConfig,
+    pub board: BoardConfig,
+    pub log: LogConfig,
+    /// Pins to set up each time the device connects.
+    pub pins: Vec<PinConfig>,
+    /// File the config was read from.
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DaemonConfig {
+    /// Addresses the HTTP API listens on.
+    pub listen: Vec<String>,
+    /// Bearer tokens accepted by the HTTP API; empty disables authentication.
+    pub auth_tokens: Vec<String>,
+    pub pidfile: Option<PathBuf>,
+    pub request_timeout_ms: Option<u64>,
+    /// How long in-flight requests get to finish once shutdown starts.
+    pub drain_timeout_ms: u64,
+    pub max_pending_requests: usize,
+    pub event_buffer: usize,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        let service = ServiceOptions::default();
+        Self {
+            listen: vec![DEFAULT_API_ADDR.to_string()],
+            auth_tokens: Vec::new(),
+            pidfile: None,
+            request_timeout_ms: None,
+            drain_timeout_ms: 5000,
+            max_pending_requests: service.max_pending_requests,
+            event_buffer: service.event_buffer,
+        }
+    }
+}
+
+impl DaemonConfig {
+    pub fn drain_timeout(&self) -> Duration {
+        Duration::from_millis(self.drain_timeout_ms)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeviceConfig {
+    /// Serial port to open instead of searching by VID/PID.
+    pub port: Option<String>,
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    pub serial_number: Option<String>,
+    /// Name stored on the board with `copi name`.
+    pub name: Option<String>,
+}
+
+impl DeviceConfig {
+    pub fn selector(&self) -> DeviceSelector {
+        DeviceSelector {
+            port: self.port.clone(),
+            vid: self.vid,
+            pid: self.pid,
+            serial_number: self.serial_number.clone(),
+            name: self.name.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BoardConfig {
+    /// Built-in profile to start from.
+    pub profile: String,
+    /// Name for a customised profile; defaults to the built-in's.
+    pub name: Option<String>,
+    /// Replaces the profile's usable GPIOs, e.g. for a carrier board.
+    pub gpios: Option<Vec<u32>>,
+    /// Extra pin names, e.g. `relay1 = 16`.
+    pub aliases: BTreeMap<String, u32>,
+}
+
+impl Default for BoardConfig {
+    fn default() -> Self {
+        Self {
+            profile: BUILTIN_BOARDS[0].to_string(),
+            name: None,
+            gpios: None,
+            aliases: BTreeMap::new(),
+        }
+    }
+}
+
+impl BoardConfig {
+    /// Build the profile, passing problems to `error` as (key, message).
+    /// An unknown base profile falls back to the default one.
+    fn build(&self, error: &mut impl FnMut(String, String)) -> BoardProfile {
+        let mut profile = BoardProfile::builtin(&self.profile).unwrap_or_else(|| {
+            error(
+                "board.profile".into(),
+                format!(
+                    "unknown board `{}`, expected one of {}",
+                    self.profile,
+                    BUILTIN_BOARDS.join(", ")
+                ),
+            );
+            BoardProfile::default()
+        });
+        if let Some(name) = &self.name {
+            profile.name = name.clone();
+        }
+        if let Some(gpios) = &self.gpios {
+            for (i, gpio) in gpios.iter().enumerate() {
+                if *gpio >= 30 {
+                    error(format!("board.gpios[{}]", i), "must be 0..=29".into());
+                }
+            }
+            profile.gpios = gpios.clone();
+            profile.gpios.sort_unstable();
+            profile.gpios.dedup();
+        }
+        for (alias, gpio) in &self.aliases {
+            let key = format!("board.aliases.{}", alias);
+            // Names that look like GPIOs would never be looked up.
+            let plain = BoardProfile {
+                aliases: BTreeMap::new(),
+                gpios: (0..30).collect(),
+                ..profile.clone()
+            };
+            if plain.resolve(alias).is_ok() {
+                error(key, "clashes with a GPIO name".into());
+            } else if let Err(e) = profile.check_pin(*gpio) {
+                error(key, e.to_string());
+            } else {
+                profile.aliases.insert(alias.clone(), *gpio);
+            }
+        }
+        profile
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// Overridden by `COPI_LOG`.
+    pub level: String,
+    pub format: LogFormat,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            format: LogFormat::Text,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+impl LogConfig {
+    pub fn init_logger(&self) {
+        let env = env_logger::Env::default().filter_or("COPI_LOG", &self.level);
+        let mut builder = env_logger::Builder::from_env(env);
+        if let LogFormat::Json = self.format {
+            builder.format(|buf, record| {
+                let line = serde_json::json!({
+                    "time": buf.timestamp().to_string(),
+                    "level": record.level().as_str(),
+                    "target": record.target(),
+                    "message": record.args().to_string(),
+                });
+                writeln!(buf, "{}", line)
+            });
+        }
+        builder.init();
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PinConfig {
+    /// GPIO number or board pin name.
+    pub pin: PinRef,
+    pub mode: PinMode,
+    /// Output level, for `output`.
+    pub value: Option<bool>,
+    /// Frequency such as `1000` or `"1kHz"`, for `pwm`.
+    pub freq: Option<Frequency>,
+    /// Duty cycle in percent, for `pwm`.
+    pub duty: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PinMode {
+    Output,
+    Pwm,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Frequency {
+    Hz(f64),
+    Text(String),
+}
+
+impl Frequency {
+    fn hz(&self) -> Result<f64, String> {
+        match self {
+            Frequency::Hz(hz) if *hz > 0.0 && hz.is_finite() => Ok(*hz),
+            Frequency::Hz(hz) => Err(format!("frequency must be positive, got {}", hz)),
+            Frequency::Text(s) => parse_frequency(s),
+        }
+    }
+}
+
+impl PinConfig {
+    /// The request that puts the pin in its configured state.
+    pub fn message(&self, board: &BoardProfile, sys_clock_hz: u32) -> Result<Message> {
+        let pin = board.resolve_ref(&self.pin)?;
+        Ok(match self.mode {
+            PinMode::Output => Message::GpioOutputInit(GpioOutputInit {
+                pin,
+                value: self.value.unwrap_or(false),
+            }),
+            PinMode::Pwm => {
+                let freq = self.freq.as_ref().context("missing freq")?;
+                let hz = freq.hz().map_err(anyhow::Error::msg)?;
+                let timing = pwm_timing(sys_clock_hz, hz, self.duty.unwrap_or(0))?;
+                Message::PwmInit(pwm_init(pin, &timing))
+            }
+        })
+    }
+}
+
+impl Config {
+    pub fn service_options(&self) -> ServiceOptions {
+        let daemon = &self.daemon;
+        ServiceOptions {
+            request_timeout: daemon.request_timeout_ms.map(Duration::from_millis),
+            max_pending_requests: daemon.max_pending_requests,
+            event_buffer: daemon.event_buffer,
+            auth_tokens: daemon.auth_tokens.clone(),
+            // Problems were reported by `validate`.
+            board: self.board.build(&mut |_, _| {}),
+        }
+    }
+
+    /// Load `path`, or the first default location that exists.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match default_paths().into_iter().find(|p| p.is_file()) {
+                Some(path) => path,
+                None => return Ok(Self::default()),
+            },
+        };
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let mut config =
+            Self::parse(&text).with_context(|| format!("Invalid {}", path.display()))?;
+        config.source = Some(path);
+        Ok(config)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let config: Self = toml::from_str(text)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check what the types alone cannot, reporting every problem with the
+    /// key it belongs to.
+    fn validate(&self) -> Result<()> {
+        let mut errors = Vec::new();
+        let mut error = |key: String, msg: String| errors.push(format!("{}: {}", key, msg));
+
+        let daemon = &self.daemon;
+        if daemon.listen.is_empty() {
+            error("daemon.listen".into(), "needs at least one address".into());
+        }
+        for (i, addr) in daemon.listen.iter().enumerate() {
+            let port = addr.rsplit_once(':').map(|(_, port)| port.parse::<u16>());
+            if !matches!(port, Some(Ok(_))) {
+                error(
+                    format!("daemon.listen[{}]", i),
+                    format!("`{}` is not a host:port address", addr),
+                );
+            }
+        }
+        for (i, token) in daemon.auth_tokens.iter().enumerate() {
+            if token.is_empty() || token.contains(char::is_whitespace) {
+                error(
+                    format!("daemon.auth_tokens[{}]", i),
+                    "must be non-empty and contain no whitespace".into(),
+                );
+            }
+        }
+        if daemon.request_timeout_ms == Some(0) {
+            error(
+                "daemon.request_timeout_ms".into(),
+                "must be positive".into(),
+            );
+        }
+        if daemon.max_pending_requests == 0 {
+            error(
+                "daemon.max_pending_requests".into(),
+                "must be positive".into(),
+            );
+        }
+        if daemon.event_buffer == 0 {
+            error("daemon.event_buffer".into(), "must be positive".into());
+        }
+
+        let device = &self.device;
+        if device.port.is_some()
+            && (device.vid.is_some()
+                || device.pid.is_some()
+                || device.serial_number.is_some()
+                || device.name.is_some())
+        {
+            error(
+                "device.port".into(),
+                "cannot be combined with vid, pid, serial_number or name".into(),
+            );
+        }
+
+        if log::LevelFilter::from_str(&self.log.level).is_err() {
+            error(
+                "log.level".into(),
+                format!(
+                    "`{}` is not one of off, error, warn, info, debug, trace",
+                    self.log.level
+                ),
+            );
+        }
+
+        let board = self.board.build(&mut error);
+        let mut gpios = Vec::new();
+        for (i, pin) in self.pins.iter().enumerate() {
+            let key = |field: &str| format!("pins[{}].{}", i, field);
+            match board.resolve_ref(&pin.pin) {
+                Ok(gpio) => match gpios.iter().position(|g| *g == Some(gpio)) {
+                    Some(j) => error(
+                        key("pin"),
+                        format!("GP{} is already set in pins[{}]", gpio, j),
+                    ),
+                    None => gpios.push(Some(gpio)),
+                },
+                Err(e) => {
+                    error(key("pin"), e.to_string());
+                    gpios.push(None);
+                }
+            }
+            match pin.mode {
+                PinMode::Output => {
+                    for (field, set) in [("freq", pin.freq.is_some()), ("duty", pin.duty.is_some())]
+                    {
+                        if set {
+                            error(key(field), "only applies to mode = \"pwm\"".into());
+                        }
+                    }
+                }
+                PinMode::Pwm => {
+                    if pin.value.is_some() {
+                        error(key("value"), "only applies to mode = \"output\"".into());
+                    }
+                    match &pin.freq {
+                        None => error(key("freq"), "required for mode = \"pwm\"".into()),
+                        Some(freq) => {
+                            if let Err(e) = freq.hz() {
+                                error(key("freq"), e);
+                            }
+                        }
+                    }
+                    if pin.duty.is_some_and(|duty| duty > 100) {
+                        error(key("duty"), "must be 0..=100".into());
+                    }
+                }
+            }
+        }
+
+        if !errors.is_empty() {
+            bail!(errors.join("\n"));
+        }
+        Ok(())
+    }
+}
+
+/// Where the daemon looks for its config when `--config` is not given,
+/// in order of preference.
+pub fn default_paths() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    if let Some(dir) = user_config_dir() {
+        paths.push(dir.join("copi").join("config.toml"));
+    }
+    #[cfg(unix)]
+    paths.push(PathBuf::from("/etc/copi/config.toml"));
+    #[cfg(windows)]
+    if let Some(dir) = std::env::var_os("PROGRAMDATA") {
+        paths.push(PathBuf::from(dir).join("copi").join("config.toml"));
+    }
+    paths
+}
+
+fn user_config_dir() -> Option<PathBuf> {
+    #[cfg(windows)]
+    return std::env::var_os("APPDATA").map(PathBuf::from);
+
+    #[cfg(not(windows))]
+    std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_full() {
+        let config = Config::parse(
+            r#"
+            [daemon]
+            listen = ["127.0.0.1:8899", "[::1]:8899"]
+            auth_tokens = ["secret"]
+            request_timeout_ms = 2000
+
+            [device]
+            serial_number = "E66138935F2B2A2C"
+            name = "bench-left"
+
+            [log]
+            level = "debug"
+            format = "json"
+
+            [board]
+            profile = "pico2"
+
+            [board.aliases]
+            relay1 = 16
+
+            [[pins]]
+            pin = "LED"
+            mode = "output"
+            value = true
+
+            [[pins]]
+            pin = "relay1"
+            mode = "output"
+
+            [[pins]]
+            pin = 15
+            mode = "pwm"
+            freq = "1kHz"
+            duty = 30
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.daemon.listen.len(), 2);
+        assert_eq!(config.device.selector().name.as_deref(), Some("bench-left"));
+        assert_eq!(
+            config.service_options().request_timeout,
+            Some(Duration::from_secs(2))
+        );
+        let board = config.service_options().board;
+        assert_eq!(board.resolve("relay1").unwrap(), 16);
+        assert_eq!(config.pins[2].mode, PinMode::Pwm);
+        assert!(matches!(
+            config.pins[2]
+                .message(&BoardProfile::default(), 150_000_000)
+                .unwrap(),
+            Message::PwmInit(PwmInit { slice: 7, .. })
+        ));
+    }
+
+    #[test]
+    fn errors_name_the_key() {
+        let err = Config::parse("[daemon]\nlistn = []\n").unwrap_err();
+        assert!(err.to_string().contains("listn"), "{}", err);
+
+        let err = Config::parse(
+            r#"
+            [[pins]]
+            pin = 25
+            mode = "output"
+
+            [[pins]]
+            pin = 40
+            mode = "pwm"
+            "#,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(
+            err.contains("pins[1].pin: GP40 is not broken out"),
+            "{}",
+            err
+        );
+        assert!(err.contains("pins[1].freq: required"), "{}", err);
+
+        let err = Config::parse("[board.aliases]\nrelay1 = 23\n")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("board.aliases.relay1: GP23"), "{}", err);
+    }
+}
@@ -0,0 +1,52 @@
+use anyhow::Result;
+use clap::Args;
+use copi_core::client::{Client, DEFAULT_DAEMON_URL};
+
+/// Where to send requests: a running daemon, or the device itself.
+#[derive(Debug, Clone, Args)]
+pub struct Connection {
+    /// Daemon URL
+    #[arg(long, global = true, default_value = DEFAULT_DAEMON_URL, conflicts_with = "serial")]
+    pub url: String,
+
+    /// Talk to the device directly instead of a daemon, optionally on the given port
+    #[arg(
+        long,
+        global = true,
+        value_name = "PORT",
+        num_args = 0..=1,
+        default_missing_value = ""
+    )]
+    pub serial: Option<String>,
+
+    /// Token for a daemon that requires authentication
+    #[arg(long, global = true, env = "COPI_TOKEN", hide_env_values = true)]
+    pub token: Option<String>,
+
+    /// Lease to present with requests, so leased pins can be used
+    #[arg(
+        long = "lease",
+        global = true,
+        value_name = "ID",
+        env = "COPI_LEASE",
+        value_delimiter = ','
+    )]
+    pub leases: Vec<String>,
+}
+
+impl Connection {
+    pub fn connect(&self) -> Result<Client> {
+        let mut client = match self.serial.as_deref() {
+            Some("") => Client::open_serial(None)?,
+            Some(port) => Client::open_serial(Some(port))?,
+            None => match &self.token {
+                Some(token) => Client::connect_daemon(&self.url).with_token(token)?,
+                None => Client::connect_daemon(&self.url),
+            },
+        };
+        for id in &self.leases {
+            client = client.with_lease(id)?;
+        }
+        Ok(client)
+    }
+}
@@ -1,12 +1,335 @@
-use copi_core::{AppState, open_copi_serial, start_api_service, start_usb_cdc_service};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use clap::Args;
+use copi_core::{
+    ATTACH_TIMEOUT, AppState, bind_api_listener,
+    client::Client,
+    error::{Rejected, common_data},
+    generated::{GetCpuFrequency, RequestBody, request_body::Message},
+    open_serial_port, resolve_serial_port, serve_api, start_usb_cdc_service,
+};
+use futures_util::future::try_join_all;
+use sysinfo::{Pid, ProcessesToUpdate, System};
+use tokio::{sync::watch, time::timeout};
+
+use crate::{
+    config::{Config, PinConfig},
+    utils::units::DEFAULT_SYS_CLOCK_HZ,
+};
+
+#[derive(Debug, Args)]
+pub struct Daemon {
+    /// Config file, instead of the per-user or system one
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Write the daemon's PID to this file while it runs
+    #[arg(long, value_name = "PATH")]
+    pidfile: Option<PathBuf>,
+
+    /// Report whether a daemon is already running, then exit
+    #[arg(long)]
+    check: bool,
+}
+
+pub async fn start_daemon(daemon: Daemon, config: Config) -> Result<()> {
+    let pidfile = daemon
+        .pidfile
+        .as_deref()
+        .or(config.daemon.pidfile.as_deref());
+    if daemon.check {
+        return check(pidfile, &config).await;
+    }
 
-pub async fn start_daemon() {
     log::info!("Starting Copi daemon...");
+    if let Some(source) = &config.source {
+        log::info!("Using config {}", source.display());
+    }
+    let _pidfile = pidfile.map(Pidfile::create).transpose()?;
+
     let (request_tx, request_rx) = tokio::sync::mpsc::unbounded_channel();
     let (response_tx, response_rx) = tokio::sync::mpsc::unbounded_channel();
-    let state = AppState::new(request_tx, response_rx);
+    let state = AppState::with_options(request_tx, response_rx, config.service_options());
+
+    let port_name = resolve_serial_port(&config.device.selector()).await?;
+    log::info!("Found device: {:?}", port_name);
+    let port = open_serial_port(&port_name)?;
+    let mut listeners = Vec::new();
+    for addr in &config.daemon.listen {
+        listeners.push(bind_api_listener(addr).await?);
+    }
+
+    let (api_shutdown_tx, api_shutdown_rx) = watch::channel(false);
+    let (usb_shutdown_tx, usb_shutdown_rx) = watch::channel(false);
+
+    state.set_connected(true);
+    let mut usb_task = tokio::spawn(start_usb_cdc_service(
+        port,
+        request_rx,
+        response_tx,
+        usb_shutdown_rx,
+    ));
+    let servers = listeners.into_iter().map(|listener| {
+        let mut shutdown = api_shutdown_rx.clone();
+        serve_api(listener, state.clone(), async move {
+            let _ = shutdown.wait_for(|stop| *stop).await;
+        })
+    });
+    let mut api_task = tokio::spawn(try_join_all(servers));
+
+    state.refresh_device_info(Some(&port_name)).await;
+    state.collect_crash_report().await;
+    apply_pins(&state, &config.pins).await;
+    systemd::notify_ready();
+    let watchdog = systemd::spawn_watchdog(state.clone());
+
+    let (usb_done, api_done) = tokio::select! {
+        _ = shutdown_signal() => {
+            log::info!("Shutting down...");
+            (false, false)
+        }
+        _ = &mut usb_task => {
+            log::warn!("Device link closed, shutting down");
+            (true, false)
+        }
+        res = &mut api_task => {
+            if let Ok(Err(e)) = res {
+                log::error!("API service failed: {:?}", e);
+            }
+            (false, true)
+        }
+    };
+    systemd::notify_stopping();
+    if let Some(watchdog) = watchdog {
+        watchdog.abort();
+    }
+
+    // Refuse new requests and let in-flight ones complete while the device
+    // link is still up.
+    let drain_timeout = config.daemon.drain_timeout();
+    if !api_done {
+        let _ = api_shutdown_tx.send(true);
+        if timeout(drain_timeout, &mut api_task).await.is_err() {
+            log::warn!("In-flight requests did not finish in time");
+            api_task.abort();
+        }
+    }
+
+    // Then release the serial port.
+    if !usb_done {
+        let _ = usb_shutdown_tx.send(true);
+        if timeout(drain_timeout, &mut usb_task).await.is_err() {
+            log::warn!("USB service did not stop in time");
+            usb_task.abort();
+        }
+    }
+    state.set_connected(false);
+    state.close();
+    log::info!("Copi daemon stopped");
+    Ok(())
+}
+
+/// Put the configured pins in their initial state. Failures are logged and
+/// do not stop the daemon, and no request waits longer than
+/// [`ATTACH_TIMEOUT`], so a silent device cannot hold up startup.
+async fn apply_pins(state: &AppState, pins: &[PinConfig]) {
+    if pins.is_empty() {
+        return;
+    }
+    let sys_clock_hz = match query_common(state, Message::GetCpuFrequency(GetCpuFrequency {})).await
+    {
+        Ok(hz) if hz > 0 => hz as u32,
+        _ => DEFAULT_SYS_CLOCK_HZ,
+    };
+    for (i, pin) in pins.iter().enumerate() {
+        let result = match pin.message(state.board(), sys_clock_hz) {
+            Ok(message) => query_common(state, message).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(_) => log::info!("Configured pin {} as {:?}", pin.pin, pin.mode),
+            // E.g. `mode = "pwm"` on firmware without PWM support.
+            Err(e) if e.downcast_ref::<Rejected>().is_some() => {
+                log::warn!("Skipped pins[{}] (pin {}): {:#}", i, pin.pin, e)
+            }
+            Err(e) => log::error!("Failed to apply pins[{}] (pin {}): {:#}", i, pin.pin, e),
+        }
+    }
+}
+
+async fn query_common(state: &AppState, message: Message) -> Result<u64> {
+    let response = state
+        .query_within(
+            RequestBody {
+                message: Some(message),
+            },
+            ATTACH_TIMEOUT,
+        )
+        .await?;
+    common_data(&response)?.with_context(|| format!("Unexpected response: {:?}", response))
+}
+
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+async fn check(pidfile: Option<&Path>, config: &Config) -> Result<()> {
+    if let Some(path) = pidfile {
+        match Pidfile::running_pid(path)? {
+            Some(pid) => println!(
+                "Daemon process {} is running (pidfile {})",
+                pid,
+                path.display()
+            ),
+            None => println!("No daemon process for pidfile {}", path.display()),
+        }
+    }
+
+    let url = local_url(&config.daemon.listen[0]);
+    let mut client = Client::connect_daemon(&url);
+    if let Some(token) = config.daemon.auth_tokens.first() {
+        client = client.with_token(token)?;
+    }
+    match client.is_connected().await {
+        Ok(connected) => {
+            println!(
+                "Daemon is listening on {}, device {}",
+                url,
+                if connected {
+                    "connected"
+                } else {
+                    "disconnected"
+                }
+            );
+            Ok(())
+        }
+        Err(_) => {
+            println!("No daemon is listening on {}", url);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// URL to reach a listen address from this machine; wildcard addresses
+/// are reached over loopback.
+fn local_url(listen: &str) -> String {
+    let (host, port) = listen.rsplit_once(':').unwrap_or((listen, ""));
+    let host = match host {
+        "0.0.0.0" | "" => "127.0.0.1",
+        "[::]" => "[::1]",
+        host => host,
+    };
+    format!("http://{}:{}", host, port)
+}
+
+/// Holds the pidfile for the daemon's lifetime and removes it on drop.
+struct Pidfile {
+    path: PathBuf,
+}
+
+impl Pidfile {
+    fn create(path: &Path) -> Result<Self> {
+        if let Some(pid) = Self::running_pid(path)? {
+            bail!(
+                "Another daemon (pid {}) is running, see {}",
+                pid,
+                path.display()
+            );
+        }
+        std::fs::write(path, format!("{}\n", std::process::id()))
+            .with_context(|| format!("Failed to write pidfile {}", path.display()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// The PID in `path`, if that process is still alive.
+    fn running_pid(path: &Path) -> Result<Option<u32>> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", path.display()));
+            }
+        };
+        let Ok(pid) = contents.trim().parse::<u32>() else {
+            log::warn!("Ignoring invalid pidfile {}", path.display());
+            return Ok(None);
+        };
+        let pid = Pid::from_u32(pid);
+        let mut system = System::new();
+        system.refresh_processes(ProcessesToUpdate::Some(&[pid]), true);
+        Ok(system.process(pid).map(|_| pid.as_u32()))
+    }
+}
+
+impl Drop for Pidfile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            log::warn!("Failed to remove pidfile {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// `sd_notify` support; a no-op unless started by systemd with
+/// `Type=notify` (and `WatchdogSec=` for the watchdog).
+mod systemd {
+    use copi_core::AppState;
+    use tokio::task::JoinHandle;
+
+    #[cfg(target_os = "linux")]
+    pub fn notify_ready() {
+        if let Err(e) = sd_notify::notify(false, &[sd_notify::NotifyState::Ready]) {
+            log::warn!("sd_notify READY failed: {}", e);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn notify_stopping() {
+        let _ = sd_notify::notify(false, &[sd_notify::NotifyState::Stopping]);
+    }
+
+    /// Ping the watchdog at half its interval while the device link is up,
+    /// so systemd restarts a daemon that lost its device.
+    #[cfg(target_os = "linux")]
+    pub fn spawn_watchdog(state: AppState) -> Option<JoinHandle<()>> {
+        let mut usec = 0;
+        if !sd_notify::watchdog_enabled(false, &mut usec) {
+            return None;
+        }
+        let interval = std::time::Duration::from_micros(usec / 2);
+        Some(tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if state.is_connected() {
+                    let _ = sd_notify::notify(false, &[sd_notify::NotifyState::Watchdog]);
+                }
+            }
+        }))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn notify_ready() {}
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn notify_stopping() {}
 
-    let port = open_copi_serial();
-    tokio::spawn(start_api_service(state));
-    start_usb_cdc_service(port, request_rx, response_tx).await;
+    #[cfg(not(target_os = "linux"))]
+    pub fn spawn_watchdog(_state: AppState) -> Option<JoinHandle<()>> {
+        None
+    }
 }
@@ -1,22 +1,329 @@
-use std::{io::Write, path::PathBuf};
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
 
+use anyhow::{Context, Result, bail};
+use clap::Args;
+use copi_uf2::Uf2;
 use rust_embed::Embed;
 
-use crate::utils::check_pico2_info;
+use crate::{
+    connection::Connection,
+    peripheral::reboot_device,
+    utils::{BootDrive, ChipFamily, display_mount_point, find_boot_pico, read_boot_info},
+};
 
 #[derive(Embed)]
 #[folder = "../../firmware-output"]
 struct Firmware;
 
-pub fn flash(pico: PathBuf) {
-    if !check_pico2_info(&pico) {
-        log::warn!("Not a valid pico2 device: {}", pico.display());
-        return;
+/// Written and synced at a time, so progress follows the device.
+const CHUNK_SIZE: usize = 32 * 1024;
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// How long the bootloader may take to reboot after the last block.
+const REBOOT_TIMEOUT: Duration = Duration::from_secs(15);
+/// How long a device told to reboot may take to mount its boot drive.
+const BOOTSEL_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long the firmware may take to show up as a serial device.
+const ENUMERATE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Args)]
+pub struct Flash {
+    /// Path to the pico device; found automatically when omitted
+    #[arg(value_name = "PICO DEVICE")]
+    pico: Option<PathBuf>,
+
+    /// Flash this UF2 or ELF image instead of the embedded Copi firmware
+    #[arg(long, value_name = "IMAGE")]
+    file: Option<PathBuf>,
+
+    /// Write --file under the embedded firmware's name rather than its own
+    #[arg(long, requires = "file")]
+    keep_embedded_name: bool,
+
+    /// Wait for a board in BOOTSEL mode to be connected
+    #[arg(long)]
+    wait: bool,
+
+    /// Don't wait for the board to come back as a Copi device
+    #[arg(long)]
+    no_verify: bool,
+}
+
+/// An image ready to be copied to a boot drive.
+struct Image {
+    /// The file name on the drive.
+    name: String,
+    data: Vec<u8>,
+    uf2: Uf2,
+}
+
+pub fn flash(flash: Flash) -> Result<()> {
+    let drive = match flash.pico {
+        Some(pico) => match read_boot_info(&pico) {
+            Some(info) => BootDrive {
+                mount_point: pico,
+                info,
+            },
+            None => bail!("Not a Pico boot drive: {}", pico.display()),
+        },
+        None => select_drive(flash.wait)?,
+    };
+    let family = drive.info.family;
+    let image = match &flash.file {
+        Some(path) => {
+            let mut image = read_image(path, family)?;
+            if flash.keep_embedded_name {
+                let name = family
+                    .firmware_name()
+                    .with_context(|| format!("There is no Copi firmware for the {}", family))?;
+                image.name = name.to_string();
+            }
+            image
+        }
+        None => embedded_image(family)?,
+    };
+    flash_image(&drive, &image, !flash.no_verify)
+}
+
+#[derive(Debug, Args)]
+pub struct Update {
+    /// Don't wait for the board to come back as a Copi device
+    #[arg(long)]
+    no_verify: bool,
+
+    #[command(flatten)]
+    connection: Connection,
+}
+
+/// Reboot a running Copi device into BOOTSEL mode and flash the embedded
+/// firmware, without anyone touching the board.
+pub async fn update(update: Update) -> Result<()> {
+    let drives_before: Vec<PathBuf> = find_boot_pico()
+        .into_iter()
+        .map(|d| d.mount_point)
+        .collect();
+    let client = update.connection.connect()?;
+    reboot_device(&client, true).await?;
+    // Let go of the serial port before the device goes away.
+    drop(client);
+    log::info!("Waiting for the board to appear in BOOTSEL mode...");
+
+    tokio::task::spawn_blocking(move || {
+        let drive = poll(BOOTSEL_TIMEOUT, || {
+            find_boot_pico()
+                .into_iter()
+                .find(|d| !drives_before.contains(&d.mount_point))
+        })
+        .with_context(|| "The device did not come back in BOOTSEL mode")?;
+        let image = embedded_image(drive.info.family)?;
+        flash_image(&drive, &image, !update.no_verify)
+    })
+    .await?
+}
+
+/// Check `image` against the board and copy it to the boot drive.
+fn flash_image(drive: &BootDrive, image: &Image, verify_boot: bool) -> Result<()> {
+    let family = drive.info.family;
+    if let Some(uf2_family) = image
+        .uf2
+        .families()
+        .into_iter()
+        .find(|f| !family.accepts(*f))
+    {
+        bail!(
+            "{} is built for {}, but the board is an {}",
+            image.name,
+            uf2_family,
+            family
+        );
     }
+    let copi = is_copi_firmware(&image.uf2);
+    if !copi {
+        log::warn!(
+            "{} does not look like Copi firmware; the board will not come back as a Copi device",
+            image.name
+        );
+    }
+    // A board that is already running Copi is not the one being flashed.
+    let ports_before = copi_core::list_copi_serial_ports().unwrap_or_default();
 
-    let uf2 = Firmware::get("copi-firmware-pico2.uf2").unwrap();
-    let mut file = std::fs::File::create(&pico.join("copi-firmware-pico2.uf2")).unwrap();
-    file.write_all(&uf2.data).unwrap();
-    file.flush().unwrap();
-    log::info!("Flashed firmware to: {}", pico.display());
+    log::info!(
+        "Flashing {} to {} {}: {}",
+        image.name,
+        family,
+        drive.info.model,
+        display_mount_point(&drive.mount_point)
+    );
+    write_firmware(&drive.mount_point.join(&image.name), &image.data)?;
+    if !verify_boot {
+        return Ok(());
+    }
+    verify(&drive.mount_point, copi.then_some(ports_before.as_slice()))
+}
+
+fn embedded_image(family: ChipFamily) -> Result<Image> {
+    let name = family.firmware_name().with_context(|| {
+        format!(
+            "There is no Copi firmware for the {}, flash an image with --file",
+            family
+        )
+    })?;
+    let firmware = Firmware::get(name).with_context(|| {
+        format!(
+            "No {} firmware was embedded at build time ({} is missing)",
+            family, name
+        )
+    })?;
+    let uf2 =
+        Uf2::parse(&firmware.data).with_context(|| format!("{} is not a valid UF2 image", name))?;
+    Ok(Image {
+        name: name.to_string(),
+        data: firmware.data.into_owned(),
+        uf2,
+    })
+}
+
+/// Read a UF2 image, or convert an ELF file for `family`'s default UF2
+/// family. The bootloader only takes `.uf2` files, so that is the extension
+/// written.
+fn read_image(path: &Path, family: ChipFamily) -> Result<Image> {
+    let data = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let name = path
+        .with_extension("uf2")
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .with_context(|| format!("Not a file: {}", path.display()))?;
+    if data.starts_with(b"\x7fELF") {
+        let uf2 = Uf2::from_elf(&data, family.uf2_family())
+            .with_context(|| format!("Failed to convert {}", path.display()))?;
+        return Ok(Image {
+            name,
+            data: uf2.to_bytes(),
+            uf2,
+        });
+    }
+    let uf2 = Uf2::parse(&data)
+        .with_context(|| format!("{} is not a valid UF2 image", path.display()))?;
+    Ok(Image { name, data, uf2 })
+}
+
+/// Whether the image carries the USB strings the Copi firmware announces
+/// itself with.
+fn is_copi_firmware(uf2: &Uf2) -> bool {
+    let payload: Vec<u8> = uf2
+        .blocks
+        .iter()
+        .flat_map(|b| b.data.iter().copied())
+        .collect();
+    [b"Enbop".as_slice(), b"Copi".as_slice()]
+        .iter()
+        .all(|needle| payload.windows(needle.len()).any(|w| w == *needle))
+}
+
+/// The one attached board in BOOTSEL mode, waiting for it if asked to.
+fn select_drive(wait: bool) -> Result<BootDrive> {
+    let mut announced = false;
+    loop {
+        let drives = find_boot_pico();
+        match drives.as_slice() {
+            [drive] => return Ok(drive.clone()),
+            [] if wait => {
+                if !announced {
+                    log::info!("Waiting for a Pico; hold BOOTSEL while connecting it...");
+                    announced = true;
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+            [] => bail!(
+                "No Pico in BOOTSEL mode found. Hold BOOTSEL while connecting it, \
+                 or pass --wait"
+            ),
+            _ => {
+                let drives: Vec<String> = drives
+                    .iter()
+                    .map(|d| display_mount_point(&d.mount_point))
+                    .collect();
+                bail!(
+                    "Several Pico boards are in BOOTSEL mode, pass one of: {}",
+                    drives.join(", ")
+                );
+            }
+        }
+    }
+}
+
+fn write_firmware(path: &Path, data: &[u8]) -> Result<()> {
+    let mut file =
+        File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut written = 0;
+    for chunk in data.chunks(CHUNK_SIZE) {
+        let result = file.write_all(chunk).and_then(|()| file.sync_data());
+        written += chunk.len();
+        match result {
+            Ok(()) => {}
+            // The bootloader reboots as soon as it has the last block, so
+            // the drive may vanish before the final sync returns.
+            Err(e) if written == data.len() => {
+                log::debug!("Drive went away after the last block: {}", e)
+            }
+            Err(e) => {
+                eprintln!();
+                return Err(e).with_context(|| format!("Failed to write {}", path.display()));
+            }
+        }
+        eprint!(
+            "\rWriting {:>3}% ({}/{} KiB)",
+            written * 100 / data.len(),
+            written / 1024,
+            data.len() / 1024
+        );
+    }
+    eprintln!();
+    Ok(())
+}
+
+/// Confirm the board took the image: its drive goes away and, for Copi
+/// firmware, a Copi serial device not among `ports_before` appears.
+fn verify(drive: &Path, ports_before: Option<&[String]>) -> Result<()> {
+    poll(REBOOT_TIMEOUT, || {
+        (!drive.join("INFO_UF2.TXT").exists()).then_some(())
+    })
+    .with_context(|| {
+        format!(
+            "{} is still mounted, the board did not accept the firmware",
+            display_mount_point(drive)
+        )
+    })?;
+    let Some(ports_before) = ports_before else {
+        log::info!("✅ The board took the image and rebooted");
+        return Ok(());
+    };
+    let port = poll(ENUMERATE_TIMEOUT, || {
+        copi_core::list_copi_serial_ports()
+            .ok()?
+            .into_iter()
+            .find(|port| !ports_before.contains(port))
+    })
+    .with_context(|| "The board rebooted, but no Copi device appeared")?;
+    log::info!("✅ Copi is running on {}", port);
+    Ok(())
+}
+
+/// Call `f` until it returns something or `timeout` passes.
+fn poll<T>(timeout: Duration, mut f: impl FnMut() -> Option<T>) -> Option<T> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(value) = f() {
+            return Some(value);
+        }
+        if Instant::now() >= deadline {
+            return None;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
 }
@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use clap::{Args, Subcommand};
+use copi_core::lease::{LeaseInfo, LeaseRequest};
+
+use crate::{connection::Connection, utils::units::parse_duration};
+
+#[derive(Debug, Args)]
+pub struct Lease {
+    #[command(subcommand)]
+    command: LeaseCommand,
+
+    #[command(flatten)]
+    connection: Connection,
+}
+
+#[derive(Debug, Subcommand)]
+enum LeaseCommand {
+    /// Lease pins, PWM slices or PIO state machines, printing the lease id
+    Acquire {
+        /// Resources such as GP15, LED, pwm7 or pio0.sm1
+        #[arg(required = true)]
+        resources: Vec<String>,
+        /// Name shown to other clients, defaults to the user name
+        #[arg(long)]
+        owner: Option<String>,
+        /// How long the lease lasts unless renewed, e.g. 30s or 5m
+        #[arg(long, value_parser = parse_duration)]
+        ttl: Option<Duration>,
+        /// Keep renewing the lease until interrupted, then release it
+        #[arg(long)]
+        hold: bool,
+    },
+    /// Extend a lease
+    Renew {
+        id: String,
+        #[arg(long, value_parser = parse_duration)]
+        ttl: Option<Duration>,
+    },
+    /// Give up a lease
+    Release { id: String },
+    /// Show the leases held on the daemon
+    List,
+}
+
+pub async fn lease(lease: Lease) -> Result<()> {
+    let client = lease.connection.connect()?;
+    match lease.command {
+        LeaseCommand::Acquire {
+            resources,
+            owner,
+            ttl,
+            hold,
+        } => {
+            let owner = owner
+                .or_else(|| std::env::var("USER").ok())
+                .or_else(|| std::env::var("USERNAME").ok())
+                .unwrap_or_else(|| "copi".to_string());
+            let request = LeaseRequest {
+                owner,
+                resources,
+                ttl_ms: ttl.map(|ttl| ttl.as_millis() as u64),
+            };
+            let info = client.acquire_lease(&request).await?;
+            println!("{}", info.id);
+            if hold {
+                // Renew at half the lifetime so a slow round trip cannot let
+                // the lease lapse.
+                let every =
+                    Duration::from_millis(info.expires_in_ms / 2).max(Duration::from_millis(100));
+                let mut tick = tokio::time::interval(every);
+                tick.tick().await;
+                loop {
+                    tokio::select! {
+                        _ = tokio::signal::ctrl_c() => break,
+                        _ = tick.tick() => {
+                            client.renew_lease(&info.id, ttl).await?;
+                        }
+                    }
+                }
+                client.release_lease(&info.id).await?;
+            }
+        }
+        LeaseCommand::Renew { id, ttl } => print_lease(&client.renew_lease(&id, ttl).await?),
+        LeaseCommand::Release { id } => client.release_lease(&id).await?,
+        LeaseCommand::List => {
+            for info in client.leases().await? {
+                print_lease(&info);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_lease(info: &LeaseInfo) {
+    let resources: Vec<String> = info.resources.iter().map(|r| r.to_string()).collect();
+    println!(
+        "{}  {}  {}  expires in {:.1}s",
+        info.id,
+        info.owner,
+        resources.join(","),
+        info.expires_in_ms as f64 / 1000.0
+    );
+}
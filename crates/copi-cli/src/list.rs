@@ -0,0 +1,304 @@
+use std::{collections::HashMap, fmt, time::Duration};
+
+use anyhow::Result;
+use clap::Args;
+use copi_core::{
+    DeviceSelector, SerialDevice,
+    client::{Client, DEFAULT_DAEMON_URL},
+    device::DeviceReport,
+    generated::CrashReport,
+    list_serial_devices,
+};
+use serde::Serialize;
+
+use crate::utils::{BootDrive, display_mount_point, find_boot_pico};
+
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+/// How long a board the daemon does not own may take to say what it is.
+/// Firmware from before `GetDeviceInfo` never answers.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Args)]
+pub struct List {
+    /// Print JSON for scripts; with --watch, one object per line
+    #[arg(long)]
+    json: bool,
+
+    /// Keep running and print devices as they are plugged in and out
+    #[arg(long)]
+    watch: bool,
+
+    /// Daemon to ask which device it owns
+    #[arg(long, default_value = DEFAULT_DAEMON_URL)]
+    url: String,
+
+    /// Token for a daemon that requires authentication
+    #[arg(long, env = "COPI_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+enum Entry {
+    /// A board in BOOTSEL mode, ready to be flashed.
+    #[serde(rename_all = "camelCase")]
+    Bootsel {
+        mount_point: String,
+        chip: String,
+        model: String,
+        bootloader: Option<String>,
+    },
+    /// A board running Copi.
+    #[serde(rename_all = "camelCase")]
+    Device {
+        port: String,
+        serial_number: Option<String>,
+        /// Stored on the device with `copi name`.
+        name: Option<String>,
+        firmware_version: Option<String>,
+        /// URL of the daemon that owns the port.
+        daemon: Option<String>,
+        /// Held by another program, so its firmware could not be asked.
+        busy: bool,
+        /// The panic that reset the board, as reported by the daemon.
+        crash: Option<CrashReport>,
+    },
+}
+
+impl Entry {
+    /// What identifies the entry between scans.
+    fn key(&self) -> &str {
+        match self {
+            Entry::Bootsel { mount_point, .. } => mount_point,
+            Entry::Device { port, .. } => port,
+        }
+    }
+}
+
+impl From<BootDrive> for Entry {
+    fn from(drive: BootDrive) -> Self {
+        Entry::Bootsel {
+            mount_point: display_mount_point(&drive.mount_point),
+            chip: drive.info.family.to_string(),
+            model: drive.info.model,
+            bootloader: drive.info.bootloader,
+        }
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Entry::Bootsel {
+                mount_point,
+                chip,
+                model,
+                bootloader,
+            } => write!(
+                f,
+                "BOOTSEL {} {} ({}, bootloader {})",
+                chip,
+                mount_point,
+                model,
+                bootloader.as_deref().unwrap_or("unknown")
+            ),
+            Entry::Device {
+                port,
+                serial_number,
+                name,
+                firmware_version,
+                daemon,
+                busy,
+                crash,
+            } => {
+                write!(f, "Copi {}", port)?;
+                if let Some(name) = name {
+                    write!(f, " \"{}\"", name)?;
+                }
+                write!(
+                    f,
+                    " serial {}",
+                    serial_number.as_deref().unwrap_or("unknown")
+                )?;
+                if let Some(version) = firmware_version {
+                    write!(f, " firmware {}", version)?;
+                }
+                if let Some(daemon) = daemon {
+                    write!(f, " (daemon {})", daemon)?;
+                } else if *busy {
+                    write!(f, " (in use)")?;
+                }
+                if let Some(crash) = crash {
+                    write!(f, ", crashed: {} at {}", crash.message, crash.location)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct WatchEvent<'a> {
+    event: &'static str,
+    #[serde(flatten)]
+    entry: &'a Entry,
+}
+
+pub async fn list(list: List) -> Result<()> {
+    let entries = scan(&list, &HashMap::new()).await;
+    if !list.watch {
+        if list.json {
+            println!("{}", serde_json::to_string_pretty(&entries)?);
+        } else if entries.is_empty() {
+            println!("No devices found");
+        } else {
+            for entry in &entries {
+                println!("{}", entry);
+            }
+        }
+        return Ok(());
+    }
+
+    let mut known: HashMap<String, Entry> = HashMap::new();
+    let mut entries = entries;
+    loop {
+        let current: HashMap<String, Entry> = entries
+            .into_iter()
+            .map(|e| (e.key().to_string(), e))
+            .collect();
+        for (key, entry) in &current {
+            if !known.contains_key(key) {
+                print_change(&list, "added", entry)?;
+            }
+        }
+        for (key, entry) in &known {
+            if !current.contains_key(key) {
+                print_change(&list, "removed", entry)?;
+            }
+        }
+        known = current;
+
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+            _ = tokio::time::sleep(WATCH_INTERVAL) => {}
+        }
+        entries = scan(&list, &known).await;
+    }
+}
+
+fn print_change(list: &List, event: &'static str, entry: &Entry) -> Result<()> {
+    if list.json {
+        println!("{}", serde_json::to_string(&WatchEvent { event, entry })?);
+    } else {
+        let sign = if event == "added" { '+' } else { '-' };
+        println!("{} {}", sign, entry);
+    }
+    Ok(())
+}
+
+/// Everything attached now. Devices already in `known` are not asked again,
+/// since that means opening their port.
+async fn scan(list: &List, known: &HashMap<String, Entry>) -> Vec<Entry> {
+    let mut entries: Vec<Entry> = find_boot_pico().into_iter().map(Entry::from).collect();
+    let devices = list_serial_devices(&DeviceSelector::default()).unwrap_or_else(|e| {
+        log::warn!("{:#}", e);
+        Vec::new()
+    });
+    let mut daemon = None;
+    for device in devices {
+        if let Some(entry) = known.get(&device.port) {
+            entries.push(entry.clone());
+            continue;
+        }
+        if daemon.is_none() {
+            daemon = Some(daemon_device(list).await);
+        }
+        entries.push(describe(device, daemon.as_ref().unwrap().as_ref(), &list.url).await);
+    }
+    entries
+}
+
+/// The device the daemon at `--url` is attached to, if one is running.
+async fn daemon_device(list: &List) -> Option<DeviceReport> {
+    let client = match &list.token {
+        Some(token) => Client::connect_daemon(&list.url).with_token(token).ok()?,
+        None => Client::connect_daemon(&list.url),
+    };
+    client.device().await.ok()
+}
+
+async fn describe(device: SerialDevice, daemon: Option<&DeviceReport>, url: &str) -> Entry {
+    let (report, daemon, busy) = match daemon {
+        Some(report) if report.port.as_ref() == Some(&device.port) => {
+            (Some(report.clone()), Some(url.to_string()), false)
+        }
+        // Ports held elsewhere cannot be opened.
+        _ => match Client::open_serial(Some(&device.port)) {
+            Ok(client) => match tokio::time::timeout(PROBE_TIMEOUT, client.device()).await {
+                Ok(report) => (report.ok(), None, false),
+                Err(_) => {
+                    log::debug!("{} did not answer within {:?}", device.port, PROBE_TIMEOUT);
+                    (None, None, false)
+                }
+            },
+            Err(e) => {
+                log::debug!("{:#}", e);
+                (None, None, true)
+            }
+        },
+    };
+    let report = report.unwrap_or_default();
+    Entry::Device {
+        port: device.port,
+        serial_number: device.serial_number,
+        name: report.name,
+        firmware_version: report.info.map(|i| i.firmware_version),
+        daemon,
+        busy,
+        crash: report.crash,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watch_event_json() {
+        let entry = Entry::Device {
+            port: "/dev/ttyACM0".to_string(),
+            serial_number: Some("E66138935F2B2A2C".to_string()),
+            name: Some("bench-left".to_string()),
+            firmware_version: Some("0.1.0".to_string()),
+            daemon: None,
+            busy: false,
+            crash: Some(CrashReport {
+                message: "attempt to divide by zero".to_string(),
+                location: "src/pio.rs:42".to_string(),
+            }),
+        };
+        let json = serde_json::to_value(WatchEvent {
+            event: "added",
+            entry: &entry,
+        })
+        .unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "event": "added",
+                "kind": "device",
+                "port": "/dev/ttyACM0",
+                "serialNumber": "E66138935F2B2A2C",
+                "name": "bench-left",
+                "firmwareVersion": "0.1.0",
+                "daemon": null,
+                "busy": false,
+                "crash": {
+                    "message": "attempt to divide by zero",
+                    "location": "src/pio.rs:42",
+                },
+            })
+        );
+        assert_eq!(entry.key(), "/dev/ttyACM0");
+    }
+}
@@ -0,0 +1,87 @@
+use anyhow::Result;
+use clap::Args;
+use copi_core::logs::LogEntry;
+
+use crate::connection::Connection;
+
+#[derive(Debug, Args)]
+pub struct Logs {
+    /// Keep printing lines as the device sends them
+    #[arg(short, long)]
+    follow: bool,
+
+    /// Only lines at this level or above: error, warn, info, debug or trace
+    #[arg(long, default_value = "trace")]
+    level: log::Level,
+
+    /// Print each line as a JSON object
+    #[arg(long)]
+    json: bool,
+
+    #[command(flatten)]
+    connection: Connection,
+}
+
+pub async fn logs(logs: Logs) -> Result<()> {
+    let client = logs.connection.connect()?;
+    if !logs.follow {
+        for entry in client.logs().await? {
+            print_entry(&logs, &entry)?;
+        }
+        return Ok(());
+    }
+
+    let mut stream = client.follow_logs().await?;
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+            entry = stream.next() => match entry {
+                Some(entry) => print_entry(&logs, &entry?)?,
+                None => anyhow::bail!("Log stream closed"),
+            },
+        }
+    }
+}
+
+fn print_entry(logs: &Logs, entry: &LogEntry) -> Result<()> {
+    if log::Level::from(entry.level) > logs.level {
+        return Ok(());
+    }
+    if logs.json {
+        println!("{}", serde_json::to_string(entry)?);
+    } else {
+        println!(
+            "{} {:<5} {}: {}",
+            clock(entry.timestamp_ms),
+            log::Level::from(entry.level),
+            entry.module,
+            entry.message
+        );
+    }
+    Ok(())
+}
+
+/// `HH:MM:SS.mmm` in UTC.
+fn clock(timestamp_ms: u64) -> String {
+    let ms = timestamp_ms % 1000;
+    let secs = timestamp_ms / 1000 % 86_400;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        ms
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock() {
+        assert_eq!(clock(0), "00:00:00.000");
+        // 2024-01-01T13:05:09.042Z
+        assert_eq!(clock(1_704_114_309_042), "13:05:09.042");
+    }
+}
@@ -1,10 +1,16 @@
-use std::path::PathBuf;
-
 use clap::{Parser, Subcommand};
 
+mod config;
+mod connection;
 mod daemon;
 mod flash;
+mod lease;
+mod list;
+mod logs;
+mod monitor;
+mod peripheral;
 mod query;
+mod shell;
 mod utils;
 
 #[derive(Parser, Debug)]
@@ -16,52 +22,102 @@ struct Cli {
 
 #[derive(Subcommand, Debug)]
 enum Commands {
-    /// List all connected boot pico devices
-    List,
+    /// List boards in BOOTSEL mode and running Copi devices
+    List(list::List),
 
     /// Flash copi firmware to the pico device
-    Flash {
-        /// Path to the pico device
-        #[arg(value_name = "PICO DEVICE")]
-        pico: PathBuf,
-    },
+    Flash(flash::Flash),
 
-    Daemon,
+    /// Run the daemon that serves the HTTP API for a connected device
+    Daemon(daemon::Daemon),
 
-    Query(Query),
-}
+    /// Send requests to the daemon, from arguments or newline-delimited JSON
+    Query(query::Query),
+
+    /// Interactive shell with message and field completion
+    Shell(shell::Shell),
+
+    /// Control GPIO pins
+    Gpio(peripheral::Gpio),
+
+    /// Control PWM outputs
+    Pwm(peripheral::Pwm),
+
+    /// Load and run PIO programs
+    Pio(peripheral::Pio),
+
+    /// Live terminal dashboard of pins and requests
+    Monitor(monitor::Monitor),
+
+    /// Lease pins and peripherals so other clients cannot use them
+    Lease(lease::Lease),
+
+    /// Release every pin and peripheral on the device
+    Reset(peripheral::Reset),
+
+    /// Show or set the name stored on the device
+    Name(peripheral::Name),
+
+    /// Print log lines sent by the device firmware
+    Logs(logs::Logs),
 
-#[derive(Debug, Parser)]
-struct Query {
-    args: Vec<String>,
+    /// Reboot the device, optionally into BOOTSEL mode
+    Reboot(peripheral::Reboot),
+
+    /// Reboot the device into BOOTSEL mode and flash the embedded firmware
+    Update(flash::Update),
 }
 
 #[tokio::main]
 async fn main() {
-    let env = env_logger::Env::default().filter_or("COPI_LOG", "info");
-    env_logger::init_from_env(env);
-
     let cli = Cli::parse();
 
+    // The daemon's config decides how it logs, so load it first.
+    let daemon_config = match &cli.command {
+        Some(Commands::Daemon(d)) => match config::Config::load(d.config.as_deref()) {
+            Ok(config) => Some(config),
+            Err(e) => {
+                eprintln!("{:#}", e);
+                std::process::exit(1);
+            }
+        },
+        _ => None,
+    };
+    match &daemon_config {
+        Some(config) => config.log.init_logger(),
+        None => config::LogConfig::default().init_logger(),
+    }
+
     if let Some(cmd) = cli.command {
         match cmd {
-            Commands::List => {
-                utils::list_boot_pico();
-                return;
-            }
-            Commands::Flash { pico } => {
-                log::info!("Flashing copi firmware to: {}", pico.display());
-                flash::flash(pico);
-                return;
-            }
-            Commands::Daemon => {
-                daemon::start_daemon().await;
-                return;
+            Commands::List(l) => exit_on_error(list::list(l).await),
+            Commands::Flash(f) => exit_on_error(flash::flash(f)),
+            Commands::Daemon(d) => {
+                let config = daemon_config.unwrap_or_default();
+                exit_on_error(daemon::start_daemon(d, config).await)
             }
             Commands::Query(q) => {
-                query::start_query(q.args).await;
-                return;
+                let code = query::start_query(q).await;
+                std::process::exit(code);
             }
+            Commands::Shell(s) => exit_on_error(shell::start_shell(s).await),
+            Commands::Gpio(g) => exit_on_error(peripheral::gpio(g).await),
+            Commands::Pwm(p) => exit_on_error(peripheral::pwm(p).await),
+            Commands::Pio(p) => exit_on_error(peripheral::pio(p).await),
+            Commands::Monitor(m) => exit_on_error(monitor::start_monitor(m).await),
+            Commands::Lease(l) => exit_on_error(lease::lease(l).await),
+            Commands::Reset(r) => exit_on_error(peripheral::reset(r).await),
+            Commands::Name(n) => exit_on_error(peripheral::name(n).await),
+            Commands::Logs(l) => exit_on_error(logs::logs(l).await),
+            Commands::Reboot(r) => exit_on_error(peripheral::reboot(r).await),
+            Commands::Update(u) => exit_on_error(flash::update(u).await),
         }
     }
 }
+
+fn exit_on_error(result: anyhow::Result<()>) {
+    if let Err(e) = result {
+        log::error!("{:#}", e);
+        std::process::exit(1);
+    }
+}
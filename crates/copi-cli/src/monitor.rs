@@ -0,0 +1,309 @@
+use std::{collections::VecDeque, time::Duration};
+
+use anyhow::Result;
+use clap::Parser;
+use copi_core::{
+    client::Client,
+    error::common_data,
+    events::Event,
+    generated::{GpioOutputInit, GpioOutputSet, RequestBody, request_body::Message},
+    shadow::{DeviceShadow, PinRole, PinShadow},
+};
+use crossterm::event::{Event as TermEvent, EventStream, KeyCode, KeyEventKind};
+use futures_util::StreamExt as _;
+use ratatui::{
+    DefaultTerminal, Frame,
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, List, ListItem, Paragraph, Row, Table, TableState},
+};
+
+use crate::connection::Connection;
+
+const MAX_RECENT_REQUESTS: usize = 100;
+
+#[derive(Debug, Parser)]
+pub struct Monitor {
+    #[command(flatten)]
+    connection: Connection,
+}
+
+struct RecentRequest {
+    summary: String,
+    ok: bool,
+    latency_us: u64,
+}
+
+struct App {
+    shadow: DeviceShadow,
+    recent: VecDeque<RecentRequest>,
+    /// Device link state as reported by the service, if known.
+    connected: Option<bool>,
+    /// Whether we are receiving events from the service.
+    streaming: bool,
+    status: String,
+    table: TableState,
+}
+
+impl App {
+    fn apply(&mut self, event: Event) {
+        match event {
+            Event::Request {
+                request,
+                response,
+                error,
+                latency_us,
+            } => {
+                let ok = match &response {
+                    Some(response) => {
+                        self.shadow.apply(&request, response);
+                        common_data(response).is_ok()
+                    }
+                    None => false,
+                };
+                let mut summary = request_summary(&request);
+                if let Some(error) = error {
+                    summary = format!("{} ({})", summary, error);
+                } else if let Some(Err(e)) = response.as_ref().map(common_data) {
+                    summary = format!("{} ({})", summary, e.name());
+                }
+                self.recent.push_front(RecentRequest {
+                    summary,
+                    ok,
+                    latency_us,
+                });
+                self.recent.truncate(MAX_RECENT_REQUESTS);
+            }
+            Event::Connection { connected } => {
+                // The service forgets pin state when a device attaches.
+                if connected {
+                    self.shadow = DeviceShadow::default();
+                }
+                self.connected = Some(connected);
+            }
+            Event::Device { .. } | Event::Log(_) => {}
+        }
+    }
+
+    fn selected_pin(&self) -> u32 {
+        self.table.selected().unwrap_or(0) as u32
+    }
+}
+
+pub async fn start_monitor(monitor: Monitor) -> Result<()> {
+    let client = monitor.connection.connect()?;
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, &client).await;
+    ratatui::restore();
+    result
+}
+
+async fn run(terminal: &mut DefaultTerminal, client: &Client) -> Result<()> {
+    let mut app = App {
+        shadow: client.state().await.unwrap_or_default(),
+        recent: VecDeque::new(),
+        connected: client.is_connected().await.ok(),
+        streaming: false,
+        status: "q: quit  ↑/↓: select  o: make output  space: toggle".to_string(),
+        table: TableState::default().with_selected(0),
+    };
+
+    let mut events = match client.events().await {
+        Ok(events) => {
+            app.streaming = true;
+            Some(events)
+        }
+        Err(e) => {
+            app.status = format!("Events unavailable: {:#}", e);
+            None
+        }
+    };
+    let mut term_events = EventStream::new();
+    let mut tick = tokio::time::interval(Duration::from_millis(250));
+
+    loop {
+        terminal.draw(|frame| draw(frame, &mut app))?;
+
+        tokio::select! {
+            term_event = term_events.next() => {
+                let Some(Ok(TermEvent::Key(key))) = term_event else {
+                    continue;
+                };
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                    KeyCode::Down | KeyCode::Char('j') => {
+                        let next = (app.selected_pin() as usize + 1).min(app.shadow.pins.len() - 1);
+                        app.table.select(Some(next));
+                    }
+                    KeyCode::Up | KeyCode::Char('k') => {
+                        let prev = (app.selected_pin() as usize).saturating_sub(1);
+                        app.table.select(Some(prev));
+                    }
+                    KeyCode::Char('o') => {
+                        let pin = app.selected_pin();
+                        let message = Message::GpioOutputInit(GpioOutputInit { pin, value: false });
+                        app.status = send(client, message).await;
+                    }
+                    KeyCode::Char(' ') | KeyCode::Enter => {
+                        let pin = app.selected_pin();
+                        let shadow = &app.shadow.pins[pin as usize];
+                        if shadow.role != PinRole::GpioOutput {
+                            app.status = format!("GP{} is not a GPIO output, press o first", pin);
+                            continue;
+                        }
+                        let value = !shadow.level.unwrap_or(false);
+                        let message = Message::GpioOutputSet(GpioOutputSet { pin, value });
+                        app.status = send(client, message).await;
+                    }
+                    _ => {}
+                }
+            }
+            event = next_event(&mut events) => match event {
+                Some(Ok(event)) => app.apply(event),
+                Some(Err(e)) => {
+                    app.streaming = false;
+                    app.status = format!("Event stream failed: {:#}", e);
+                    events = None;
+                }
+                None => {
+                    app.streaming = false;
+                    app.status = "Event stream closed".to_string();
+                    events = None;
+                }
+            },
+            _ = tick.tick() => {}
+        }
+    }
+}
+
+async fn next_event(events: &mut Option<copi_core::client::EventStream>) -> Option<Result<Event>> {
+    match events {
+        Some(events) => events.next().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn send(client: &Client, message: Message) -> String {
+    let request = RequestBody {
+        message: Some(message),
+    };
+    let summary = request_summary(&request);
+    match client.query(request).await {
+        Ok(response) => match common_data(&response) {
+            Ok(_) => format!("{}: ok", summary),
+            Err(e) => format!("{}: {}", summary, e),
+        },
+        Err(e) => format!("{}: {:#}", summary, e),
+    }
+}
+
+fn request_summary(request: &RequestBody) -> String {
+    match &request.message {
+        Some(message) => serde_json::to_string(message).unwrap_or_default(),
+        None => "(empty)".to_string(),
+    }
+}
+
+fn draw(frame: &mut Frame, app: &mut App) {
+    let [main, status] =
+        Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+    let [pins, right] =
+        Layout::horizontal([Constraint::Length(44), Constraint::Min(0)]).areas(main);
+    let [connection, requests] =
+        Layout::vertical([Constraint::Length(4), Constraint::Min(0)]).areas(right);
+
+    let rows = app.shadow.pins.iter().enumerate().map(|(i, pin)| {
+        Row::new(vec![
+            format!("GP{}", i),
+            role_name(pin.role).to_string(),
+            pin_value(pin),
+        ])
+    });
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(5),
+            Constraint::Length(12),
+            Constraint::Min(0),
+        ],
+    )
+    .header(Row::new(vec!["Pin", "Role", "Value"]).style(Style::new().add_modifier(Modifier::BOLD)))
+    .block(Block::bordered().title("Pins"))
+    .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(table, pins, &mut app.table);
+
+    let (device, device_color) = match app.connected {
+        Some(true) => ("connected", Color::Green),
+        Some(false) => ("disconnected", Color::Red),
+        None => ("unknown", Color::Yellow),
+    };
+    let (stream, stream_color) = if app.streaming {
+        ("live", Color::Green)
+    } else {
+        ("offline", Color::Red)
+    };
+    let connection_text = vec![
+        Line::styled(format!("Device: {}", device), Style::new().fg(device_color)),
+        Line::styled(format!("Events: {}", stream), Style::new().fg(stream_color)),
+    ];
+    frame.render_widget(
+        Paragraph::new(connection_text).block(Block::bordered().title("Connection")),
+        connection,
+    );
+
+    let items = app.recent.iter().map(|r| {
+        let style = if r.ok {
+            Style::new()
+        } else {
+            Style::new().fg(Color::Red)
+        };
+        ListItem::new(format!(
+            "{:>8.1} ms  {}",
+            r.latency_us as f64 / 1000.0,
+            r.summary
+        ))
+        .style(style)
+    });
+    frame.render_widget(
+        List::new(items).block(Block::bordered().title("Recent requests")),
+        requests,
+    );
+
+    frame.render_widget(Paragraph::new(app.status.as_str()), status);
+}
+
+fn role_name(role: PinRole) -> &'static str {
+    match role {
+        PinRole::Unknown => "?",
+        PinRole::None => "-",
+        PinRole::GpioInput => "GPIO in",
+        PinRole::GpioOutput => "GPIO out",
+        PinRole::PwmOut => "PWM out",
+        PinRole::PwmIn => "PWM in",
+        PinRole::Pio => "PIO",
+    }
+}
+
+fn pin_value(pin: &PinShadow) -> String {
+    match pin.role {
+        PinRole::GpioInput | PinRole::GpioOutput => match pin.level {
+            Some(true) => "high".to_string(),
+            Some(false) => "low".to_string(),
+            None => "?".to_string(),
+        },
+        PinRole::PwmOut | PinRole::PwmIn => match pin.duty_percent {
+            Some(duty) => format!("{}%", duty),
+            None => "?".to_string(),
+        },
+        PinRole::Pio => format!(
+            "PIO{} SM{}",
+            pin.pio_block.unwrap_or_default(),
+            pin.pio_sm.unwrap_or_default()
+        ),
+        PinRole::Unknown | PinRole::None => String::new(),
+    }
+}
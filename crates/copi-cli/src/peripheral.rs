@@ -0,0 +1,427 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::{Args, Subcommand};
+use copi_core::{
+    client::Client,
+    error::common_data,
+    generated::{self, request_body::Message, *},
+};
+
+use crate::{
+    connection::Connection,
+    utils::units::{
+        DEFAULT_SYS_CLOCK_HZ, PwmTiming, parse_frequency, parse_level, parse_percent, pwm_slice,
+        pwm_timing,
+    },
+};
+
+#[derive(Debug, Args)]
+pub struct Gpio {
+    #[command(subcommand)]
+    command: GpioCommand,
+
+    #[command(flatten)]
+    connection: Connection,
+}
+
+#[derive(Debug, Subcommand)]
+enum GpioCommand {
+    /// Configure a pin as output with an initial level
+    Init {
+        /// GPIO number or board pin name, e.g. 25, GP15 or LED
+        pin: String,
+        #[arg(value_parser = parse_level, default_value = "low")]
+        level: bool,
+    },
+    /// Drive an output pin high or low
+    Set {
+        /// GPIO number or board pin name, e.g. 25, GP15 or LED
+        pin: String,
+        #[arg(value_parser = parse_level)]
+        level: bool,
+    },
+    /// Read the level of an output pin
+    Get {
+        /// GPIO number or board pin name, e.g. 25, GP15 or LED
+        pin: String,
+    },
+    /// Release an output pin
+    Deinit {
+        /// GPIO number or board pin name, e.g. 25, GP15 or LED
+        pin: String,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct Pwm {
+    #[command(subcommand)]
+    command: PwmCommand,
+
+    #[command(flatten)]
+    connection: Connection,
+}
+
+#[derive(Debug, Subcommand)]
+enum PwmCommand {
+    /// Start PWM output on a pin, e.g. `--pin 15 --freq 1k --duty 30%`
+    Set {
+        /// GPIO number or board pin name, e.g. 25, GP15 or LED
+        #[arg(long)]
+        pin: String,
+        /// Frequency in Hz, with optional k/M suffix
+        #[arg(long, value_parser = parse_frequency)]
+        freq: f64,
+        /// Duty cycle, 0-100%
+        #[arg(long, value_parser = parse_percent, default_value = "50%")]
+        duty: u32,
+    },
+    /// Change the duty cycle of a running PWM pin
+    Duty {
+        /// GPIO number or board pin name, e.g. 25, GP15 or LED
+        #[arg(long)]
+        pin: String,
+        #[arg(value_parser = parse_percent)]
+        duty: u32,
+    },
+    /// Stop the PWM slice driving a pin and release its pins
+    Deinit {
+        /// GPIO number or board pin name, e.g. 25, GP15 or LED
+        #[arg(long)]
+        pin: String,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct Pio {
+    #[command(subcommand)]
+    command: PioCommand,
+
+    #[command(flatten)]
+    connection: Connection,
+}
+
+#[derive(Debug, Subcommand)]
+enum PioCommand {
+    /// Assemble a .pio file and load it into a PIO block
+    Load {
+        file: PathBuf,
+        #[arg(long, value_parser = clap::value_parser!(u32).range(0..3))]
+        block: u32,
+    },
+    /// Configure a state machine with the loaded program on a pin
+    SmInit {
+        #[arg(long, value_parser = clap::value_parser!(u32).range(0..3))]
+        block: u32,
+        #[arg(long, value_parser = clap::value_parser!(u32).range(0..4))]
+        sm: u32,
+        /// GPIO number or board pin name, e.g. 25, GP15 or LED
+        #[arg(long)]
+        pin: String,
+    },
+    /// Start a state machine
+    Enable {
+        #[arg(long, value_parser = clap::value_parser!(u32).range(0..3))]
+        block: u32,
+        #[arg(long, value_parser = clap::value_parser!(u32).range(0..4))]
+        sm: u32,
+    },
+    /// Stop a state machine
+    Disable {
+        #[arg(long, value_parser = clap::value_parser!(u32).range(0..3))]
+        block: u32,
+        #[arg(long, value_parser = clap::value_parser!(u32).range(0..4))]
+        sm: u32,
+    },
+    /// Push a word into a state machine's TX FIFO
+    Push {
+        #[arg(long, value_parser = clap::value_parser!(u32).range(0..3))]
+        block: u32,
+        #[arg(long, value_parser = clap::value_parser!(u32).range(0..4))]
+        sm: u32,
+        value: u32,
+    },
+    /// Stop a state machine and release its pin
+    SmDeinit {
+        #[arg(long, value_parser = clap::value_parser!(u32).range(0..3))]
+        block: u32,
+        #[arg(long, value_parser = clap::value_parser!(u32).range(0..4))]
+        sm: u32,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct Reset {
+    #[command(flatten)]
+    connection: Connection,
+}
+
+#[derive(Debug, Args)]
+pub struct Reboot {
+    /// Reboot into the USB bootloader, ready to be flashed
+    #[arg(long)]
+    bootsel: bool,
+
+    #[command(flatten)]
+    connection: Connection,
+}
+
+#[derive(Debug, Args)]
+pub struct Name {
+    /// New name for the device; without it, the current one is printed
+    name: Option<String>,
+
+    /// Remove the stored name
+    #[arg(long, conflicts_with = "name")]
+    clear: bool,
+
+    #[command(flatten)]
+    connection: Connection,
+}
+
+pub async fn gpio(gpio: Gpio) -> Result<()> {
+    let client = gpio.connection.connect()?;
+    match gpio.command {
+        GpioCommand::Init { pin, level } => {
+            let pin = resolve_pin(&client, &pin).await?;
+            common(
+                &client,
+                Message::GpioOutputInit(GpioOutputInit { pin, value: level }),
+            )
+            .await?;
+            println!("GP{} output {}", pin, level_name(level));
+        }
+        GpioCommand::Set { pin, level } => {
+            let pin = resolve_pin(&client, &pin).await?;
+            common(
+                &client,
+                Message::GpioOutputSet(GpioOutputSet { pin, value: level }),
+            )
+            .await?;
+            println!("GP{} {}", pin, level_name(level));
+        }
+        GpioCommand::Get { pin } => {
+            let pin = resolve_pin(&client, &pin).await?;
+            let data = common(&client, Message::GpioOutputGet(GpioOutputGet { pin })).await?;
+            println!("GP{} {}", pin, level_name(data != 0));
+        }
+        GpioCommand::Deinit { pin } => {
+            let pin = resolve_pin(&client, &pin).await?;
+            common(&client, Message::PinDeinit(PinDeinit { pin })).await?;
+            println!("GP{} released", pin);
+        }
+    }
+    Ok(())
+}
+
+pub async fn pwm(pwm: Pwm) -> Result<()> {
+    let client = pwm.connection.connect()?;
+    match pwm.command {
+        PwmCommand::Set { pin, freq, duty } => {
+            let pin = resolve_pin(&client, &pin).await?;
+            let sys_clock_hz = sys_clock_hz(&client).await;
+            let timing = pwm_timing(sys_clock_hz, freq, duty)?;
+            let (slice, is_b) = pwm_slice(pin);
+            common(&client, Message::PwmInit(pwm_init(pin, &timing))).await?;
+            println!(
+                "GP{} PWM slice {}{}: {} Hz, {}% (divider {}, top {})",
+                pin,
+                slice,
+                if is_b { 'B' } else { 'A' },
+                sys_clock_hz as f64 / (timing.divider as f64 * (timing.top as f64 + 1.0)),
+                duty,
+                timing.divider,
+                timing.top
+            );
+        }
+        PwmCommand::Duty { pin, duty } => {
+            let pin = resolve_pin(&client, &pin).await?;
+            let message =
+                Message::PwmSetDutyCyclePercent(PwmSetDutyCyclePercent { pin, percent: duty });
+            common(&client, message).await?;
+            println!("GP{} {}%", pin, duty);
+        }
+        PwmCommand::Deinit { pin } => {
+            let pin = resolve_pin(&client, &pin).await?;
+            let (slice, _) = pwm_slice(pin);
+            common(&client, Message::PwmDeinit(PwmDeinit { slice })).await?;
+            println!("PWM slice {} released", slice);
+        }
+    }
+    Ok(())
+}
+
+pub async fn pio(pio: Pio) -> Result<()> {
+    let client = pio.connection.connect()?;
+    match pio.command {
+        PioCommand::Load { file, block } => {
+            let source = std::fs::read_to_string(&file)
+                .with_context(|| format!("Failed to read {}", file.display()))?;
+            let program = copi_core::pio::load_program_message(block, &source)?;
+            let len = program.program_len;
+            common(&client, Message::PioLoadProgram(program)).await?;
+            println!("Loaded {} instructions into PIO{}", len, block);
+        }
+        PioCommand::SmInit { block, sm, pin } => {
+            let pin = resolve_pin(&client, &pin).await?;
+            let message = Message::PioSmInit(PioSmInit {
+                pio_num: block,
+                sm_num: sm,
+                pin_num: pin,
+            });
+            common(&client, message).await?;
+            println!("PIO{} SM{} on GP{}", block, sm, pin);
+        }
+        PioCommand::Enable { block, sm } => sm_set_enable(&client, block, sm, true).await?,
+        PioCommand::Disable { block, sm } => sm_set_enable(&client, block, sm, false).await?,
+        PioCommand::Push { block, sm, value } => {
+            let message = Message::PioSmPush(PioSmPush {
+                pio_num: block,
+                sm_num: sm,
+                instr: value,
+            });
+            common(&client, message).await?;
+            println!("PIO{} SM{} <- {}", block, sm, value);
+        }
+        PioCommand::SmDeinit { block, sm } => {
+            let message = Message::PioSmDeinit(PioSmDeinit {
+                pio_num: block,
+                sm_num: sm,
+            });
+            common(&client, message).await?;
+            println!("PIO{} SM{} released", block, sm);
+        }
+    }
+    Ok(())
+}
+
+/// Release every pin and peripheral on the device.
+pub async fn reset(reset: Reset) -> Result<()> {
+    let client = reset.connection.connect()?;
+    common(&client, Message::ResetAll(ResetAll {})).await?;
+    println!("Device reset");
+    Ok(())
+}
+
+/// Reboot the device, normally or into BOOTSEL mode.
+pub async fn reboot(reboot: Reboot) -> Result<()> {
+    let client = reboot.connection.connect()?;
+    reboot_device(&client, reboot.bootsel).await?;
+    if reboot.bootsel {
+        println!("Device rebooting into BOOTSEL mode");
+    } else {
+        println!("Device rebooting");
+    }
+    Ok(())
+}
+
+/// Show or change the name stored in the device's flash.
+pub async fn name(name: Name) -> Result<()> {
+    let client = name.connection.connect()?;
+    let new_name = if name.clear {
+        Some(String::new())
+    } else {
+        name.name
+    };
+    let Some(new_name) = new_name else {
+        let response = client
+            .query_message(Message::GetDeviceName(GetDeviceName {}))
+            .await?;
+        match response.message {
+            Some(response_body::Message::DeviceName(d)) if d.name.is_empty() => {
+                println!("No name set")
+            }
+            Some(response_body::Message::DeviceName(d)) => println!("{}", d.name),
+            _ => anyhow::bail!("The firmware does not store a name; run copi update"),
+        }
+        return Ok(());
+    };
+
+    // Firmware that does not know SetDeviceName never answers it.
+    let report = client.device().await?;
+    if report
+        .unsupported_messages
+        .iter()
+        .any(|m| m == "setDeviceName")
+    {
+        anyhow::bail!("The firmware does not store a name; run copi update");
+    }
+    common(
+        &client,
+        Message::SetDeviceName(SetDeviceName {
+            name: new_name.clone(),
+        }),
+    )
+    .await?;
+    if new_name.is_empty() {
+        println!("Device name cleared");
+    } else {
+        println!("Device named {}", new_name);
+    }
+    Ok(())
+}
+
+pub(crate) async fn reboot_device(client: &Client, bootsel: bool) -> Result<()> {
+    let message = Message::Reboot(generated::Reboot { bootsel });
+    common(client, message).await?;
+    Ok(())
+}
+
+/// Drive only `pin`'s output of its slice; the other output is left
+/// unassigned.
+pub(crate) fn pwm_init(pin: u32, timing: &PwmTiming) -> PwmInit {
+    let (slice, is_b) = pwm_slice(pin);
+    PwmInit {
+        slice,
+        a: (!is_b).then_some(pin),
+        b: is_b.then_some(pin),
+        divider: timing.divider,
+        compare_a: if is_b { 0 } else { timing.compare },
+        compare_b: if is_b { timing.compare } else { 0 },
+        top: timing.top,
+    }
+}
+
+async fn sm_set_enable(client: &Client, block: u32, sm: u32, enable: bool) -> Result<()> {
+    let message = Message::PioSmSetEnable(PioSmSetEnable {
+        pio_num: block,
+        sm_num: sm,
+        enable,
+    });
+    common(client, message).await?;
+    println!(
+        "PIO{} SM{} {}",
+        block,
+        sm,
+        if enable { "enabled" } else { "disabled" }
+    );
+    Ok(())
+}
+
+/// Resolve a pin argument against the board profile of the daemon or device.
+async fn resolve_pin(client: &Client, pin: &str) -> Result<u32> {
+    client.board().await?.resolve(pin)
+}
+
+/// Send `message` and return `Common.data`, failing on device errors.
+async fn common(client: &Client, message: Message) -> Result<u64> {
+    let response = client.query_message(message).await?;
+    match common_data(&response)? {
+        Some(data) => Ok(data),
+        None => anyhow::bail!("Unexpected response: {:?}", response),
+    }
+}
+
+async fn sys_clock_hz(client: &Client) -> u32 {
+    match common(client, Message::GetCpuFrequency(GetCpuFrequency {})).await {
+        Ok(hz) if hz > 0 => hz as u32,
+        _ => {
+            log::debug!("Using default system clock {} Hz", DEFAULT_SYS_CLOCK_HZ);
+            DEFAULT_SYS_CLOCK_HZ
+        }
+    }
+}
+
+fn level_name(level: bool) -> &'static str {
+    if level { "high" } else { "low" }
+}
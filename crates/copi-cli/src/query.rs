@@ -1,64 +1,309 @@
-use copi_core::generated::RequestBody;
-use copi_core::generated::ResponseBody;
-use copi_core::generated::request_body;
+use std::{
+    io::{BufRead, BufReader},
+    path::PathBuf,
+};
+
+use anyhow::{Context, Result, bail};
+use clap::{Parser, ValueEnum};
+use copi_core::{
+    board::{self, BoardProfile},
+    client::Client,
+    error::common_data,
+    generated::{RequestBody, ResponseBody, request_body},
+    schema,
+};
 use prost::Message;
+use serde_json::json;
+
+use crate::connection::Connection;
+
+#[derive(Debug, Parser)]
+pub struct Query {
+    /// Message name and `key=value` fields, e.g. `gpioOutputInit pin=25 value=true`.
+    /// Without arguments, requests are read from stdin.
+    args: Vec<String>,
+
+    /// Read newline-delimited JSON requests from a file (`-` for stdin)
+    #[arg(short, long, value_name = "FILE", conflicts_with = "args")]
+    input: Option<PathBuf>,
+
+    /// Output format, one result per line
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Json)]
+    format: OutputFormat,
+
+    /// Stop at the first failed request
+    #[arg(long)]
+    fail_fast: bool,
+
+    #[command(flatten)]
+    connection: Connection,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum OutputFormat {
+    Json,
+    Pretty,
+    ProtoHex,
+}
+
+/// Pin fields may use the board's pin names, e.g. `pin=LED`.
+pub(crate) fn parse_message(
+    args: &[String],
+    board: &BoardProfile,
+) -> Result<request_body::Message> {
+    let Some(type_name) = args.first() else {
+        bail!("Missing message name");
+    };
+
+    let mut fields = Vec::new();
+    for arg in &args[1..] {
+        let Some((key, value)) = arg.split_once('=') else {
+            bail!("Invalid argument format, expected key=value: {}", arg);
+        };
+        if key.is_empty() || value.is_empty() {
+            bail!("Key or value cannot be empty: {}", arg);
+        }
+        let value = if board::pin_fields(type_name).contains(&key) {
+            board.resolve(value)?.to_string()
+        } else {
+            value.to_string()
+        };
+        fields.push((key, value));
+    }
+
+    let fields: Vec<(&str, &str)> = fields.iter().map(|(k, v)| (*k, v.as_str())).collect();
+    let value = schema::parse_fields(type_name, &fields)?;
+    serde_json::from_value(value).with_context(|| format!("Invalid {} message", type_name))
+}
+
+/// Parse one input line, either a full `RequestBody` (`{"message": {...}}`)
+/// or just the message (`{"gpioOutputInit": {...}}`).
+pub(crate) fn parse_request_line(line: &str, board: &BoardProfile) -> Result<RequestBody> {
+    let mut value: serde_json::Value =
+        serde_json::from_str(line).with_context(|| "Invalid JSON")?;
+    board.resolve_json(&mut value)?;
+    if value.get("message").is_some() {
+        return serde_json::from_value(value).with_context(|| "Invalid request");
+    }
+    let message = serde_json::from_value(value).with_context(|| "Invalid message")?;
+    Ok(RequestBody {
+        message: Some(message),
+    })
+}
 
-fn parse_message(args: Vec<String>) -> Result<request_body::Message, serde_json::Error> {
-    let type_name = &args[0];
-    let mut map = serde_json::Map::new();
+enum Outcome {
+    Ok,
+    DeviceError,
+    Failed,
+}
 
-    let mut i = 1;
-    while i < args.len() {
-        if !args[i].contains('=') {
-            panic!("Invalid argument format: {}", args[i]);
+/// Run the query command and return the process exit code: 0 if every
+/// request succeeded, 1 if the device reported an error, 2 otherwise.
+pub async fn start_query(query: Query) -> i32 {
+    let client = match query.connection.connect() {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("{:#}", e);
+            return 2;
         }
-        let parts: Vec<&str> = args[i].splitn(2, '=').collect();
-        if parts.len() != 2 {
-            panic!("Invalid argument format: {}", args[i]);
+    };
+    let board = match client.board().await {
+        Ok(board) => board,
+        Err(e) => {
+            eprintln!("{:#}", e);
+            return 2;
         }
-        let key = parts[0].to_string();
-        let value = parts[1].to_string();
-        if key.is_empty() || value.is_empty() {
-            panic!("Key or value cannot be empty: {}", args[i]);
+    };
+
+    if !query.args.is_empty() {
+        let outcome = match parse_message(&query.args, &board) {
+            Ok(message) => {
+                let request = RequestBody {
+                    message: Some(message),
+                };
+                run_one(&client, query.format, None, request).await
+            }
+            Err(e) => report_error(query.format, None, &e),
+        };
+        return exit_code([outcome]);
+    }
+
+    let reader: Box<dyn BufRead> = match &query.input {
+        Some(path) if path.as_os_str() != "-" => match std::fs::File::open(path) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(e) => {
+                eprintln!("Failed to open {}: {}", path.display(), e);
+                return 2;
+            }
+        },
+        _ => Box::new(BufReader::new(std::io::stdin())),
+    };
+
+    let mut outcomes = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line_no = i + 1;
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                eprintln!("Failed to read input: {}", e);
+                outcomes.push(Outcome::Failed);
+                break;
+            }
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
         }
 
-        let val: serde_json::Value = match serde_json::from_str(&value) {
-            Ok(v) => v,
-            Err(_) => serde_json::Value::String(value.clone()),
+        let outcome = match parse_request_line(line, &board) {
+            Ok(request) => run_one(&client, query.format, Some(line_no), request).await,
+            Err(e) => report_error(query.format, Some(line_no), &e),
         };
+        let failed = !matches!(outcome, Outcome::Ok);
+        outcomes.push(outcome);
+        if failed && query.fail_fast {
+            break;
+        }
+    }
+    exit_code(outcomes)
+}
 
-        map.insert(key.clone(), val);
-        i += 1;
+fn exit_code(outcomes: impl IntoIterator<Item = Outcome>) -> i32 {
+    let mut code = 0;
+    for outcome in outcomes {
+        match outcome {
+            Outcome::Ok => {}
+            Outcome::DeviceError => code = code.max(1),
+            Outcome::Failed => code = 2,
+        }
     }
+    code
+}
 
-    serde_json::from_value(serde_json::json!({
-        type_name: map
-    }))
+async fn run_one(
+    client: &Client,
+    format: OutputFormat,
+    line: Option<usize>,
+    request: RequestBody,
+) -> Outcome {
+    let response = match client.query(request).await {
+        Ok(response) => response,
+        Err(e) => return report_error(format, line, &e),
+    };
+    let device_error = common_data(&response).err();
+    print_response(format, line, &response, device_error.as_ref());
+    match device_error {
+        Some(_) => Outcome::DeviceError,
+        None => Outcome::Ok,
+    }
 }
 
-pub async fn start_query(args: Vec<String>) {
-    let parsed = parse_message(args).unwrap_or_else(|e| {
-        panic!("Failed to parse message: {}", e);
+pub(crate) fn print_response(
+    format: OutputFormat,
+    line: Option<usize>,
+    response: &ResponseBody,
+    device_error: Option<&copi_core::error::DeviceError>,
+) {
+    if let OutputFormat::ProtoHex = format {
+        println!("{}", to_hex(&response.encode_to_vec()));
+        if let Some(e) = device_error {
+            eprintln!("{}{}", line_prefix(line), e);
+        }
+        return;
+    }
+
+    let mut result = json!({
+        "ok": device_error.is_none(),
+        "response": response,
     });
+    if let Some(e) = device_error {
+        result["error"] = json!({ "code": e.code, "name": e.name(), "message": e.to_string() });
+    }
+    if let Some(line) = line {
+        result["line"] = json!(line);
+    }
+    print_json(format, &result);
+}
+
+fn report_error(format: OutputFormat, line: Option<usize>, e: &anyhow::Error) -> Outcome {
+    match format {
+        OutputFormat::ProtoHex => eprintln!("{}{:#}", line_prefix(line), e),
+        _ => {
+            let mut result = json!({
+                "ok": false,
+                "error": { "message": format!("{:#}", e) },
+            });
+            if let Some(line) = line {
+                result["line"] = json!(line);
+            }
+            print_json(format, &result);
+        }
+    }
+    Outcome::Failed
+}
+
+pub(crate) fn print_json(format: OutputFormat, value: &serde_json::Value) {
+    match format {
+        OutputFormat::Pretty => println!("{}", serde_json::to_string_pretty(value).unwrap()),
+        _ => println!("{}", value),
+    }
+}
+
+fn line_prefix(line: Option<usize>) -> String {
+    line.map(|l| format!("line {}: ", l)).unwrap_or_default()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use copi_core::generated::{GpioOutputGet, GpioOutputInit};
+
+    #[test]
+    fn parse_args() {
+        let args: Vec<String> = ["gpioOutputInit", "pin=25", "value=true"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let board = BoardProfile::default();
+        let message = parse_message(&args, &board).unwrap();
+        assert_eq!(
+            message,
+            request_body::Message::GpioOutputInit(GpioOutputInit {
+                pin: 25,
+                value: true
+            })
+        );
+        let led: Vec<String> = vec!["gpioOutputGet".into(), "pin=LED".into()];
+        assert_eq!(
+            parse_message(&led, &board).unwrap(),
+            request_body::Message::GpioOutputGet(GpioOutputGet { pin: 25 })
+        );
+        assert!(parse_message(&["gpioOutputInit".into(), "pin".into()], &board).is_err());
+        assert!(parse_message(&[], &board).is_err());
+    }
 
-    let mut request_body = RequestBody::default();
-    request_body.message = Some(parsed);
-    let data = request_body.encode_to_vec();
-
-    // reqwest send request_body protoful message
-    let client = reqwest::Client::new();
-    let response = client
-        .post("http://localhost:8899/query")
-        .header("Content-Type", "application/protobuf")
-        .body(data)
-        .send()
-        .await
-        .expect("Failed to send request");
-    if !response.status().is_success() {
-        panic!("Request failed with status: {}", response.status());
+    #[test]
+    fn parse_lines() {
+        let board = BoardProfile::default();
+        let full = parse_request_line(
+            r#"{"message": {"gpioOutputSet": {"pin": 4, "value": false}}}"#,
+            &board,
+        )
+        .unwrap();
+        let short =
+            parse_request_line(r#"{"gpioOutputSet": {"pin": 4, "value": false}}"#, &board).unwrap();
+        assert_eq!(full, short);
+        assert!(parse_request_line(r#"{"noSuchMessage": {}}"#, &board).is_err());
+        let led = parse_request_line(r#"{"gpioOutputGet": {"pin": "LED"}}"#, &board).unwrap();
+        assert_eq!(
+            led.message,
+            Some(request_body::Message::GpioOutputGet(GpioOutputGet {
+                pin: 25
+            }))
+        );
     }
-    let response_bytes = response.bytes().await.expect("Failed to read response");
-    let response_body =
-        ResponseBody::decode(response_bytes.as_ref()).expect("Failed to decode response body");
-    println!("Response: {:?}", response_body);
 }
@@ -0,0 +1,238 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::Parser;
+use copi_core::{
+    board::BoardProfile,
+    client::Client,
+    error::common_data,
+    generated::RequestBody,
+    schema::{self, FieldKind},
+};
+use rustyline::{
+    Context, Editor, ExternalPrinter as _, Helper, Highlighter, Hinter, Validator,
+    completion::{Completer, Pair},
+    error::ReadlineError,
+    history::DefaultHistory,
+};
+
+use crate::{
+    connection::Connection,
+    query::{OutputFormat, parse_message, print_response},
+};
+
+const HELP: &str = "\
+Usage: <message> [field=value ...]   e.g. gpioOutputInit pin=25 value=true
+       messages                      list request messages
+       fields <message>              list a message's fields
+       help                          show this help
+       exit                          leave the shell
+Press Tab to complete message and field names.";
+
+#[derive(Debug, Parser)]
+pub struct Shell {
+    #[command(flatten)]
+    connection: Connection,
+}
+
+pub async fn start_shell(shell: Shell) -> Result<()> {
+    let client = shell.connection.connect()?;
+    let board = client.board().await?;
+
+    let mut editor: Editor<ShellHelper, DefaultHistory> = Editor::new()?;
+    editor.set_helper(Some(ShellHelper));
+    let history = history_path();
+    if let Some(history) = &history {
+        let _ = editor.load_history(history);
+    }
+
+    // Print events above the prompt while the user is typing.
+    let mut printer = editor.create_external_printer()?;
+    let events_client = client.clone();
+    let events_task = tokio::spawn(async move {
+        let mut events = match events_client.events().await {
+            Ok(events) => events,
+            Err(e) => {
+                let _ = printer.print(format!("Events unavailable: {:#}", e));
+                return;
+            }
+        };
+        while let Some(event) = events.next().await {
+            let text = match event {
+                Ok(event) => format!("event: {}", serde_json::to_string(&event).unwrap()),
+                Err(e) => format!("Event stream failed: {:#}", e),
+            };
+            if printer.print(text).is_err() {
+                break;
+            }
+        }
+    });
+
+    let handle = tokio::runtime::Handle::current();
+    let editor = tokio::task::spawn_blocking(move || {
+        repl(&mut editor, &client, &board, &handle);
+        editor
+    })
+    .await?;
+
+    events_task.abort();
+    if let Some(history) = &history {
+        let mut editor = editor;
+        if let Err(e) = editor.save_history(history) {
+            log::warn!("Failed to save history: {}", e);
+        }
+    }
+    Ok(())
+}
+
+fn repl(
+    editor: &mut Editor<ShellHelper, DefaultHistory>,
+    client: &Client,
+    board: &BoardProfile,
+    handle: &tokio::runtime::Handle,
+) {
+    println!("Copi shell, type `help` for usage.");
+    loop {
+        let line = match editor.readline("copi> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("{}", e);
+                break;
+            }
+        };
+        let args: Vec<String> = line.split_whitespace().map(String::from).collect();
+        let Some(command) = args.first() else {
+            continue;
+        };
+        let _ = editor.add_history_entry(line.as_str());
+
+        match command.as_str() {
+            "exit" | "quit" => break,
+            "help" => println!("{}", HELP),
+            "messages" => {
+                for message in schema::request_messages() {
+                    println!("{}", message.name);
+                }
+            }
+            "fields" => print_fields(args.get(1).map(String::as_str)),
+            _ => {
+                let message = match parse_message(&args, board) {
+                    Ok(message) => message,
+                    Err(e) => {
+                        eprintln!("{:#}", e);
+                        continue;
+                    }
+                };
+                let request = RequestBody {
+                    message: Some(message),
+                };
+                match handle.block_on(client.query(request)) {
+                    Ok(response) => {
+                        let device_error = common_data(&response).err();
+                        print_response(
+                            OutputFormat::Pretty,
+                            None,
+                            &response,
+                            device_error.as_ref(),
+                        );
+                    }
+                    Err(e) => eprintln!("{:#}", e),
+                }
+            }
+        }
+    }
+}
+
+fn print_fields(message: Option<&str>) {
+    let Some(schema) = message.and_then(schema::request_message) else {
+        eprintln!("Usage: fields <message>, see `messages`");
+        return;
+    };
+    for field in &schema.fields {
+        let optional = if field.optional { " (optional)" } else { "" };
+        println!("{}: {}{}", field.name, kind_name(field.kind), optional);
+    }
+}
+
+fn kind_name(kind: FieldKind) -> &'static str {
+    match kind {
+        FieldKind::Bool => "bool",
+        FieldKind::Unsigned => "unsigned integer",
+        FieldKind::Signed => "integer",
+        FieldKind::String => "string",
+        FieldKind::Bytes => "bytes",
+        FieldKind::Other => "value",
+    }
+}
+
+fn history_path() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+    Some(PathBuf::from(home).join(".copi_history"))
+}
+
+#[derive(Helper, Hinter, Highlighter, Validator)]
+struct ShellHelper;
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let before = &line[..pos];
+        let start = before.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let word = &before[start..];
+        let previous: Vec<&str> = before[..start].split_whitespace().collect();
+
+        let Some(message) = previous.first() else {
+            let commands = ["messages", "fields", "help", "exit"];
+            let names = schema::request_messages()
+                .iter()
+                .map(|m| m.name.as_str())
+                .chain(commands);
+            return Ok((start, candidates(names, word, " ")));
+        };
+
+        if *message == "fields" {
+            let names = schema::request_messages().iter().map(|m| m.name.as_str());
+            return Ok((start, candidates(names, word, "")));
+        }
+        let Some(schema) = schema::request_message(message) else {
+            return Ok((start, Vec::new()));
+        };
+
+        if let Some((key, value)) = word.split_once('=') {
+            if schema.field(key).is_some_and(|f| f.kind == FieldKind::Bool) {
+                let start = start + key.len() + 1;
+                return Ok((start, candidates(["true", "false"].into_iter(), value, " ")));
+            }
+            return Ok((start, Vec::new()));
+        }
+
+        let used: Vec<&str> = previous[1..]
+            .iter()
+            .filter_map(|arg| arg.split_once('=').map(|(key, _)| key))
+            .collect();
+        let fields = schema
+            .fields
+            .iter()
+            .map(|f| f.name.as_str())
+            .filter(|name| !used.contains(name));
+        Ok((start, candidates(fields, word, "=")))
+    }
+}
+
+fn candidates<'a>(names: impl Iterator<Item = &'a str>, prefix: &str, suffix: &str) -> Vec<Pair> {
+    names
+        .filter(|name| name.starts_with(prefix))
+        .map(|name| Pair {
+            display: name.to_string(),
+            replacement: format!("{}{}", name, suffix),
+        })
+        .collect()
+}
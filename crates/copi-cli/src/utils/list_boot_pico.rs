@@ -1,51 +1,177 @@
-use std::{fs, path::PathBuf};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
 
+use copi_uf2::Family;
 use sysinfo::{DiskKind, Disks};
 
-pub fn check_pico2_info(path: &PathBuf) -> bool {
-    let info_file = path.join("INFO_UF2.TXT");
-    if info_file.exists() {
-        if let Ok(contents) = fs::read_to_string(&info_file) {
-            if contents.contains("RP2350") {
-                return true;
+/// The chip family a boot drive belongs to, which decides the firmware image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChipFamily {
+    /// The original Pico.
+    Rp2040,
+    /// The Pico 2.
+    Rp2350,
+}
+
+impl ChipFamily {
+    /// The name of the embedded firmware image for this family. Copi
+    /// firmware is only built for the RP2350 so far.
+    pub fn firmware_name(self) -> Option<&'static str> {
+        match self {
+            ChipFamily::Rp2040 => None,
+            ChipFamily::Rp2350 => Some("copi-firmware-pico2.uf2"),
+        }
+    }
+
+    /// The UF2 family ELF files are converted for.
+    pub fn uf2_family(self) -> Family {
+        match self {
+            ChipFamily::Rp2040 => Family::Rp2040,
+            ChipFamily::Rp2350 => Family::Rp2350ArmSecure,
+        }
+    }
+
+    /// Whether an image built for UF2 `family` runs on this chip.
+    pub fn accepts(self, family: Family) -> bool {
+        match family {
+            Family::Rp2040 => self == ChipFamily::Rp2040,
+            Family::Rp2350ArmSecure | Family::Rp2350RiscV | Family::Rp2350ArmNonSecure => {
+                self == ChipFamily::Rp2350
+            }
+            Family::Absolute | Family::Data => true,
+        }
+    }
+}
+
+impl fmt::Display for ChipFamily {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChipFamily::Rp2040 => write!(f, "RP2040"),
+            ChipFamily::Rp2350 => write!(f, "RP2350"),
+        }
+    }
+}
+
+/// What a boot drive says about itself in `INFO_UF2.TXT`, e.g.
+///
+/// ```text
+/// UF2 Bootloader v1.0
+/// Model: Raspberry Pi RP2350
+/// Board-ID: RP2350
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BootInfo {
+    pub family: ChipFamily,
+    pub model: String,
+    pub board_id: String,
+    /// The bootloader version, e.g. `v1.0`.
+    pub bootloader: Option<String>,
+}
+
+impl BootInfo {
+    /// Parse `INFO_UF2.TXT`. Returns `None` for drives that are not a
+    /// Raspberry Pi boot drive.
+    pub fn parse(contents: &str) -> Option<BootInfo> {
+        let mut model = None;
+        let mut board_id = None;
+        let mut bootloader = None;
+        for line in contents.lines().map(str::trim) {
+            if let Some(version) = line.strip_prefix("UF2 Bootloader ") {
+                bootloader = Some(version.trim().to_string());
+            } else if let Some((key, value)) = line.split_once(':') {
+                match key.trim() {
+                    "Model" => model = Some(value.trim().to_string()),
+                    "Board-ID" => board_id = Some(value.trim().to_string()),
+                    _ => {}
+                }
             }
         }
+        let board_id = board_id?;
+        // The RP2040 bootloader calls itself RPI-RP2, the RP2350 one RP2350.
+        let family = match board_id.as_str() {
+            "RPI-RP2" => ChipFamily::Rp2040,
+            id if id.starts_with("RP2350") => ChipFamily::Rp2350,
+            _ => return None,
+        };
+        Some(BootInfo {
+            family,
+            model: model.unwrap_or_else(|| board_id.clone()),
+            board_id,
+            bootloader,
+        })
     }
-    false
 }
 
-pub fn list_boot_pico() {
-    // This function is a placeholder for the actual implementation.
-    // It should contain the logic to list bootable Pico devices.
-    log::info!("Listing bootable Pico devices...");
+/// A board attached in BOOTSEL mode.
+#[derive(Debug, Clone)]
+pub struct BootDrive {
+    pub mount_point: PathBuf,
+    pub info: BootInfo,
+}
+
+/// Read the boot information of the drive mounted at `path`, if it is a
+/// Raspberry Pi boot drive.
+pub fn read_boot_info(path: &Path) -> Option<BootInfo> {
+    let contents = fs::read_to_string(path.join("INFO_UF2.TXT")).ok()?;
+    BootInfo::parse(&contents)
+}
+
+/// The Pico and Pico 2 boards attached in BOOTSEL mode.
+pub fn find_boot_pico() -> Vec<BootDrive> {
     let disks = Disks::new_with_refreshed_list();
-    let disks: Vec<_> = disks
+    disks
         .list()
         .iter()
-        .filter(|d| match d.kind() {
-            DiskKind::Unknown(_) => true,
-            _ => false,
+        .filter(|d| matches!(d.kind(), DiskKind::Unknown(_)))
+        .filter_map(|d| {
+            let mount_point = PathBuf::from(d.mount_point());
+            let info = read_boot_info(&mount_point)?;
+            Some(BootDrive { mount_point, info })
         })
-        .collect();
-    if disks.is_empty() {
-        log::warn!("No bootable Pico devices found.");
-        return;
-    }
-
-    for disk in disks {
-        // check INFO_UF2.TXT
-        // UF2 Bootloader v1.0
-        // Model: Raspberry Pi RP2350
-        // Board-ID: RP2350
-        let mount_point: PathBuf = disk.mount_point().into();
-        if check_pico2_info(&mount_point) {
-            #[cfg(target_os = "windows")]
-            log::info!(
-                "✅ Pico2: {}",
-                mount_point.display().to_string().replace("\\", "")
-            );
-            #[cfg(not(target_os = "windows"))]
-            log::info!("✅ Pico2: {}", mount_point.display());
-        }
+        .collect()
+}
+
+/// A mount point as the user would type it, e.g. `F:` rather than `F:\`.
+pub fn display_mount_point(path: &Path) -> String {
+    #[cfg(target_os = "windows")]
+    return path.display().to_string().replace("\\", "");
+    #[cfg(not(target_os = "windows"))]
+    return path.display().to_string();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rp2350_info() {
+        let info = BootInfo::parse(
+            "UF2 Bootloader v1.0\r\nModel: Raspberry Pi RP2350\r\nBoard-ID: RP2350\r\n",
+        )
+        .unwrap();
+        assert_eq!(info.family, ChipFamily::Rp2350);
+        assert_eq!(info.model, "Raspberry Pi RP2350");
+        assert_eq!(info.bootloader.as_deref(), Some("v1.0"));
+    }
+
+    #[test]
+    fn test_parse_rp2040_info() {
+        let info =
+            BootInfo::parse("UF2 Bootloader v3.0\nModel: Raspberry Pi RP2\nBoard-ID: RPI-RP2\n")
+                .unwrap();
+        assert_eq!(info.family, ChipFamily::Rp2040);
+        assert_eq!(info.board_id, "RPI-RP2");
+        assert_eq!(info.bootloader.as_deref(), Some("v3.0"));
+        assert_eq!(info.family.firmware_name(), None);
+        assert!(info.family.accepts(Family::Rp2040));
+        assert!(!info.family.accepts(Family::Rp2350ArmSecure));
+    }
+
+    #[test]
+    fn test_parse_other_drive() {
+        assert!(BootInfo::parse("UF2 Bootloader v3.0\nBoard-ID: SAMD21\n").is_none());
+        assert!(BootInfo::parse("").is_none());
     }
 }
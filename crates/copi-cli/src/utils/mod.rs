@@ -1,3 +1,4 @@
 mod list_boot_pico;
+pub mod units;
 
 pub use list_boot_pico::*;
@@ -0,0 +1,135 @@
+use std::time::Duration;
+
+use anyhow::{Result, bail};
+
+/// Default `clk_sys` of the RP2350, used when the device does not report it.
+pub const DEFAULT_SYS_CLOCK_HZ: u32 = 150_000_000;
+
+pub fn parse_level(s: &str) -> Result<bool, String> {
+    match s.to_ascii_lowercase().as_str() {
+        "high" | "h" | "1" | "true" | "on" => Ok(true),
+        "low" | "l" | "0" | "false" | "off" => Ok(false),
+        _ => Err(format!("`{}` is not a level, use high or low", s)),
+    }
+}
+
+/// Parse a frequency such as `50`, `1k`, `1.5kHz` or `2MHz` into Hz.
+pub fn parse_frequency(s: &str) -> Result<f64, String> {
+    let lower = s.trim().to_ascii_lowercase();
+    let number = lower.trim_end_matches("hz");
+    let (number, scale) = match number.char_indices().last() {
+        Some((i, 'k')) => (&number[..i], 1e3),
+        Some((i, 'm')) => (&number[..i], 1e6),
+        _ => (number, 1.0),
+    };
+    let hz = number
+        .trim()
+        .parse::<f64>()
+        .map_err(|_| format!("`{}` is not a frequency", s))?
+        * scale;
+    if !(hz > 0.0 && hz.is_finite()) {
+        return Err(format!("frequency must be positive, got `{}`", s));
+    }
+    Ok(hz)
+}
+
+/// Parse a duty cycle such as `30%` or `30` into percent.
+pub fn parse_percent(s: &str) -> Result<u32, String> {
+    let percent: u32 = s
+        .trim()
+        .trim_end_matches('%')
+        .parse()
+        .map_err(|_| format!("`{}` is not a percentage", s))?;
+    if percent > 100 {
+        return Err(format!("percentage must be 0..=100, got {}", percent));
+    }
+    Ok(percent)
+}
+
+/// Parse a duration such as `500ms`, `30s`, `5m` or `30` (seconds).
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let lower = s.trim().to_ascii_lowercase();
+    let (number, scale_ms) = if let Some(n) = lower.strip_suffix("ms") {
+        (n, 1)
+    } else if let Some(n) = lower.strip_suffix('s') {
+        (n, 1000)
+    } else if let Some(n) = lower.strip_suffix('m') {
+        (n, 60_000)
+    } else {
+        (lower.as_str(), 1000)
+    };
+    let number: u64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("`{}` is not a duration", s))?;
+    Ok(Duration::from_millis(number * scale_ms))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct PwmTiming {
+    pub divider: u32,
+    pub top: u32,
+    pub compare: u32,
+}
+
+/// Pick the smallest integer clock divider that fits `freq_hz` into the
+/// 16-bit counter, for the most duty cycle resolution.
+pub fn pwm_timing(sys_clock_hz: u32, freq_hz: f64, percent: u32) -> Result<PwmTiming> {
+    for divider in 1..=255u32 {
+        let period = (sys_clock_hz as f64 / (freq_hz * divider as f64)).round();
+        if period > 65536.0 {
+            continue;
+        }
+        if period < 2.0 {
+            break;
+        }
+        let period = period as u32;
+        return Ok(PwmTiming {
+            divider,
+            top: period - 1,
+            compare: period * percent / 100,
+        });
+    }
+    bail!(
+        "{} Hz cannot be generated from a {} Hz system clock",
+        freq_hz,
+        sys_clock_hz
+    )
+}
+
+/// PWM slice driving a GPIO, and whether it is the slice's B output.
+pub fn pwm_slice(pin: u32) -> (u32, bool) {
+    ((pin >> 1) & 7, pin & 1 == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn units() {
+        assert_eq!(parse_level("HIGH"), Ok(true));
+        assert!(parse_level("up").is_err());
+        assert_eq!(parse_frequency("1k"), Ok(1000.0));
+        assert_eq!(parse_frequency("1.5kHz"), Ok(1500.0));
+        assert_eq!(parse_frequency("2MHz"), Ok(2_000_000.0));
+        assert!(parse_frequency("fast").is_err());
+        assert_eq!(parse_percent("30%"), Ok(30));
+        assert!(parse_percent("101").is_err());
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration("30"), Ok(Duration::from_secs(30)));
+        assert!(parse_duration("soon").is_err());
+    }
+
+    #[test]
+    fn timing() {
+        let t = pwm_timing(150_000_000, 1000.0, 30).unwrap();
+        assert_eq!(t.divider, 3);
+        assert_eq!(t.top, 49_999);
+        assert_eq!(t.compare, 15_000);
+        assert!(pwm_timing(150_000_000, 1.0, 50).is_err());
+        assert_eq!(pwm_slice(15), (7, true));
+        assert_eq!(pwm_slice(16), (0, false));
+    }
+}
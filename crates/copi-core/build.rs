@@ -1,7 +1,17 @@
+use std::{fmt::Write as _, path::Path};
+
+use prost::Message as _;
+use prost_types::FileDescriptorSet;
+
 fn main() {
+    println!("cargo:rerun-if-changed=../../copi-proto/host_to_mcu.proto");
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
     let mut config = prost_build::Config::new();
+    config.file_descriptor_set_path(out_dir.join("copi_descriptor.bin"));
     config.type_attribute(".", "#[derive(serde::Serialize, serde::Deserialize)]");
     config.type_attribute(".", "#[serde(rename_all = \"camelCase\")]");
+    // Omitted fields take their protobuf default, as in the binary encoding.
+    config.message_attribute(".", "#[serde(default)]");
     // config.field_attribute("skip_response", "#[serde(default)]");
     config
         .compile_protos(
@@ -9,4 +19,48 @@ fn main() {
             &["../../copi-proto"],
         )
         .unwrap();
+    write_request_tags(&out_dir);
+}
+
+/// Generate `request_body::Message::tag`, the field number of each variant
+/// in `RequestBody`, from the descriptors prost-build wrote.
+fn write_request_tags(out_dir: &Path) {
+    let set = std::fs::read(out_dir.join("copi_descriptor.bin")).unwrap();
+    let set = FileDescriptorSet::decode(set.as_slice()).unwrap();
+    let request_body = set
+        .file
+        .iter()
+        .flat_map(|file| &file.message_type)
+        .find(|message| message.name() == "RequestBody")
+        .expect("RequestBody is missing from the proto");
+
+    let mut code = String::new();
+    code.push_str("impl request_body::Message {\n");
+    code.push_str("    /// Field number of the variant in `RequestBody`.\n");
+    code.push_str("    pub fn tag(&self) -> u32 {\n");
+    code.push_str("        match self {\n");
+    for field in &request_body.field {
+        writeln!(
+            code,
+            "            Self::{}(_) => {},",
+            upper_camel(field.name()),
+            field.number()
+        )
+        .unwrap();
+    }
+    code.push_str("        }\n    }\n}\n");
+    std::fs::write(out_dir.join("request_tags.rs"), code).unwrap();
+}
+
+/// The variant name prost-build gives a `snake_case` field.
+fn upper_camel(name: &str) -> String {
+    name.split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect()
 }
@@ -0,0 +1,39 @@
+use std::convert::Infallible;
+
+use axum::{
+    extract::State,
+    response::sse::{Event as SseEvent, KeepAlive, Sse},
+};
+use futures_util::Stream;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::AppState;
+
+/// Stream [`crate::events::Event`]s as server-sent events, one JSON object per
+/// `data:` line.
+pub async fn events(
+    State(state): State<AppState>,
+) -> Sse<impl Stream<Item = Result<SseEvent, Infallible>>> {
+    let rx = state.subscribe_events();
+    let shutdown = Box::pin(state.shutdown_requested());
+    let stream =
+        futures_util::stream::unfold((rx, shutdown), |(mut rx, mut shutdown)| async move {
+            loop {
+                let event = tokio::select! {
+                    _ = &mut shutdown => return None,
+                    event = rx.recv() => event,
+                };
+                match event {
+                    Ok(event) => {
+                        let data = serde_json::to_string(&event).unwrap();
+                        return Some((Ok(SseEvent::default().data(data)), (rx, shutdown)));
+                    }
+                    Err(RecvError::Lagged(n)) => {
+                        log::warn!("Event subscriber lagged, skipped {} events", n);
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
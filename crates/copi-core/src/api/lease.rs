@@ -0,0 +1,111 @@
+use std::{convert::Infallible, time::Duration};
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{
+        IntoResponse, Response,
+        sse::{Event as SseEvent, KeepAlive, Sse},
+    },
+};
+use futures_util::{StreamExt as _, stream};
+use serde::Deserialize;
+
+use crate::{
+    AppState,
+    error::Rejected,
+    lease::{LeaseInfo, LeaseRequest},
+};
+
+use super::{bad_request, error_response};
+
+/// How often a watch stream checks whether its lease is still held.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+pub async fn list(State(state): State<AppState>) -> Json<Vec<LeaseInfo>> {
+    Json(state.leases())
+}
+
+pub async fn acquire(
+    State(state): State<AppState>,
+    Json(request): Json<LeaseRequest>,
+) -> Result<Json<LeaseInfo>, Response> {
+    state.acquire_lease(&request).map(Json).map_err(|e| {
+        // Conflicts are 409s; anything else is a malformed request.
+        if e.is::<Rejected>() {
+            error_response(e)
+        } else {
+            bad_request(e)
+        }
+    })
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenewParams {
+    ttl_ms: Option<u64>,
+}
+
+pub async fn renew(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(params): Query<RenewParams>,
+) -> Result<Json<LeaseInfo>, StatusCode> {
+    state
+        .renew_lease(&id, params.ttl_ms.map(Duration::from_millis))
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+pub async fn release(State(state): State<AppState>, Path(id): Path<String>) -> StatusCode {
+    match state.release_lease(&id) {
+        Some(_) => StatusCode::NO_CONTENT,
+        None => StatusCode::NOT_FOUND,
+    }
+}
+
+/// Releases the lease when dropped, i.e. when the watching client goes away.
+struct WatchGuard {
+    state: AppState,
+    id: String,
+}
+
+impl Drop for WatchGuard {
+    fn drop(&mut self) {
+        if self.state.release_lease(&self.id).is_some() {
+            log::info!("Lease watcher disconnected, released lease");
+        }
+    }
+}
+
+/// Hold a lease for as long as this stream stays open. Sends the lease once,
+/// then ends when it expires or is released.
+pub async fn watch(State(state): State<AppState>, Path(id): Path<String>) -> Response {
+    let Some(lease) = state.leases().into_iter().find(|lease| lease.id == id) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let first = SseEvent::default().data(serde_json::to_string(&lease).unwrap());
+    let shutdown = state.shutdown_requested();
+    let guard = WatchGuard { state, id };
+    let held = async move {
+        tokio::pin!(shutdown);
+        loop {
+            tokio::select! {
+                _ = &mut shutdown => break,
+                _ = tokio::time::sleep(WATCH_INTERVAL) => {}
+            }
+            if !guard.state.is_lease_held(&guard.id) {
+                break;
+            }
+        }
+        // The lease is gone or the daemon is stopping; dropping the guard here
+        // also covers the client disconnecting, which drops this future.
+        drop(guard);
+    };
+    let stream = stream::once(async { Ok::<_, Infallible>(first) })
+        .chain(stream::once(held).filter_map(|()| async { None }));
+    Sse::new(stream)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
@@ -0,0 +1,55 @@
+use std::convert::Infallible;
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    response::{
+        IntoResponse, Response,
+        sse::{Event as SseEvent, KeepAlive, Sse},
+    },
+};
+use futures_util::{StreamExt as _, stream};
+use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{AppState, events::Event};
+
+#[derive(Debug, Deserialize)]
+pub struct LogsParams {
+    #[serde(default)]
+    follow: bool,
+}
+
+/// The device log lines kept so far. With `?follow=true`, a server-sent
+/// event stream of them as [`Event::Log`]s, continuing with new ones.
+pub async fn logs(State(state): State<AppState>, Query(params): Query<LogsParams>) -> Response {
+    if !params.follow {
+        return Json(state.logs()).into_response();
+    }
+
+    let (backlog, rx) = state.follow_logs();
+    let shutdown = Box::pin(state.shutdown_requested());
+    let new = stream::unfold((rx, shutdown), |(mut rx, mut shutdown)| async move {
+        loop {
+            let event = tokio::select! {
+                _ = &mut shutdown => return None,
+                event = rx.recv() => event,
+            };
+            match event {
+                Ok(Event::Log(entry)) => return Some((entry, (rx, shutdown))),
+                Ok(_) => {}
+                Err(RecvError::Lagged(n)) => {
+                    log::warn!("Log subscriber lagged, skipped {} events", n);
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+    let stream = stream::iter(backlog).chain(new).map(|entry| {
+        let data = serde_json::to_string(&Event::Log(entry)).unwrap();
+        Ok::<_, Infallible>(SseEvent::default().data(data))
+    });
+    Sse::new(stream)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
@@ -1,33 +1,43 @@
 use crate::AppState;
+use crate::board::BoardProfile;
+use crate::device::DeviceReport;
+use crate::error::{Rejected, Unavailable};
 use crate::generated::RequestBody;
+use crate::lease::LEASE_HEADER;
+use crate::session::{Caller, PERSISTENT_HEADER, SESSION_HEADER};
+use crate::shadow::DeviceShadow;
 use axum::body::Body;
-use axum::extract::FromRequest;
-use axum::http::Request;
-use axum::http::header::CONTENT_TYPE;
+use axum::extract::{FromRequest, Query};
+use axum::http::header::{AUTHORIZATION, CONTENT_TYPE};
+use axum::http::{HeaderMap, Request};
+use axum::middleware::Next;
 use axum::response::{IntoResponse, Response};
 use axum::{Json, extract::State, http::StatusCode};
 use http_body_util::BodyExt as _;
+use prost::Message as _;
+use serde::Deserialize;
 
+pub mod events;
+pub mod lease;
+pub mod logs;
 // TODO: Uncomment and implement these modules as needed
 // pub mod gpio;
 // pub mod pio;
 pub mod playground;
 // pub mod pwm;
+pub mod session;
 
 pub enum BodyFormat<T> {
     Protobuf(T),
     Json(T),
 }
 
-impl<S, T> FromRequest<S> for BodyFormat<T>
-where
-    S: Send + Sync,
-    T: prost::Message + Default + Send,
-    Json<T>: FromRequest<S>,
-{
-    type Rejection = StatusCode;
+/// Decodes a request in either format. JSON requests may name pins (`LED`,
+/// `GP15`); both are checked against the board profile.
+impl FromRequest<AppState> for BodyFormat<RequestBody> {
+    type Rejection = Response;
 
-    async fn from_request(req: Request<Body>, state: &S) -> Result<Self, Self::Rejection> {
+    async fn from_request(req: Request<Body>, state: &AppState) -> Result<Self, Self::Rejection> {
         let content_type = req
             .headers()
             .get(CONTENT_TYPE)
@@ -41,22 +51,77 @@ where
             let bytes = body
                 .collect()
                 .await
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?
                 .to_bytes();
 
-            let protobuf_body = T::decode(&bytes[..]).map_err(|_| StatusCode::BAD_REQUEST)?;
+            let protobuf_body = RequestBody::decode(&bytes[..])
+                .map_err(|_| StatusCode::BAD_REQUEST.into_response())?;
+            state
+                .board()
+                .check_request(&protobuf_body)
+                .map_err(bad_request)?;
 
             Ok(BodyFormat::Protobuf(protobuf_body))
         } else {
-            let Json(body) = Json::<T>::from_request(req, state)
+            let Json(value) = Json::<serde_json::Value>::from_request(req, state)
                 .await
-                .map_err(|_| StatusCode::BAD_REQUEST)?;
+                .map_err(|_| StatusCode::BAD_REQUEST.into_response())?;
+            let body = parse_json_request(state.board(), value).map_err(bad_request)?;
 
             Ok(BodyFormat::Json(body))
         }
     }
 }
 
+/// Decode the JSON form of a request, resolving pin names, and check it
+/// against the board profile.
+fn parse_json_request(
+    board: &BoardProfile,
+    mut value: serde_json::Value,
+) -> anyhow::Result<RequestBody> {
+    board.resolve_json(&mut value)?;
+    let request = serde_json::from_value(value)?;
+    board.check_request(&request)?;
+    Ok(request)
+}
+
+/// The leases and session a request is made with, from its headers.
+fn caller(headers: &HeaderMap) -> Caller {
+    let leases = headers
+        .get_all(LEASE_HEADER)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+        .collect();
+    let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+    Caller {
+        leases,
+        session: header(SESSION_HEADER).map(|id| id.trim().to_string()),
+        persistent: header(PERSISTENT_HEADER).is_some_and(|v| v.eq_ignore_ascii_case("true")),
+    }
+}
+
+fn bad_request(e: anyhow::Error) -> Response {
+    (StatusCode::BAD_REQUEST, format!("{:#}", e)).into_response()
+}
+
+/// Requests the service rules out are the client's fault (409). A device
+/// that is busy or does not answer in time is a 503 or 504; anything else is
+/// a failure talking to the device.
+fn error_response(e: anyhow::Error) -> Response {
+    if let Some(rejected) = e.downcast_ref::<Rejected>() {
+        return (StatusCode::CONFLICT, rejected.to_string()).into_response();
+    }
+    let status = match e.downcast_ref::<Unavailable>() {
+        Some(Unavailable::TooManyPending { .. }) => StatusCode::SERVICE_UNAVAILABLE,
+        Some(Unavailable::TimedOut(_)) => StatusCode::GATEWAY_TIMEOUT,
+        None => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    (status, format!("{:#}", e)).into_response()
+}
+
 pub struct ProtoBufResponse<T>(pub T);
 
 impl<T> IntoResponse for ProtoBufResponse<T>
@@ -64,29 +129,26 @@ where
     T: prost::Message,
 {
     fn into_response(self) -> Response {
-        match self.0.encode_to_vec() {
-            data => {
-                let headers = [(CONTENT_TYPE, "application/protobuf")];
-                (headers, data).into_response()
-            }
-        }
+        let headers = [(CONTENT_TYPE, "application/protobuf")];
+        (headers, self.0.encode_to_vec()).into_response()
     }
 }
 
 #[axum::debug_handler]
 pub async fn query(
     State(state): State<AppState>,
+    headers: HeaderMap,
     body_format: BodyFormat<RequestBody>,
-) -> Result<impl IntoResponse, StatusCode> {
+) -> Result<impl IntoResponse, Response> {
     let is_protobuf = matches!(body_format, BodyFormat::Protobuf(_));
     let req = match body_format {
         BodyFormat::Json(req) => req,
         BodyFormat::Protobuf(req) => req,
     };
 
-    let res = state.device_channel.query(req).await.map_err(|e| {
+    let res = state.query_as(req, &caller(&headers)).await.map_err(|e| {
         log::error!("Failed to query device: {:?}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
+        error_response(e)
     })?;
 
     let resp = if is_protobuf {
@@ -100,16 +162,64 @@ pub async fn query(
 #[axum::debug_handler]
 pub async fn command(
     State(state): State<AppState>,
+    headers: HeaderMap,
     body_format: BodyFormat<RequestBody>,
-) -> Result<(), StatusCode> {
+) -> Result<(), Response> {
     let req = match body_format {
         BodyFormat::Json(req) => req,
         BodyFormat::Protobuf(req) => req,
     };
 
-    state.device_channel.send(req).map_err(|e| {
+    state.send_as(req, &caller(&headers)).map_err(|e| {
         log::error!("Failed to send command: {:?}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
+        error_response(e)
     })?;
     Ok(())
 }
+
+#[axum::debug_handler]
+pub async fn board(State(state): State<AppState>) -> Json<BoardProfile> {
+    Json(state.board().clone())
+}
+
+#[axum::debug_handler]
+pub async fn state(State(state): State<AppState>) -> Json<DeviceShadow> {
+    Json(state.shadow())
+}
+
+#[axum::debug_handler]
+pub async fn device(State(state): State<AppState>) -> Json<DeviceReport> {
+    Json(state.device())
+}
+
+#[axum::debug_handler]
+pub async fn status(State(state): State<AppState>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "connected": state.is_connected() }))
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenParams {
+    token: Option<String>,
+}
+
+/// Require one of the configured tokens, either as `Authorization: Bearer`
+/// or, for clients such as `EventSource` that cannot set headers, as a
+/// `token` query parameter.
+pub async fn auth(
+    State(state): State<AppState>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let header = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    let query = Query::<TokenParams>::try_from_uri(req.uri())
+        .ok()
+        .and_then(|Query(params)| params.token);
+    if !state.is_authorized(header.or(query.as_deref())) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    Ok(next.run(req).await)
+}
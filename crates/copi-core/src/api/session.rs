@@ -0,0 +1,124 @@
+use axum::{
+    Json,
+    extract::{
+        Path, Query, State,
+        ws::{Message as WsMessage, WebSocket, WebSocketUpgrade},
+    },
+    http::{HeaderMap, StatusCode},
+    response::Response,
+};
+use prost::Message as _;
+use serde::Deserialize;
+
+use crate::{
+    AppState,
+    generated::RequestBody,
+    session::{Caller, SessionInfo, SessionRequest},
+};
+
+use super::{caller, parse_json_request};
+
+pub async fn list(State(state): State<AppState>) -> Json<Vec<SessionInfo>> {
+    Json(state.sessions())
+}
+
+pub async fn open(
+    State(state): State<AppState>,
+    Json(request): Json<SessionRequest>,
+) -> Json<SessionInfo> {
+    Json(state.open_session(&request))
+}
+
+/// End a session, releasing what it set up. Returns what was released.
+pub async fn close(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<SessionInfo>, StatusCode> {
+    state
+        .close_session(&id)
+        .await
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SocketParams {
+    owner: Option<String>,
+}
+
+/// Run requests over a WebSocket, in a session that ends with the socket.
+///
+/// Text frames carry JSON requests as for `/query`, optionally with
+/// `"persistent": true`, and binary frames protobuf ones. Each is answered
+/// in the same format, or with `{"error": "..."}` as text.
+pub async fn websocket(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    Query(params): Query<SocketParams>,
+    headers: HeaderMap,
+) -> Response {
+    let caller = caller(&headers);
+    let owner = params.owner.unwrap_or_else(|| "websocket".to_string());
+    ws.on_upgrade(move |socket| serve_socket(socket, state, caller, owner))
+}
+
+async fn serve_socket(mut socket: WebSocket, state: AppState, mut caller: Caller, owner: String) {
+    let session = state.open_connection_session(&owner);
+    caller.session = Some(session.id.clone());
+    let shutdown = state.shutdown_requested();
+    tokio::pin!(shutdown);
+    loop {
+        let frame = tokio::select! {
+            _ = &mut shutdown => break,
+            frame = socket.recv() => frame,
+        };
+        let reply = match frame {
+            Some(Ok(WsMessage::Text(text))) => text_request(&state, &caller, &text).await,
+            Some(Ok(WsMessage::Binary(bytes))) => binary_request(&state, &caller, &bytes).await,
+            Some(Ok(WsMessage::Close(_))) | Some(Err(_)) | None => break,
+            // Pings are answered by axum.
+            Some(Ok(_)) => continue,
+        };
+        if socket.send(reply).await.is_err() {
+            break;
+        }
+    }
+    state.close_session(&session.id).await;
+}
+
+async fn text_request(state: &AppState, caller: &Caller, text: &str) -> WsMessage {
+    let result = async {
+        let mut value: serde_json::Value = serde_json::from_str(text)?;
+        let persistent = value
+            .as_object_mut()
+            .and_then(|request| request.remove("persistent"))
+            .and_then(|persistent| persistent.as_bool())
+            .unwrap_or(false);
+        let request = parse_json_request(state.board(), value)?;
+        let caller = Caller {
+            persistent: caller.persistent || persistent,
+            ..caller.clone()
+        };
+        let response = state.query_as(request, &caller).await?;
+        Ok::<_, anyhow::Error>(serde_json::to_string(&response)?)
+    }
+    .await;
+    WsMessage::Text(result.unwrap_or_else(error_text).into())
+}
+
+async fn binary_request(state: &AppState, caller: &Caller, bytes: &[u8]) -> WsMessage {
+    let result = async {
+        let request = RequestBody::decode(bytes)?;
+        state.board().check_request(&request)?;
+        state.query_as(request, caller).await
+    }
+    .await;
+    match result {
+        Ok(response) => WsMessage::Binary(response.encode_to_vec().into()),
+        Err(e) => WsMessage::Text(error_text(e).into()),
+    }
+}
+
+fn error_text(e: anyhow::Error) -> String {
+    serde_json::json!({ "error": format!("{:#}", e) }).to_string()
+}
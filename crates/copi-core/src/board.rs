@@ -0,0 +1,169 @@
+//! Board profiles: which GPIOs a board breaks out and the names they go by,
+//! so requests can say `LED` or `relay1` instead of `25`.
+
+use std::{collections::BTreeMap, fmt};
+
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::generated::{RequestBody, request_body::Message};
+
+/// Names of the built-in profiles, the first being the default.
+pub const BUILTIN_BOARDS: &[&str] = &["pico2", "pico2w", "rp2350a"];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BoardProfile {
+    pub name: String,
+    /// GPIOs usable on this board, ascending.
+    pub gpios: Vec<u32>,
+    /// Extra pin names such as `LED`, matched case-insensitively.
+    pub aliases: BTreeMap<String, u32>,
+}
+
+/// A pin as written by a user: a GPIO number or a name from the profile.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PinRef {
+    Number(u32),
+    Name(String),
+}
+
+impl fmt::Display for PinRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PinRef::Number(pin) => write!(f, "{}", pin),
+            PinRef::Name(name) => f.write_str(name),
+        }
+    }
+}
+
+impl Default for BoardProfile {
+    fn default() -> Self {
+        Self::builtin(BUILTIN_BOARDS[0]).unwrap()
+    }
+}
+
+impl BoardProfile {
+    pub fn builtin(name: &str) -> Option<Self> {
+        // GP23-25 and GP29 are wired to the SMPS, VBUS sense, LED and VSYS
+        // ADC on the Pico 2; on the Pico 2 W they belong to the radio.
+        let header = (0..=22).chain(26..=28);
+        let (mut gpios, aliases): (Vec<u32>, &[(&str, u32)]) = match name {
+            "pico2" => (header.chain([25]).collect(), &[("LED", 25)]),
+            "pico2w" => (header.collect(), &[]),
+            "rp2350a" => ((0..30).collect(), &[]),
+            _ => return None,
+        };
+        gpios.sort_unstable();
+        Some(Self {
+            name: name.to_string(),
+            gpios,
+            aliases: aliases
+                .iter()
+                .map(|(alias, pin)| (alias.to_string(), *pin))
+                .collect(),
+        })
+    }
+
+    /// GPIO number for `pin`: a number, `GPn`/`GPIOn`, or an alias.
+    pub fn resolve(&self, pin: &str) -> Result<u32> {
+        let upper = pin.trim().to_ascii_uppercase();
+        let number = upper
+            .strip_prefix("GPIO")
+            .or_else(|| upper.strip_prefix("GP"))
+            .unwrap_or(&upper);
+        let gpio = match number.parse::<u32>() {
+            Ok(gpio) => gpio,
+            Err(_) => match self
+                .aliases
+                .iter()
+                .find(|(alias, _)| alias.eq_ignore_ascii_case(&upper))
+            {
+                Some((_, gpio)) => *gpio,
+                None => bail!("Unknown pin `{}` on {}", pin.trim(), self.name),
+            },
+        };
+        self.check_pin(gpio)?;
+        Ok(gpio)
+    }
+
+    pub fn resolve_ref(&self, pin: &PinRef) -> Result<u32> {
+        match pin {
+            PinRef::Number(gpio) => self.check_pin(*gpio).map(|_| *gpio),
+            PinRef::Name(name) => self.resolve(name),
+        }
+    }
+
+    pub fn check_pin(&self, gpio: u32) -> Result<()> {
+        if self.gpios.binary_search(&gpio).is_err() {
+            bail!("GP{} is not broken out on {}", gpio, self.name);
+        }
+        Ok(())
+    }
+
+    /// Reject requests that use a pin this board does not have.
+    pub fn check_request(&self, request: &RequestBody) -> Result<()> {
+        match &request.message {
+            Some(Message::PwmDeinit(m)) if m.slice >= 8 => {
+                bail!("PWM slice must be 0..=7, got {}", m.slice)
+            }
+            Some(Message::PioSmDeinit(m)) if m.pio_num >= 3 || m.sm_num >= 4 => {
+                bail!("No state machine pio{}.sm{}", m.pio_num, m.sm_num)
+            }
+            _ => {}
+        }
+        let pins: Vec<u32> = match &request.message {
+            Some(Message::GpioOutputInit(m)) => vec![m.pin],
+            Some(Message::GpioOutputSet(m)) => vec![m.pin],
+            Some(Message::GpioOutputGet(m)) => vec![m.pin],
+            Some(Message::PinDeinit(m)) => vec![m.pin],
+            Some(Message::PwmInit(m)) => m.a.into_iter().chain(m.b).collect(),
+            Some(Message::PwmSetDutyCyclePercent(m)) => vec![m.pin],
+            Some(Message::PioSmInit(m)) => vec![m.pin_num],
+            _ => Vec::new(),
+        };
+        pins.into_iter().try_for_each(|pin| self.check_pin(pin))
+    }
+
+    /// Replace pin names in the JSON form of a request, either a whole
+    /// `RequestBody` or just its message, with GPIO numbers.
+    pub fn resolve_json(&self, request: &mut serde_json::Value) -> Result<()> {
+        let request = if request.get("message").is_some() {
+            &mut request["message"]
+        } else {
+            request
+        };
+        let Some(messages) = request.as_object_mut() else {
+            return Ok(());
+        };
+        for (message, fields) in messages {
+            let Some(fields) = fields.as_object_mut() else {
+                continue;
+            };
+            for field in pin_fields(message) {
+                let name = fields
+                    .get(*field)
+                    .and_then(|v| v.as_str())
+                    .map(str::to_owned);
+                if let Some(name) = name {
+                    fields.insert(field.to_string(), self.resolve(&name)?.into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// JSON names of the fields of `message` that hold a GPIO number.
+pub fn pin_fields(message: &str) -> &'static [&'static str] {
+    match message {
+        "gpioOutputInit"
+        | "gpioOutputSet"
+        | "gpioOutputGet"
+        | "pinDeinit"
+        | "pwmSetDutyCyclePercent" => &["pin"],
+        "pwmInit" => &["a", "b"],
+        "pioSmInit" => &["pinNum"],
+        _ => &[],
+    }
+}
@@ -0,0 +1,522 @@
+//! Talk to a device either directly over its serial port or through a
+//! running daemon's HTTP API.
+
+use std::{collections::VecDeque, sync::Arc, time::Duration};
+
+use anyhow::{Context, Result, bail};
+use prost::Message as _;
+use reqwest::header::{AUTHORIZATION, HeaderMap, HeaderName, HeaderValue};
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    watch,
+};
+
+use crate::{
+    AppState,
+    board::BoardProfile,
+    device::DeviceReport,
+    events::Event,
+    generated::{RequestBody, ResponseBody, request_body},
+    lease::{LEASE_HEADER, LeaseInfo, LeaseRequest},
+    logs::LogEntry,
+    session::{Caller, SESSION_HEADER, SessionInfo, SessionRequest},
+    shadow::DeviceShadow,
+};
+
+pub const DEFAULT_DAEMON_URL: &str = "http://127.0.0.1:8899";
+
+#[derive(Clone)]
+pub enum Client {
+    /// Owns the serial port; the USB service stops when the last clone drops.
+    Local {
+        state: AppState,
+        _shutdown_tx: Arc<watch::Sender<bool>>,
+        /// Leases and session presented with every request.
+        caller: Caller,
+    },
+    Remote {
+        base_url: String,
+        http: reqwest::Client,
+        /// Sent with every request; `http` is rebuilt when they change.
+        headers: HeaderMap,
+    },
+}
+
+impl Client {
+    /// Open the given serial port, or the first Copi device found, and run
+    /// the USB service in the current Tokio runtime.
+    #[cfg(any(target_os = "macos", target_os = "linux", target_os = "windows"))]
+    pub fn open_serial(port_name: Option<&str>) -> Result<Self> {
+        let port_name = match port_name {
+            Some(port_name) => port_name.to_string(),
+            None => crate::find_copi_serial_port()?,
+        };
+        let port = crate::open_serial_port(&port_name)?;
+
+        let (request_tx, request_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (response_tx, response_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let state = AppState::new(request_tx, response_rx);
+        let service_state = state.clone();
+        state.set_connected(true);
+        tokio::spawn(async move {
+            crate::start_usb_cdc_service(port, request_rx, response_tx, shutdown_rx).await;
+            service_state.set_connected(false);
+        });
+
+        Ok(Client::Local {
+            state,
+            _shutdown_tx: Arc::new(shutdown_tx),
+            caller: Caller::default(),
+        })
+    }
+
+    /// Use a daemon at `base_url`, e.g. [`DEFAULT_DAEMON_URL`].
+    pub fn connect_daemon(base_url: &str) -> Self {
+        Client::Remote {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            http: reqwest::Client::new(),
+            headers: HeaderMap::new(),
+        }
+    }
+
+    /// Send `token` to the daemon as a bearer token. Local clients ignore it.
+    pub fn with_token(self, token: &str) -> Result<Self> {
+        let mut value =
+            HeaderValue::from_str(&format!("Bearer {}", token)).with_context(|| "Invalid token")?;
+        value.set_sensitive(true);
+        self.with_header(AUTHORIZATION, value, false)
+    }
+
+    /// Present lease `id` with every request, so resources it covers can be
+    /// used.
+    pub fn with_lease(self, id: &str) -> Result<Self> {
+        match self {
+            Client::Local {
+                state,
+                _shutdown_tx,
+                mut caller,
+            } => {
+                caller.leases.push(id.to_string());
+                Ok(Client::Local {
+                    state,
+                    _shutdown_tx,
+                    caller,
+                })
+            }
+            remote => {
+                let value = HeaderValue::from_str(id).with_context(|| "Invalid lease id")?;
+                remote.with_header(HeaderName::from_static(LEASE_HEADER), value, true)
+            }
+        }
+    }
+
+    /// Make every request part of session `id`, so what they set up is
+    /// released when the session ends.
+    pub fn with_session(self, id: &str) -> Result<Self> {
+        match self {
+            Client::Local {
+                state,
+                _shutdown_tx,
+                mut caller,
+            } => {
+                caller.session = Some(id.to_string());
+                Ok(Client::Local {
+                    state,
+                    _shutdown_tx,
+                    caller,
+                })
+            }
+            remote => {
+                let value = HeaderValue::from_str(id).with_context(|| "Invalid session id")?;
+                remote.with_header(HeaderName::from_static(SESSION_HEADER), value, false)
+            }
+        }
+    }
+
+    fn with_header(self, name: HeaderName, value: HeaderValue, append: bool) -> Result<Self> {
+        let Client::Remote {
+            base_url,
+            mut headers,
+            ..
+        } = self
+        else {
+            return Ok(self);
+        };
+        if append {
+            headers.append(name, value);
+        } else {
+            headers.insert(name, value);
+        }
+        let http = reqwest::Client::builder()
+            .default_headers(headers.clone())
+            .build()
+            .with_context(|| "Failed to build HTTP client")?;
+        Ok(Client::Remote {
+            base_url,
+            http,
+            headers,
+        })
+    }
+
+    pub async fn query(&self, body: RequestBody) -> Result<ResponseBody> {
+        match self {
+            Client::Local { state, caller, .. } => {
+                // As the daemon does when the device attaches, so requests
+                // the firmware does not handle are refused up front.
+                if !state.device().is_known() {
+                    state.refresh_device_info(None).await;
+                }
+                state.query_as(body, caller).await
+            }
+            Client::Remote { base_url, http, .. } => {
+                let response = http
+                    .post(format!("{}/query", base_url))
+                    .header("Content-Type", "application/protobuf")
+                    .body(body.encode_to_vec())
+                    .send()
+                    .await
+                    .with_context(|| format!("Failed to reach daemon at {}", base_url))?;
+                let status = response.status();
+                if !status.is_success() {
+                    let text = response.text().await.unwrap_or_default();
+                    bail!("Daemon returned {}: {}", status, text);
+                }
+                let bytes = response.bytes().await?;
+                ResponseBody::decode(bytes.as_ref()).with_context(|| "Failed to decode response")
+            }
+        }
+    }
+
+    pub async fn query_message(&self, message: request_body::Message) -> Result<ResponseBody> {
+        self.query(RequestBody {
+            message: Some(message),
+        })
+        .await
+    }
+
+    /// Whether the service currently has a link to the device.
+    pub async fn is_connected(&self) -> Result<bool> {
+        match self {
+            Client::Local { state, .. } => Ok(state.is_connected()),
+            Client::Remote { base_url, http, .. } => {
+                let status: serde_json::Value = http
+                    .get(format!("{}/status", base_url))
+                    .send()
+                    .await
+                    .with_context(|| format!("Failed to reach daemon at {}", base_url))?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                Ok(status["connected"].as_bool().unwrap_or(false))
+            }
+        }
+    }
+
+    /// The board profile requests are checked against, for resolving pin
+    /// names on this side.
+    pub async fn board(&self) -> Result<BoardProfile> {
+        match self {
+            Client::Local { state, .. } => Ok(state.board().clone()),
+            Client::Remote { base_url, http, .. } => Ok(http
+                .get(format!("{}/board", base_url))
+                .send()
+                .await
+                .with_context(|| format!("Failed to reach daemon at {}", base_url))?
+                .error_for_status()?
+                .json()
+                .await?),
+        }
+    }
+
+    /// What the device reports about itself. A local client asks it now.
+    pub async fn device(&self) -> Result<DeviceReport> {
+        match self {
+            Client::Local { state, .. } => Ok(state.refresh_device_info(None).await),
+            Client::Remote { base_url, http, .. } => Ok(http
+                .get(format!("{}/device", base_url))
+                .send()
+                .await
+                .with_context(|| format!("Failed to reach daemon at {}", base_url))?
+                .error_for_status()?
+                .json()
+                .await?),
+        }
+    }
+
+    /// The service's model of what each pin is doing.
+    pub async fn state(&self) -> Result<DeviceShadow> {
+        match self {
+            Client::Local { state, .. } => Ok(state.shadow()),
+            Client::Remote { base_url, http, .. } => Ok(http
+                .get(format!("{}/state", base_url))
+                .send()
+                .await
+                .with_context(|| format!("Failed to reach daemon at {}", base_url))?
+                .error_for_status()?
+                .json()
+                .await?),
+        }
+    }
+
+    /// Lease resources such as `GP15` or `pio0.sm1` to `owner`; other
+    /// clients' requests touching them are refused until it is released or
+    /// expires. Pass the returned id to [`Client::with_lease`].
+    pub async fn acquire_lease(&self, request: &LeaseRequest) -> Result<LeaseInfo> {
+        match self {
+            Client::Local { state, .. } => state.acquire_lease(request),
+            Client::Remote { base_url, http, .. } => {
+                let response = http
+                    .post(format!("{}/leases", base_url))
+                    .json(request)
+                    .send()
+                    .await
+                    .with_context(|| format!("Failed to reach daemon at {}", base_url))?;
+                let status = response.status();
+                if !status.is_success() {
+                    let text = response.text().await.unwrap_or_default();
+                    bail!("Daemon returned {}: {}", status, text);
+                }
+                Ok(response.json().await?)
+            }
+        }
+    }
+
+    pub async fn renew_lease(&self, id: &str, ttl: Option<Duration>) -> Result<LeaseInfo> {
+        match self {
+            Client::Local { state, .. } => state
+                .renew_lease(id, ttl)
+                .with_context(|| format!("No lease {}", id)),
+            Client::Remote { base_url, http, .. } => {
+                let mut request = http.post(format!("{}/leases/{}/renew", base_url, id));
+                if let Some(ttl) = ttl {
+                    request = request.query(&[("ttlMs", ttl.as_millis() as u64)]);
+                }
+                Ok(request
+                    .send()
+                    .await
+                    .with_context(|| format!("Failed to reach daemon at {}", base_url))?
+                    .error_for_status()?
+                    .json()
+                    .await?)
+            }
+        }
+    }
+
+    pub async fn release_lease(&self, id: &str) -> Result<()> {
+        match self {
+            Client::Local { state, .. } => state
+                .release_lease(id)
+                .map(|_| ())
+                .with_context(|| format!("No lease {}", id)),
+            Client::Remote { base_url, http, .. } => {
+                http.delete(format!("{}/leases/{}", base_url, id))
+                    .send()
+                    .await
+                    .with_context(|| format!("Failed to reach daemon at {}", base_url))?
+                    .error_for_status()?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Start a session for `owner`. Pass the returned id to
+    /// [`Client::with_session`].
+    pub async fn open_session(&self, request: &SessionRequest) -> Result<SessionInfo> {
+        match self {
+            Client::Local { state, .. } => Ok(state.open_session(request)),
+            Client::Remote { base_url, http, .. } => Ok(http
+                .post(format!("{}/sessions", base_url))
+                .json(request)
+                .send()
+                .await
+                .with_context(|| format!("Failed to reach daemon at {}", base_url))?
+                .error_for_status()?
+                .json()
+                .await?),
+        }
+    }
+
+    /// End session `id`, releasing what it set up on the device.
+    pub async fn close_session(&self, id: &str) -> Result<SessionInfo> {
+        match self {
+            Client::Local { state, .. } => state
+                .close_session(id)
+                .await
+                .with_context(|| format!("No session {}", id)),
+            Client::Remote { base_url, http, .. } => Ok(http
+                .delete(format!("{}/sessions/{}", base_url, id))
+                .send()
+                .await
+                .with_context(|| format!("Failed to reach daemon at {}", base_url))?
+                .error_for_status()?
+                .json()
+                .await?),
+        }
+    }
+
+    pub async fn leases(&self) -> Result<Vec<LeaseInfo>> {
+        match self {
+            Client::Local { state, .. } => Ok(state.leases()),
+            Client::Remote { base_url, http, .. } => Ok(http
+                .get(format!("{}/leases", base_url))
+                .send()
+                .await
+                .with_context(|| format!("Failed to reach daemon at {}", base_url))?
+                .error_for_status()?
+                .json()
+                .await?),
+        }
+    }
+
+    /// Log lines the device has sent to the service, oldest first.
+    pub async fn logs(&self) -> Result<Vec<LogEntry>> {
+        match self {
+            Client::Local { state, .. } => Ok(state.logs()),
+            Client::Remote { base_url, http, .. } => Ok(http
+                .get(format!("{}/logs", base_url))
+                .send()
+                .await
+                .with_context(|| format!("Failed to reach daemon at {}", base_url))?
+                .error_for_status()?
+                .json()
+                .await?),
+        }
+    }
+
+    /// Like [`Client::logs`], then every new line as it arrives.
+    pub async fn follow_logs(&self) -> Result<LogStream> {
+        match self {
+            Client::Local { state, .. } => {
+                let (backlog, rx) = state.follow_logs();
+                Ok(LogStream {
+                    backlog: backlog.into(),
+                    events: EventStream::Local(rx),
+                })
+            }
+            Client::Remote { base_url, http, .. } => {
+                let response = http
+                    .get(format!("{}/logs?follow=true", base_url))
+                    .send()
+                    .await
+                    .with_context(|| format!("Failed to reach daemon at {}", base_url))?
+                    .error_for_status()?;
+                Ok(LogStream {
+                    backlog: VecDeque::new(),
+                    events: EventStream::Remote {
+                        response,
+                        buf: Vec::new(),
+                        pending: VecDeque::new(),
+                    },
+                })
+            }
+        }
+    }
+
+    pub async fn events(&self) -> Result<EventStream> {
+        match self {
+            Client::Local { state, .. } => Ok(EventStream::Local(state.subscribe_events())),
+            Client::Remote { base_url, http, .. } => {
+                let response = http
+                    .get(format!("{}/events", base_url))
+                    .send()
+                    .await
+                    .with_context(|| format!("Failed to reach daemon at {}", base_url))?
+                    .error_for_status()?;
+                Ok(EventStream::Remote {
+                    response,
+                    buf: Vec::new(),
+                    pending: VecDeque::new(),
+                })
+            }
+        }
+    }
+}
+
+pub enum EventStream {
+    Local(broadcast::Receiver<Event>),
+    Remote {
+        response: reqwest::Response,
+        /// Bytes of events that have not fully arrived, undecoded since a
+        /// chunk may end inside a UTF-8 sequence.
+        buf: Vec<u8>,
+        pending: VecDeque<Event>,
+    },
+}
+
+impl EventStream {
+    /// Wait for the next event; `None` once the source has gone away.
+    pub async fn next(&mut self) -> Option<Result<Event>> {
+        match self {
+            EventStream::Local(rx) => loop {
+                match rx.recv().await {
+                    Ok(event) => return Some(Ok(event)),
+                    Err(RecvError::Lagged(n)) => {
+                        log::warn!("Event stream lagged, skipped {} events", n);
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            },
+            EventStream::Remote {
+                response,
+                buf,
+                pending,
+            } => loop {
+                if let Some(event) = pending.pop_front() {
+                    return Some(Ok(event));
+                }
+                let chunk = match response.chunk().await {
+                    Ok(Some(chunk)) => chunk,
+                    Ok(None) => return None,
+                    Err(e) => return Some(Err(e.into())),
+                };
+                buf.extend_from_slice(&chunk);
+                if let Err(e) = parse_sse(buf, pending) {
+                    return Some(Err(e));
+                }
+            },
+        }
+    }
+}
+
+pub struct LogStream {
+    backlog: VecDeque<LogEntry>,
+    events: EventStream,
+}
+
+impl LogStream {
+    /// Wait for the next log line; `None` once the source has gone away.
+    pub async fn next(&mut self) -> Option<Result<LogEntry>> {
+        if let Some(entry) = self.backlog.pop_front() {
+            return Some(Ok(entry));
+        }
+        loop {
+            match self.events.next().await? {
+                Ok(Event::Log(entry)) => return Some(Ok(entry)),
+                Ok(_) => {}
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Move every complete server-sent event out of `buf` into `out`.
+fn parse_sse(buf: &mut Vec<u8>, out: &mut VecDeque<Event>) -> Result<()> {
+    while let Some(end) = buf.windows(2).position(|w| w == b"\n\n") {
+        let block: Vec<u8> = buf.drain(..end + 2).collect();
+        let block = std::str::from_utf8(&block).context("Event is not valid UTF-8")?;
+        let data: Vec<&str> = block
+            .lines()
+            .filter_map(|line| line.strip_prefix("data:"))
+            .map(|data| data.strip_prefix(' ').unwrap_or(data))
+            .collect();
+        // Keep-alive comments carry no data.
+        if data.is_empty() {
+            continue;
+        }
+        let event = serde_json::from_str(&data.join("\n"))
+            .with_context(|| format!("Invalid event: {}", data.join("\n")))?;
+        out.push_back(event);
+    }
+    Ok(())
+}
@@ -0,0 +1,140 @@
+//! What the firmware says about itself in answer to `GetDeviceInfo`, checked
+//! against the protocol this host speaks. Without the check, a firmware
+//! built from other messages only shows up as requests that fail to decode.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Rejected,
+    generated::{
+        CrashReport, DeviceInfo, RequestBody, ResponseBody, request_body::Message, response_body,
+    },
+    schema::{message_of, request_messages},
+};
+
+/// Bumped with every change to the messages in copi-proto or how they are
+/// framed. The firmware reports its own in `DeviceInfo`.
+pub const PROTOCOL_VERSION: u32 = 4;
+
+/// Longest name the firmware stores with `SetDeviceName`, in bytes.
+pub const DEVICE_NAME_MAX_LEN: usize = 24;
+
+/// The device as served at `GET /device`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceReport {
+    /// The serial port the device is attached on, when known.
+    pub port: Option<String>,
+    /// The name stored on the device with `SetDeviceName`.
+    pub name: Option<String>,
+    /// The panic that reset the device before it attached, if any.
+    pub crash: Option<CrashReport>,
+    /// `None` until the device has been asked, or when it could not answer.
+    pub info: Option<DeviceInfo>,
+    pub host_protocol_version: u32,
+    /// Requests this host knows that the firmware does not handle.
+    pub unsupported_messages: Vec<String>,
+    /// Why the firmware and this host may not understand each other.
+    pub mismatch: Option<String>,
+}
+
+impl DeviceReport {
+    pub fn from_info(info: DeviceInfo) -> DeviceReport {
+        let unsupported_messages = request_messages()
+            .iter()
+            .filter(|m| {
+                1u64.checked_shl(m.tag)
+                    .is_none_or(|bit| info.supported_messages & bit == 0)
+            })
+            .map(|m| m.name.clone())
+            .collect();
+        let mismatch = (info.protocol_version != PROTOCOL_VERSION).then(|| {
+            format!(
+                "Firmware {} speaks protocol version {}, this host version {}",
+                info.firmware_version, info.protocol_version, PROTOCOL_VERSION
+            )
+        });
+        DeviceReport {
+            port: None,
+            name: None,
+            crash: None,
+            info: Some(info),
+            host_protocol_version: PROTOCOL_VERSION,
+            unsupported_messages,
+            mismatch,
+        }
+    }
+
+    /// Check the device's answer to `GetDeviceInfo`.
+    pub fn from_response(response: &ResponseBody) -> DeviceReport {
+        match &response.message {
+            Some(response_body::Message::DeviceInfo(info)) => Self::from_info(info.clone()),
+            // Not an answer to the handshake, e.g. an error.
+            _ => Self::unanswered("The firmware does not report its version; flash a newer one"),
+        }
+    }
+
+    /// Whether the device has been asked yet.
+    pub fn is_known(&self) -> bool {
+        self.info.is_some() || self.mismatch.is_some()
+    }
+
+    /// Refuse requests the firmware said it does not handle. It would answer
+    /// them with `UnknownError`, or not at all if it does not know the tag.
+    pub fn check_request(&self, msg: &RequestBody) -> Result<(), Rejected> {
+        match message_of(msg) {
+            Some(m) if self.unsupported_messages.contains(&m.name) => Err(Rejected(format!(
+                "The firmware on this device does not handle {}",
+                m.name
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn unanswered(reason: &str) -> DeviceReport {
+        DeviceReport {
+            port: None,
+            name: None,
+            crash: None,
+            info: None,
+            host_protocol_version: PROTOCOL_VERSION,
+            unsupported_messages: Vec::new(),
+            mismatch: Some(reason.to_string()),
+        }
+    }
+}
+
+/// The name in a `GetDeviceName` answer; the firmware sends an empty one
+/// when none is stored.
+pub fn name_from_response(response: &ResponseBody) -> Option<String> {
+    match &response.message {
+        Some(response_body::Message::DeviceName(d)) if !d.name.is_empty() => Some(d.name.clone()),
+        _ => None,
+    }
+}
+
+/// The crash in a `GetCrashReport` answer; the firmware leaves both fields
+/// empty when there was none.
+pub fn crash_from_response(response: &ResponseBody) -> Option<CrashReport> {
+    match &response.message {
+        Some(response_body::Message::CrashReport(c))
+            if !c.message.is_empty() || !c.location.is_empty() =>
+        {
+            Some(c.clone())
+        }
+        _ => None,
+    }
+}
+
+/// Refuse device names the firmware cannot store.
+pub fn check_request(msg: &RequestBody) -> Result<(), Rejected> {
+    match &msg.message {
+        Some(Message::SetDeviceName(m)) if m.name.len() > DEVICE_NAME_MAX_LEN => Err(Rejected(
+            format!("Device names are at most {} bytes", DEVICE_NAME_MAX_LEN),
+        )),
+        Some(Message::SetDeviceName(m)) if m.name.chars().any(char::is_control) => Err(Rejected(
+            "Device names cannot contain control characters".to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
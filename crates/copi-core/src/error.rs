@@ -0,0 +1,80 @@
+use std::{fmt, time::Duration};
+
+use crate::generated::{ResponseBody, ResponseCommonErrorCode, response_body};
+
+/// A non-zero error code reported by the device in a `Common` response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceError {
+    pub code: u32,
+    pub data: u64,
+}
+
+impl DeviceError {
+    pub fn name(&self) -> &'static str {
+        ResponseCommonErrorCode::try_from(self.code as i32)
+            .map(|c| c.as_str_name())
+            .unwrap_or("UNRECOGNIZED_ERROR")
+    }
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "device error {} ({}), data {}",
+            self.name(),
+            self.code,
+            self.data
+        )
+    }
+}
+
+impl std::error::Error for DeviceError {}
+
+/// A request refused by the host before it reached the device, because the
+/// known device state says it cannot succeed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejected(pub String);
+
+impl fmt::Display for Rejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+/// The device could not answer a request: too many were already waiting for
+/// it, or it did not answer in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unavailable {
+    TooManyPending { limit: usize },
+    TimedOut(Duration),
+}
+
+impl fmt::Display for Unavailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unavailable::TooManyPending { limit } => {
+                write!(f, "Too many pending requests (limit {})", limit)
+            }
+            Unavailable::TimedOut(limit) => write!(f, "No response within {:?}", limit),
+        }
+    }
+}
+
+impl std::error::Error for Unavailable {}
+
+/// Extract `data` from a `Common` response, or the device error it carries.
+///
+/// Responses of any other kind are returned as `Ok(None)`.
+pub fn common_data(body: &ResponseBody) -> Result<Option<u64>, DeviceError> {
+    match &body.message {
+        Some(response_body::Message::Common(common)) if common.error != 0 => Err(DeviceError {
+            code: common.error,
+            data: common.data,
+        }),
+        Some(response_body::Message::Common(common)) => Ok(Some(common.data)),
+        _ => Ok(None),
+    }
+}
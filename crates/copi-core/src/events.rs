@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    generated::{RequestBody, ResponseBody},
+    logs::LogEntry,
+};
+
+/// Capacity of the event broadcast channel; slow subscribers skip ahead.
+pub const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// Something that happened on the device or in the service, pushed to every
+/// subscriber of [`crate::AppState::subscribe_events`] and `GET /events`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Event {
+    /// An unsolicited message from the device (a response with request ID 0).
+    Device { body: ResponseBody },
+    /// A query that went through the service, with its round-trip time.
+    #[serde(rename_all = "camelCase")]
+    Request {
+        request: RequestBody,
+        response: Option<ResponseBody>,
+        error: Option<String>,
+        latency_us: u64,
+    },
+    /// The link to the device came up or went down.
+    Connection { connected: bool },
+    /// A log line from the firmware.
+    Log(LogEntry),
+}
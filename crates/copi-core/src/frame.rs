@@ -0,0 +1,44 @@
+//! Reading length-prefixed messages from the serial link, see `copi-frame`.
+//! Messages go out with prost's `encode_length_delimited_to_vec`.
+
+use anyhow::{Result, anyhow};
+use copi_frame::{Frame, next_frame};
+use prost::Message;
+
+pub use copi_frame::MAX_FRAME_LEN;
+
+/// Bytes read from the device that have not made a whole message yet.
+#[derive(Debug, Default)]
+pub struct FrameBuffer {
+    buf: Vec<u8>,
+}
+
+impl FrameBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn extend(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    /// The next whole message, if one has arrived. A length past
+    /// [`MAX_FRAME_LEN`] means the stream is out of step, so everything
+    /// buffered is dropped.
+    pub fn next_message<M: Message + Default>(&mut self) -> Option<Result<M>> {
+        let range = match next_frame(&self.buf) {
+            Frame::Incomplete => return None,
+            Frame::Complete(range) => range,
+            Frame::TooLong => {
+                self.buf.clear();
+                return Some(Err(anyhow!(
+                    "Message is longer than {} bytes",
+                    MAX_FRAME_LEN
+                )));
+            }
+        };
+        let message = M::decode(&self.buf[range.clone()]).map_err(Into::into);
+        self.buf.drain(..range.end);
+        Some(message)
+    }
+}
@@ -0,0 +1,324 @@
+//! Leases let a client claim pins, PWM slices and PIO state machines for a
+//! while, so other clients' requests touching them are refused.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    board::BoardProfile,
+    error::Rejected,
+    generated::{RequestBody, request_body::Message},
+};
+
+/// HTTP header listing the leases a client holds, comma separated.
+pub const LEASE_HEADER: &str = "x-copi-lease";
+
+/// Lease lifetime when the client does not ask for one.
+pub const DEFAULT_LEASE_TTL: Duration = Duration::from_secs(30);
+
+const PIO_SM_COUNT: u32 = 4;
+
+/// A fresh id for a lease or session. Ids double as the credential for
+/// using them, so they are 128 bits from the OS random number generator.
+pub(crate) fn random_id() -> String {
+    let mut bytes = [0u8; 16];
+    getrandom::fill(&mut bytes).expect("OS random number generator failed");
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Resource {
+    Pin(u32),
+    PwmSlice(u32),
+    PioSm { block: u32, sm: u32 },
+}
+
+impl fmt::Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Resource::Pin(pin) => write!(f, "GP{}", pin),
+            Resource::PwmSlice(slice) => write!(f, "pwm{}", slice),
+            Resource::PioSm { block, sm } => write!(f, "pio{}.sm{}", block, sm),
+        }
+    }
+}
+
+impl Serialize for Resource {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Resource {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Parses the canonical names written by `Display`.
+impl FromStr for Resource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse_with(s, |pin| {
+            let gpio = pin
+                .strip_prefix("GP")
+                .and_then(|n| n.parse().ok())
+                .with_context(|| format!("`{}` is not a resource", pin))?;
+            Ok(gpio)
+        })
+    }
+}
+
+impl Resource {
+    /// Parse `pwm3`, `pio0.sm1`, or a pin as the board names it.
+    pub fn parse(s: &str, board: &BoardProfile) -> Result<Self> {
+        Self::parse_with(s, |pin| board.resolve(pin))
+    }
+
+    fn parse_with(s: &str, pin: impl FnOnce(&str) -> Result<u32>) -> Result<Self> {
+        let lower = s.trim().to_ascii_lowercase();
+        if let Some(slice) = lower.strip_prefix("pwm") {
+            let slice = slice
+                .parse()
+                .with_context(|| format!("`{}` is not a PWM slice", s))?;
+            if slice >= 8 {
+                bail!("PWM slice must be 0..=7, got {}", slice);
+            }
+            return Ok(Resource::PwmSlice(slice));
+        }
+        if let Some(rest) = lower.strip_prefix("pio") {
+            let parsed = rest
+                .split_once(".sm")
+                .and_then(|(block, sm)| Some((block.parse().ok()?, sm.parse().ok()?)));
+            let Some((block, sm)) = parsed else {
+                bail!("`{}` is not a state machine, expected e.g. pio0.sm1", s);
+            };
+            if block >= 3 || sm >= PIO_SM_COUNT {
+                bail!("`{}` is out of range, expected pio0-2 and sm0-3", s);
+            }
+            return Ok(Resource::PioSm { block, sm });
+        }
+        Ok(Resource::Pin(pin(s.trim())?))
+    }
+
+    /// Everything `request` would touch on the device.
+    pub fn of_request(request: &RequestBody) -> Vec<Resource> {
+        let slice_of = |pin: u32| Resource::PwmSlice((pin >> 1) & 7);
+        match &request.message {
+            Some(Message::GpioOutputInit(m)) => vec![Resource::Pin(m.pin)],
+            Some(Message::GpioOutputSet(m)) => vec![Resource::Pin(m.pin)],
+            Some(Message::GpioOutputGet(m)) => vec![Resource::Pin(m.pin)],
+            Some(Message::PinDeinit(m)) => vec![Resource::Pin(m.pin)],
+            Some(Message::PwmDeinit(m)) => vec![Resource::PwmSlice(m.slice)],
+            Some(Message::PioSmDeinit(m)) => vec![Resource::PioSm {
+                block: m.pio_num,
+                sm: m.sm_num,
+            }],
+            Some(Message::PwmInit(m)) => std::iter::once(Resource::PwmSlice(m.slice))
+                .chain(m.a.into_iter().chain(m.b).map(Resource::Pin))
+                .collect(),
+            Some(Message::PwmSetDutyCyclePercent(m)) => {
+                vec![Resource::Pin(m.pin), slice_of(m.pin)]
+            }
+            // A new program can replace the one any state machine is running.
+            Some(Message::PioLoadProgram(m)) => (0..PIO_SM_COUNT)
+                .map(|sm| Resource::PioSm {
+                    block: m.pio_num,
+                    sm,
+                })
+                .collect(),
+            Some(Message::PioSmInit(m)) => vec![
+                Resource::PioSm {
+                    block: m.pio_num,
+                    sm: m.sm_num,
+                },
+                Resource::Pin(m.pin_num),
+            ],
+            Some(Message::PioSmSetEnable(m)) => vec![Resource::PioSm {
+                block: m.pio_num,
+                sm: m.sm_num,
+            }],
+            Some(Message::PioSmPush(m)) => vec![Resource::PioSm {
+                block: m.pio_num,
+                sm: m.sm_num,
+            }],
+            Some(Message::PioSmExecInstr(m)) => vec![Resource::PioSm {
+                block: m.pio_num,
+                sm: m.sm_num,
+            }],
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Lease {
+    owner: String,
+    resources: Vec<Resource>,
+    expires_at: Instant,
+}
+
+/// A lease as reported to clients.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeaseInfo {
+    pub id: String,
+    pub owner: String,
+    pub resources: Vec<Resource>,
+    pub expires_in_ms: u64,
+}
+
+/// The body of `POST /leases`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeaseRequest {
+    pub owner: String,
+    /// Resource names such as `GP15`, `LED`, `pwm7` or `pio0.sm1`.
+    pub resources: Vec<String>,
+    pub ttl_ms: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+pub struct LeaseTable {
+    leases: HashMap<String, Lease>,
+}
+
+impl LeaseTable {
+    pub fn acquire(
+        &mut self,
+        owner: &str,
+        resources: Vec<Resource>,
+        ttl: Duration,
+    ) -> Result<LeaseInfo, Rejected> {
+        self.expire();
+        if resources.is_empty() {
+            return Err(Rejected("A lease needs at least one resource".into()));
+        }
+        for resource in &resources {
+            if let Some((_, lease)) = self.holder(*resource) {
+                return Err(Rejected(format!(
+                    "{} is leased by {}",
+                    resource, lease.owner
+                )));
+            }
+        }
+        let id = loop {
+            let id = random_id();
+            if !self.leases.contains_key(&id) {
+                break id;
+            }
+        };
+        self.leases.insert(
+            id.clone(),
+            Lease {
+                owner: owner.to_string(),
+                resources,
+                expires_at: Instant::now() + ttl,
+            },
+        );
+        Ok(self.info(&id).unwrap())
+    }
+
+    pub fn renew(&mut self, id: &str, ttl: Duration) -> Option<LeaseInfo> {
+        self.expire();
+        self.leases.get_mut(id)?.expires_at = Instant::now() + ttl;
+        self.info(id)
+    }
+
+    pub fn release(&mut self, id: &str) -> Option<LeaseInfo> {
+        let info = self.info(id);
+        if let Some(lease) = self.leases.remove(id) {
+            log::info!("Released lease of {} on {:?}", lease.owner, lease.resources);
+        }
+        info
+    }
+
+    pub fn list(&mut self) -> Vec<LeaseInfo> {
+        self.expire();
+        let mut leases: Vec<LeaseInfo> =
+            self.leases.keys().filter_map(|id| self.info(id)).collect();
+        leases.sort_by(|a, b| a.owner.cmp(&b.owner).then(a.id.cmp(&b.id)));
+        leases
+    }
+
+    /// Whether lease `id` is still held.
+    pub fn is_held(&mut self, id: &str) -> bool {
+        self.expire();
+        self.leases.contains_key(id)
+    }
+
+    /// Refuse `request` if it touches a resource leased to someone not
+    /// presenting that lease's id in `held`.
+    pub fn check(&mut self, request: &RequestBody, held: &[String]) -> Result<(), Rejected> {
+        self.expire();
+        if self.leases.is_empty() {
+            return Ok(());
+        }
+        // A reset or reboot touches everything.
+        if let Some(Message::ResetAll(_) | Message::Reboot(_)) = &request.message {
+            match self.leases.iter().find(|(id, _)| !held.contains(id)) {
+                Some((_, lease)) => {
+                    let resources: Vec<String> =
+                        lease.resources.iter().map(ToString::to_string).collect();
+                    return Err(Rejected(format!(
+                        "{} leased by {}",
+                        resources.join(", "),
+                        lease.owner
+                    )));
+                }
+                None => return Ok(()),
+            }
+        }
+        for resource in Resource::of_request(request) {
+            match self.holder(resource) {
+                Some((id, lease)) if !held.iter().any(|h| h == id) => {
+                    return Err(Rejected(format!(
+                        "{} is leased by {}",
+                        resource, lease.owner
+                    )));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn holder(&self, resource: Resource) -> Option<(&String, &Lease)> {
+        self.leases
+            .iter()
+            .find(|(_, lease)| lease.resources.contains(&resource))
+    }
+
+    fn info(&self, id: &str) -> Option<LeaseInfo> {
+        let lease = self.leases.get(id)?;
+        Some(LeaseInfo {
+            id: id.to_string(),
+            owner: lease.owner.clone(),
+            resources: lease.resources.clone(),
+            expires_in_ms: lease
+                .expires_at
+                .saturating_duration_since(Instant::now())
+                .as_millis() as u64,
+        })
+    }
+
+    fn expire(&mut self) {
+        let now = Instant::now();
+        self.leases.retain(|_, lease| {
+            let live = lease.expires_at > now;
+            if !live {
+                log::info!("Lease of {} on {:?} expired", lease.owner, lease.resources);
+            }
+            live
+        });
+    }
+}
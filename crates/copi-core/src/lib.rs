@@ -1,37 +1,76 @@
 mod api;
+pub mod board;
+#[cfg(feature = "client")]
+pub mod client;
+pub mod device;
+pub mod error;
+pub mod events;
+pub mod frame;
+pub mod lease;
+pub mod logs;
 // #[cfg(target_os = "android")]
 pub mod mobile;
+pub mod pio;
+pub mod schema;
+pub mod session;
+pub mod shadow;
 // mod types;
 pub mod generated {
     include!(concat!(env!("OUT_DIR"), "/copi.rs"));
+    include!(concat!(env!("OUT_DIR"), "/request_tags.rs"));
 }
 
 use std::{
     collections::HashMap,
     net::SocketAddr,
-    sync::{Arc, Mutex, atomic::AtomicU32},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU32, Ordering},
+    },
+    time::{Duration, Instant},
 };
 
 use anyhow::{Context, Result};
 use axum::{
     Router,
-    routing::{get, post},
+    routing::{delete, get, post},
 };
+use board::BoardProfile;
+use device::DeviceReport;
+use error::{Rejected, Unavailable, common_data};
+use events::{EVENT_CHANNEL_CAPACITY, Event};
 use generated::request_body::Message;
 use generated::*;
+use lease::{DEFAULT_LEASE_TTL, LeaseInfo, LeaseRequest, LeaseTable, Resource};
+use logs::{LogBuffer, LogEntry, LogLevel};
 use prost::Message as _;
+use session::{
+    Caller, DEFAULT_SESSION_IDLE_TIMEOUT, SessionInfo, SessionRequest, SessionTable,
+    release_request,
+};
+use shadow::DeviceShadow;
+use subtle::ConstantTimeEq as _;
 use tokio::io::AsyncReadExt;
 use tokio::{
     io::AsyncWriteExt,
     sync::{
+        broadcast,
         mpsc::{UnboundedReceiver, UnboundedSender},
-        oneshot,
+        oneshot, watch,
     },
     task::JoinHandle,
 };
 
 pub const MAX_USB_PACKET_SIZE: usize = 64;
 
+pub const COPI_USB_VID: u16 = 0x9527;
+pub const COPI_USB_PID: u16 = 0xacdc;
+
+/// How long the requests sent while a device attaches wait for an answer,
+/// whatever the configured request timeout. Firmware that does not know a
+/// message never answers it.
+pub const ATTACH_TIMEOUT: Duration = Duration::from_secs(2);
+
 struct NonZeroU32Count(AtomicU32);
 
 impl NonZeroU32Count {
@@ -49,33 +88,77 @@ impl NonZeroU32Count {
     }
 }
 
+/// Limits and access control for the service behind an [`AppState`].
+#[derive(Debug, Clone)]
+pub struct ServiceOptions {
+    /// Fail a query that has had no response for this long.
+    pub request_timeout: Option<Duration>,
+    /// Refuse new queries while this many are waiting for a response.
+    pub max_pending_requests: usize,
+    /// Events buffered per subscriber before a slow one starts skipping.
+    pub event_buffer: usize,
+    /// Bearer tokens accepted by the HTTP API; empty means no authentication.
+    pub auth_tokens: Vec<String>,
+    /// Requests using pins the board does not break out are rejected.
+    pub board: BoardProfile,
+}
+
+impl Default for ServiceOptions {
+    fn default() -> Self {
+        Self {
+            request_timeout: None,
+            max_pending_requests: 256,
+            event_buffer: EVENT_CHANNEL_CAPACITY,
+            auth_tokens: Vec::new(),
+            board: BoardProfile::default(),
+        }
+    }
+}
+
 #[derive(Clone)]
 struct DeviceChannel {
     non_zero_count: Arc<NonZeroU32Count>,
     callbacks: Arc<Mutex<HashMap<u32, oneshot::Sender<ResponseBody>>>>,
     request_tx: Arc<UnboundedSender<CopiRequest>>,
+    request_timeout: Option<Duration>,
+    max_pending_requests: usize,
 }
 
 impl DeviceChannel {
-    pub async fn query(&self, msg: RequestBody) -> Result<ResponseBody> {
+    /// Send `msg` and wait for its response, for at most `limit`.
+    pub async fn query(&self, msg: RequestBody, limit: Option<Duration>) -> Result<ResponseBody> {
         let id = self.non_zero_count.next();
         let (tx, rx) = oneshot::channel();
         {
             let mut callbacks = self.callbacks.lock().unwrap();
+            if callbacks.len() >= self.max_pending_requests {
+                return Err(Unavailable::TooManyPending {
+                    limit: self.max_pending_requests,
+                }
+                .into());
+            }
             callbacks.insert(id, tx);
         }
 
-        let mut request = CopiRequest::default(); // (id, msg);
-        request.request_id = id;
-        request.payload.replace(msg);
+        let request = CopiRequest {
+            request_id: id,
+            payload: Some(msg),
+        };
         self.request_tx
             .send(request)
             .with_context(|| "Failed to send request")?;
 
-        let res = rx
-            .await
-            .with_context(|| "Failed to receive response, sender dropped")?;
-        Ok(res)
+        let res = match limit {
+            Some(limit) => match tokio::time::timeout(limit, rx).await {
+                Ok(res) => res,
+                Err(_) => {
+                    self.callbacks.lock().unwrap().remove(&id);
+                    return Err(Unavailable::TimedOut(limit).into());
+                }
+            },
+            None => rx.await,
+        };
+        res.with_context(|| "Failed to receive response, sender dropped")
     }
 
     pub fn send(&self, msg: RequestBody) -> Result<()> {
@@ -91,7 +174,17 @@ impl DeviceChannel {
 #[derive(Clone)]
 pub struct AppState {
     device_channel: DeviceChannel,
+    events_tx: broadcast::Sender<Event>,
+    connected: Arc<AtomicBool>,
+    shutdown_tx: Arc<watch::Sender<bool>>,
     response_task: Arc<JoinHandle<()>>,
+    auth_tokens: Arc<Vec<String>>,
+    board: Arc<BoardProfile>,
+    shadow: Arc<Mutex<DeviceShadow>>,
+    leases: Arc<Mutex<LeaseTable>>,
+    sessions: Arc<Mutex<SessionTable>>,
+    device: Arc<Mutex<DeviceReport>>,
+    logs: Arc<Mutex<LogBuffer>>,
 }
 
 impl AppState {
@@ -99,39 +192,455 @@ impl AppState {
         request_tx: UnboundedSender<CopiRequest>,
         response_rx: UnboundedReceiver<CopiResponse>,
         #[cfg(target_os = "android")] runtime: &tokio::runtime::Runtime,
+    ) -> Self {
+        Self::with_options(
+            request_tx,
+            response_rx,
+            ServiceOptions::default(),
+            #[cfg(target_os = "android")]
+            runtime,
+        )
+    }
+
+    pub fn with_options(
+        request_tx: UnboundedSender<CopiRequest>,
+        response_rx: UnboundedReceiver<CopiResponse>,
+        options: ServiceOptions,
+        #[cfg(target_os = "android")] runtime: &tokio::runtime::Runtime,
     ) -> Self {
         let device_channel = DeviceChannel {
             non_zero_count: Arc::new(NonZeroU32Count::new()),
             callbacks: Arc::new(Mutex::new(HashMap::new())),
             request_tx: Arc::new(request_tx),
+            request_timeout: options.request_timeout,
+            max_pending_requests: options.max_pending_requests.max(1),
         };
 
+        let (events_tx, _) = broadcast::channel(options.event_buffer.max(1));
+
         let callbacks = device_channel.callbacks.clone();
+        let response_events_tx = events_tx.clone();
+        let logs = Arc::new(Mutex::new(LogBuffer::default()));
         #[cfg(not(target_os = "android"))]
-        let response_task = tokio::spawn(Self::handle_response(response_rx, callbacks));
+        let response_task = tokio::spawn(Self::handle_response(
+            response_rx,
+            callbacks,
+            response_events_tx,
+            logs.clone(),
+        ));
         #[cfg(target_os = "android")]
-        let response_task = runtime.spawn(Self::handle_response(response_rx, callbacks));
+        let response_task = runtime.spawn(Self::handle_response(
+            response_rx,
+            callbacks,
+            response_events_tx,
+            logs.clone(),
+        ));
 
         Self {
             device_channel,
+            events_tx,
+            connected: Arc::new(AtomicBool::new(false)),
+            shutdown_tx: Arc::new(watch::channel(false).0),
             response_task: Arc::new(response_task),
+            auth_tokens: Arc::new(options.auth_tokens),
+            board: Arc::new(options.board),
+            shadow: Arc::new(Mutex::new(DeviceShadow::default())),
+            leases: Arc::new(Mutex::new(LeaseTable::default())),
+            sessions: Arc::new(Mutex::new(SessionTable::default())),
+            device: Arc::new(Mutex::new(DeviceReport::default())),
+            logs,
+        }
+    }
+
+    /// Whether `token` may use the HTTP API. Always true when no tokens are
+    /// configured.
+    pub fn is_authorized(&self, token: Option<&str>) -> bool {
+        self.auth_tokens.is_empty()
+            || token.is_some_and(|token| {
+                // Every token is compared in constant time, so response
+                // times do not tell how much of a guess was right.
+                self.auth_tokens.iter().fold(false, |found, t| {
+                    found | bool::from(t.as_bytes().ct_eq(token.as_bytes()))
+                })
+            })
+    }
+
+    pub fn board(&self) -> &BoardProfile {
+        &self.board
+    }
+
+    /// What each pin is doing, as far as requests through this service show.
+    pub fn shadow(&self) -> DeviceShadow {
+        self.shadow.lock().unwrap().clone()
+    }
+
+    /// Send a request to the device and wait for its response.
+    pub async fn query(&self, msg: RequestBody) -> Result<ResponseBody> {
+        self.query_as(msg, &Caller::default()).await
+    }
+
+    /// Like [`AppState::query`], with the leases and session of `caller`.
+    pub async fn query_as(&self, msg: RequestBody, caller: &Caller) -> Result<ResponseBody> {
+        self.check_request(&msg, caller)?;
+        self.forward(msg, caller).await
+    }
+
+    /// Like [`AppState::query`], giving up after `limit` even when no
+    /// request timeout is configured.
+    pub async fn query_within(&self, msg: RequestBody, limit: Duration) -> Result<ResponseBody> {
+        let caller = Caller::default();
+        self.check_request(&msg, &caller)?;
+        let limit = match self.device_channel.request_timeout {
+            Some(configured) => configured.min(limit),
+            None => limit,
+        };
+        self.forward_within(msg, &caller, Some(limit)).await
+    }
+
+    async fn forward(&self, msg: RequestBody, caller: &Caller) -> Result<ResponseBody> {
+        self.forward_within(msg, caller, self.device_channel.request_timeout)
+            .await
+    }
+
+    async fn forward_within(
+        &self,
+        msg: RequestBody,
+        caller: &Caller,
+        limit: Option<Duration>,
+    ) -> Result<ResponseBody> {
+        let start = Instant::now();
+        let res = self.device_channel.query(msg.clone(), limit).await;
+        if let Ok(response) = &res {
+            self.shadow.lock().unwrap().apply(&msg, response);
+            if matches!(common_data(response), Ok(Some(_))) {
+                self.sessions.lock().unwrap().record(&msg, caller);
+                if let Some(Message::SetDeviceName(m)) = &msg.message {
+                    self.device.lock().unwrap().name = (!m.name.is_empty()).then(|| m.name.clone());
+                }
+            }
+        }
+        self.publish_event(Event::Request {
+            request: msg,
+            response: res.as_ref().ok().cloned(),
+            error: res.as_ref().err().map(|e| format!("{:#}", e)),
+            latency_us: start.elapsed().as_micros() as u64,
+        });
+        res
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    /// Record whether the device link is up and tell subscribers.
+    pub fn set_connected(&self, connected: bool) {
+        if self.connected.swap(connected, Ordering::SeqCst) != connected {
+            // Whatever is attached now may have been configured elsewhere.
+            if connected {
+                *self.shadow.lock().unwrap() = DeviceShadow::default();
+                *self.device.lock().unwrap() = DeviceReport::default();
+            }
+            self.publish_event(Event::Connection { connected });
+        }
+    }
+
+    /// What the device said about itself when last asked.
+    pub fn device(&self) -> DeviceReport {
+        self.device.lock().unwrap().clone()
+    }
+
+    /// Ask the device what it is, log anything this host cannot work with,
+    /// and keep the answer for [`AppState::device`]. `port` is reported
+    /// with it.
+    pub async fn refresh_device_info(&self, port: Option<&str>) -> DeviceReport {
+        let request = RequestBody {
+            message: Some(Message::GetDeviceInfo(GetDeviceInfo {})),
+        };
+        let mut report = match self.query_within(request, ATTACH_TIMEOUT).await {
+            Ok(response) => DeviceReport::from_response(&response),
+            // Firmware from before the handshake ignores the request.
+            Err(e)
+                if matches!(
+                    e.downcast_ref::<Unavailable>(),
+                    Some(Unavailable::TimedOut(_))
+                ) =>
+            {
+                DeviceReport::unanswered(
+                    "The firmware does not answer GetDeviceInfo; flash a newer one",
+                )
+            }
+            Err(e) => DeviceReport::unanswered(&format!(
+                "The device did not answer GetDeviceInfo: {:#}",
+                e
+            )),
+        };
+        report.port = port.map(str::to_string);
+        if report.info.is_some()
+            && !report
+                .unsupported_messages
+                .iter()
+                .any(|m| m == "getDeviceName")
+        {
+            let request = RequestBody {
+                message: Some(Message::GetDeviceName(GetDeviceName {})),
+            };
+            match self.query_within(request, ATTACH_TIMEOUT).await {
+                Ok(response) => report.name = device::name_from_response(&response),
+                Err(e) => log::warn!("The device did not answer GetDeviceName: {:#}", e),
+            }
+        }
+        // A crash found earlier stays until the device reattaches.
+        report.crash = self.device.lock().unwrap().crash.clone();
+        if let Some(info) = &report.info {
+            log::info!(
+                "Device {}{} {}, firmware {} (protocol {})",
+                report
+                    .name
+                    .as_ref()
+                    .map(|n| format!("\"{}\" ", n))
+                    .unwrap_or_default(),
+                info.chip_model,
+                info.unique_id,
+                info.firmware_version,
+                info.protocol_version
+            );
+        }
+        if let Some(mismatch) = &report.mismatch {
+            log::warn!("{}", mismatch);
         }
+        if !report.unsupported_messages.is_empty() {
+            log::info!(
+                "Not handled by this firmware: {}",
+                report.unsupported_messages.join(", ")
+            );
+        }
+        *self.device.lock().unwrap() = report.clone();
+        report
+    }
+
+    /// Ask the device for the panic that reset it, log it and keep it for
+    /// [`AppState::device`]. The firmware forgets the panic once it has
+    /// answered, so only the service that owns the device asks, right after
+    /// [`AppState::refresh_device_info`].
+    pub async fn collect_crash_report(&self) -> Option<CrashReport> {
+        let device = self.device();
+        if device.info.is_none()
+            || device
+                .unsupported_messages
+                .iter()
+                .any(|m| m == "getCrashReport")
+        {
+            return None;
+        }
+        let request = RequestBody {
+            message: Some(Message::GetCrashReport(GetCrashReport {})),
+        };
+        let crash = match self.query_within(request, ATTACH_TIMEOUT).await {
+            Ok(response) => device::crash_from_response(&response)?,
+            Err(e) => {
+                log::warn!("The device did not answer GetCrashReport: {:#}", e);
+                return None;
+            }
+        };
+        // Kept with the device's own log lines for `GET /logs`.
+        let entry = LogEntry::new(
+            LogLevel::Error,
+            "panic",
+            &format!("{} at {}", crash.message, crash.location),
+        );
+        log::error!("The device crashed before it attached: {}", entry.message);
+        Self::record_log(&self.logs, &self.events_tx, entry);
+        self.device.lock().unwrap().crash = Some(crash.clone());
+        Some(crash)
+    }
+
+    /// Send a request to the device without waiting for a response.
+    ///
+    /// The outcome is unknown, so nothing it sets up is tied to a session.
+    pub fn send(&self, msg: RequestBody) -> Result<()> {
+        self.send_as(msg, &Caller::default())
+    }
+
+    pub fn send_as(&self, msg: RequestBody, caller: &Caller) -> Result<()> {
+        self.check_request(&msg, caller)?;
+        self.device_channel.send(msg)
+    }
+
+    fn check_request(&self, msg: &RequestBody, caller: &Caller) -> Result<()> {
+        self.board.check_request(msg)?;
+        device::check_request(msg)?;
+        self.device.lock().unwrap().check_request(msg)?;
+        match &caller.session {
+            Some(id) if !self.sessions.lock().unwrap().touch(id) => {
+                return Err(Rejected(format!("Session {} has ended", id)).into());
+            }
+            _ => {}
+        }
+        self.leases.lock().unwrap().check(msg, &caller.leases)?;
+        self.shadow.lock().unwrap().check(msg)?;
+        Ok(())
+    }
+
+    /// Lease the resources named in `request` to its owner.
+    pub fn acquire_lease(&self, request: &LeaseRequest) -> Result<LeaseInfo> {
+        let resources = request
+            .resources
+            .iter()
+            .map(|r| Resource::parse(r, &self.board))
+            .collect::<Result<Vec<_>>>()?;
+        let ttl = request
+            .ttl_ms
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_LEASE_TTL);
+        let lease = self
+            .leases
+            .lock()
+            .unwrap()
+            .acquire(&request.owner, resources, ttl)?;
+        log::info!("Leased {:?} to {}", lease.resources, lease.owner);
+        Ok(lease)
+    }
+
+    pub fn renew_lease(&self, id: &str, ttl: Option<Duration>) -> Option<LeaseInfo> {
+        self.leases
+            .lock()
+            .unwrap()
+            .renew(id, ttl.unwrap_or(DEFAULT_LEASE_TTL))
+    }
+
+    pub fn release_lease(&self, id: &str) -> Option<LeaseInfo> {
+        self.leases.lock().unwrap().release(id)
+    }
+
+    pub fn leases(&self) -> Vec<LeaseInfo> {
+        self.leases.lock().unwrap().list()
+    }
+
+    pub fn is_lease_held(&self, id: &str) -> bool {
+        self.leases.lock().unwrap().is_held(id)
+    }
+
+    /// Start a session; see [`session`].
+    pub fn open_session(&self, request: &SessionRequest) -> SessionInfo {
+        let idle_timeout = request
+            .idle_timeout_ms
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_SESSION_IDLE_TIMEOUT);
+        let session = self
+            .sessions
+            .lock()
+            .unwrap()
+            .open(&request.owner, Some(idle_timeout));
+        log::info!("Opened session {} for {}", session.id, session.owner);
+        session
+    }
+
+    /// Start a session that lasts until [`AppState::close_session`], such as
+    /// one tied to a WebSocket.
+    pub fn open_connection_session(&self, owner: &str) -> SessionInfo {
+        let session = self.sessions.lock().unwrap().open(owner, None);
+        log::info!("Opened session {} for {}", session.id, session.owner);
+        session
+    }
+
+    /// End session `id` and release what it set up on the device.
+    pub async fn close_session(&self, id: &str) -> Option<SessionInfo> {
+        let session = self.sessions.lock().unwrap().close(id)?;
+        self.release_session(&session).await;
+        Some(session)
+    }
+
+    /// End sessions that have gone quiet for longer than their idle timeout.
+    pub async fn close_idle_sessions(&self) {
+        let idle = self.sessions.lock().unwrap().close_idle();
+        for session in idle {
+            log::info!("Session {} of {} timed out", session.id, session.owner);
+            self.release_session(&session).await;
+        }
+    }
+
+    pub fn sessions(&self) -> Vec<SessionInfo> {
+        self.sessions.lock().unwrap().list()
+    }
+
+    async fn release_session(&self, session: &SessionInfo) {
+        // Newest first, so peripherals go before the pins they use.
+        for &resource in session.resources.iter().rev() {
+            let result = self
+                .forward(release_request(resource), &Caller::default())
+                .await
+                .and_then(|response| common_data(&response).map_err(Into::into));
+            match result {
+                Ok(_) => log::info!("Released {} of session {}", resource, session.id),
+                Err(e) => log::warn!(
+                    "Failed to release {} of session {}: {:#}",
+                    resource,
+                    session.id,
+                    e
+                ),
+            }
+        }
+    }
+
+    pub fn subscribe_events(&self) -> broadcast::Receiver<Event> {
+        self.events_tx.subscribe()
+    }
+
+    /// Log lines the device has sent, oldest first.
+    pub fn logs(&self) -> Vec<LogEntry> {
+        self.logs.lock().unwrap().entries()
+    }
+
+    /// [`AppState::logs`] and a subscription that carries on from the last
+    /// of them, with nothing missed or repeated in between.
+    pub fn follow_logs(&self) -> (Vec<LogEntry>, broadcast::Receiver<Event>) {
+        let logs = self.logs.lock().unwrap();
+        (logs.entries(), self.subscribe_events())
+    }
+
+    pub fn publish_event(&self, event: Event) {
+        // No subscribers is not an error.
+        let _ = self.events_tx.send(event);
+    }
+
+    fn record_log(logs: &Mutex<LogBuffer>, events_tx: &broadcast::Sender<Event>, entry: LogEntry) {
+        // Held while publishing so `follow_logs` sees each line exactly once.
+        let mut logs = logs.lock().unwrap();
+        logs.push(entry.clone());
+        let _ = events_tx.send(Event::Log(entry));
     }
 
     async fn handle_response(
         mut response_rx: UnboundedReceiver<CopiResponse>,
         callbacks: Arc<Mutex<HashMap<u32, oneshot::Sender<ResponseBody>>>>,
+        events_tx: broadcast::Sender<Event>,
+        logs: Arc<Mutex<LogBuffer>>,
     ) {
         while let Some(resp) = response_rx.recv().await {
             let id = resp.request_id;
-            if id == 0 {
-                log::warn!("Received response with ID 0, ignoring");
-                continue;
-            }
             let Some(payload) = resp.payload else {
                 log::warn!("Received response with no payload, ignoring");
                 continue;
             };
+            if id == 0 {
+                match &payload.message {
+                    Some(response_body::Message::DeviceLog(device_log)) => {
+                        let entry = LogEntry::from_proto(device_log);
+                        log::log!(
+                            target: "copi::device",
+                            entry.level.into(),
+                            "{}: {}",
+                            entry.module,
+                            entry.message
+                        );
+                        Self::record_log(&logs, &events_tx, entry);
+                    }
+                    _ => {
+                        let _ = events_tx.send(Event::Device { body: payload });
+                    }
+                }
+                continue;
+            }
 
             let mut callbacks = callbacks.lock().unwrap();
             if let Some(sender) = callbacks.remove(&id) {
@@ -143,29 +652,203 @@ impl AppState {
             }
         }
     }
+
+    /// Tell long-running API handlers such as `/events` to finish.
+    pub fn begin_shutdown(&self) {
+        self.shutdown_tx.send_replace(true);
+    }
+
+    /// Resolves once [`AppState::begin_shutdown`] has been called.
+    pub fn shutdown_requested(&self) -> impl Future<Output = ()> + Send + use<> {
+        let mut rx = self.shutdown_tx.subscribe();
+        async move {
+            let _ = rx.wait_for(|stop| *stop).await;
+        }
+    }
+
+    /// Stop routing responses and fail every pending query.
+    ///
+    /// Waiters see "sender dropped", which the API turns into a 500.
+    pub fn close(&self) {
+        self.begin_shutdown();
+        self.response_task.abort();
+        let mut callbacks = self.device_channel.callbacks.lock().unwrap();
+        if !callbacks.is_empty() {
+            log::warn!("Dropping {} pending request(s)", callbacks.len());
+        }
+        callbacks.clear();
+    }
 }
 
 #[cfg(any(target_os = "macos", target_os = "linux", target_os = "windows"))]
 pub fn open_copi_serial() -> tokio_serial::SerialStream {
-    use tokio_serial::SerialPortBuilderExt as _;
+    let port_name = match find_copi_serial_port() {
+        Ok(port_name) => port_name,
+        Err(e) => {
+            log::warn!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    log::info!("Found device: {:?}", port_name);
 
-    let Some(device) = serialport::available_ports()
-        .unwrap()
+    open_serial_port(&port_name).unwrap()
+}
+
+/// Picks the device to use when several may be attached. Unset fields
+/// match anything, except that VID/PID default to Copi's.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceSelector {
+    /// Use this port as is, without enumerating USB devices.
+    pub port: Option<String>,
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    pub serial_number: Option<String>,
+    /// The name stored on the device with `SetDeviceName`. Only
+    /// [`resolve_serial_port`] checks it, since it means asking the device.
+    pub name: Option<String>,
+}
+
+/// List the serial ports of all attached devices with the Copi VID/PID.
+#[cfg(any(target_os = "macos", target_os = "linux", target_os = "windows"))]
+pub fn list_copi_serial_ports() -> Result<Vec<String>> {
+    list_serial_ports(&DeviceSelector::default())
+}
+
+/// List the serial ports of all attached devices matching `selector`.
+#[cfg(any(target_os = "macos", target_os = "linux", target_os = "windows"))]
+pub fn list_serial_ports(selector: &DeviceSelector) -> Result<Vec<String>> {
+    Ok(list_serial_devices(selector)?
         .into_iter()
-        .find(|s| match &s.port_type {
-            serialport::SerialPortType::UsbPort(info) => info.vid == 0x9527 && info.pid == 0xacdc,
-            _ => false,
+        .map(|d| d.port)
+        .collect())
+}
+
+/// A device attached over USB serial.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SerialDevice {
+    pub port: String,
+    pub serial_number: Option<String>,
+}
+
+/// List all attached devices matching `selector`, with their USB serial
+/// numbers.
+#[cfg(any(target_os = "macos", target_os = "linux", target_os = "windows"))]
+pub fn list_serial_devices(selector: &DeviceSelector) -> Result<Vec<SerialDevice>> {
+    if let Some(port) = &selector.port {
+        return Ok(vec![SerialDevice {
+            port: port.clone(),
+            serial_number: None,
+        }]);
+    }
+    let vid = selector.vid.unwrap_or(COPI_USB_VID);
+    let pid = selector.pid.unwrap_or(COPI_USB_PID);
+    let ports = serialport::available_ports().with_context(|| "Failed to list serial ports")?;
+    Ok(ports
+        .into_iter()
+        .filter_map(|s| match s.port_type {
+            serialport::SerialPortType::UsbPort(info)
+                if info.vid == vid
+                    && info.pid == pid
+                    && selector
+                        .serial_number
+                        .as_ref()
+                        .is_none_or(|sn| info.serial_number.as_ref() == Some(sn)) =>
+            {
+                Some(SerialDevice {
+                    port: s.port_name,
+                    serial_number: info.serial_number,
+                })
+            }
+            _ => None,
         })
-    else {
-        log::warn!("Device not found");
-        std::process::exit(1);
+        .collect())
+}
+
+#[cfg(any(target_os = "macos", target_os = "linux", target_os = "windows"))]
+pub fn find_copi_serial_port() -> Result<String> {
+    find_serial_port(&DeviceSelector::default())
+}
+
+#[cfg(any(target_os = "macos", target_os = "linux", target_os = "windows"))]
+pub fn find_serial_port(selector: &DeviceSelector) -> Result<String> {
+    list_serial_ports(selector)?
+        .into_iter()
+        .next()
+        .with_context(|| "Device not found")
+}
+
+/// Like [`find_serial_port`], but when `selector.name` is set, each matching
+/// device is asked for its name and the first one that has it is picked.
+#[cfg(any(target_os = "macos", target_os = "linux", target_os = "windows"))]
+pub async fn resolve_serial_port(selector: &DeviceSelector) -> Result<String> {
+    let Some(name) = &selector.name else {
+        return find_serial_port(selector);
+    };
+    for port in list_serial_ports(selector)? {
+        match read_device_name(&port).await {
+            Ok(Some(n)) if &n == name => return Ok(port),
+            Ok(_) => {}
+            Err(e) => log::debug!("{:#}", e),
+        }
+    }
+    anyhow::bail!("No device named {:?} found", name)
+}
+
+/// How long [`read_device_name`] waits for the device to answer.
+#[cfg(any(target_os = "macos", target_os = "linux", target_os = "windows"))]
+const READ_NAME_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Ask the device on `port_name` for its stored name, then close the port.
+#[cfg(any(target_os = "macos", target_os = "linux", target_os = "windows"))]
+pub async fn read_device_name(port_name: &str) -> Result<Option<String>> {
+    let mut port = open_serial_port(port_name)?;
+    let request = CopiRequest {
+        request_id: 1,
+        payload: Some(RequestBody {
+            message: Some(Message::GetDeviceName(GetDeviceName {})),
+        }),
     };
+    port.write_all(&request.encode_length_delimited_to_vec())
+        .await?;
+
+    let mut response_buf = [0u8; MAX_USB_PACKET_SIZE];
+    let mut frames = frame::FrameBuffer::new();
+    let deadline = tokio::time::Instant::now() + READ_NAME_TIMEOUT;
+    loop {
+        let n = tokio::time::timeout_at(deadline, port.read(&mut response_buf))
+            .await
+            .with_context(|| format!("No answer from {}", port_name))??;
+        if n == 0 {
+            anyhow::bail!("{} closed before answering", port_name);
+        }
+        frames.extend(&response_buf[..n]);
+        // Log lines the device sends unprompted come with request ID 0.
+        while let Some(response) = frames.next_message::<CopiResponse>() {
+            match response {
+                Ok(response) if response.request_id == request.request_id => {
+                    return Ok(response
+                        .payload
+                        .as_ref()
+                        .and_then(device::name_from_response));
+                }
+                Ok(_) => {}
+                Err(e) => log::debug!("Skipped a message from {}: {:#}", port_name, e),
+            }
+        }
+    }
+}
 
-    log::info!("Found device: {:?}", device.port_name);
+/// Open a serial port by path, e.g. `/dev/ttyACM0` or `COM3`.
+///
+/// Must be called from within a Tokio runtime.
+#[cfg(any(target_os = "macos", target_os = "linux", target_os = "windows"))]
+pub fn open_serial_port(port_name: &str) -> Result<tokio_serial::SerialStream> {
+    use tokio_serial::SerialPortBuilderExt as _;
 
-    tokio_serial::new(device.port_name, 0)
+    tokio_serial::new(port_name, 0)
         .open_native_async()
-        .unwrap()
+        .with_context(|| format!("Failed to open serial port {}", port_name))
 }
 
 #[cfg(any(target_os = "macos", target_os = "linux", target_os = "windows"))]
@@ -173,16 +856,24 @@ pub async fn start_usb_cdc_service(
     mut port: tokio_serial::SerialStream,
     mut request_rx: UnboundedReceiver<CopiRequest>,
     response_tx: UnboundedSender<CopiResponse>,
+    mut shutdown: watch::Receiver<bool>,
 ) {
-    let mut request_buf = [0u8; MAX_USB_PACKET_SIZE];
     let mut response_buf = [0u8; MAX_USB_PACKET_SIZE];
-    loop {
+    let mut frames = frame::FrameBuffer::new();
+    'service: loop {
         tokio::select! {
+            res = shutdown.changed() => {
+                // A dropped sender is treated as a shutdown request.
+                if res.is_err() || *shutdown.borrow() {
+                    log::info!("USB CDC service shutdown requested");
+                    break;
+                }
+            }
             req = request_rx.recv() => {
                 if let Some(req) = req {
                     // TODO use buf
                     // TODO check size
-                    match port.write_all(&req.encode_to_vec()).await {
+                    match port.write_all(&req.encode_length_delimited_to_vec()).await {
                         Ok(_) => {
                             log::info!("Sent command: {:?}", req);
                         }
@@ -203,17 +894,19 @@ pub async fn start_usb_cdc_service(
                             continue;
                         }
 
-                        let response = CopiResponse::decode(&response_buf[..n]);
-                        match response {
-                            Ok(resp) => {
-                                log::info!("Received response: {:?}", resp);
-                                if response_tx.send(resp).is_err() {
-                                    log::warn!("Failed to send response to receiver");
-                                    break;
+                        frames.extend(&response_buf[..n]);
+                        while let Some(response) = frames.next_message::<CopiResponse>() {
+                            match response {
+                                Ok(resp) => {
+                                    log::info!("Received response: {:?}", resp);
+                                    if response_tx.send(resp).is_err() {
+                                        log::warn!("Failed to send response to receiver");
+                                        break 'service;
+                                    }
+                                }
+                                Err(e) => {
+                                    log::error!("Failed to decode response: {:#}", e);
                                 }
-                            }
-                            Err(e) => {
-                                log::error!("Failed to decode response: {:?}", e);
                             }
                         }
                     }
@@ -227,19 +920,92 @@ pub async fn start_usb_cdc_service(
     }
 }
 
+pub const DEFAULT_API_ADDR: &str = "0.0.0.0:8899";
+
 pub async fn start_api_service(state: AppState) {
+    start_api_service_with_shutdown(state, std::future::pending())
+        .await
+        .unwrap();
+}
+
+/// Serve the HTTP API until `shutdown` resolves, then stop accepting
+/// connections and release the listening socket.
+pub async fn start_api_service_with_shutdown<F>(state: AppState, shutdown: F) -> Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let listener = bind_api_listener(DEFAULT_API_ADDR).await?;
+    serve_api(listener, state, shutdown).await
+}
+
+pub async fn bind_api_listener(addr: &str) -> Result<tokio::net::TcpListener> {
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to bind {}", addr))?;
+    log::info!("listening on {}", listener.local_addr()?);
+    Ok(listener)
+}
+
+/// Serve the HTTP API on `listener`. Once `shutdown` resolves, new
+/// connections are refused and in-flight requests are allowed to finish.
+pub async fn serve_api<F>(
+    listener: tokio::net::TcpListener,
+    state: AppState,
+    shutdown: F,
+) -> Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
     let app = Router::new()
         .route("/query", post(api::query))
         .route("/command", post(api::command))
+        .route("/events", get(api::events::events))
+        .route("/status", get(api::status))
+        .route("/board", get(api::board))
+        .route("/state", get(api::state))
+        .route("/device", get(api::device))
+        .route("/logs", get(api::logs::logs))
+        .route("/leases", get(api::lease::list).post(api::lease::acquire))
+        .route("/leases/{id}", delete(api::lease::release))
+        .route("/leases/{id}/renew", post(api::lease::renew))
+        .route("/leases/{id}/watch", get(api::lease::watch))
+        .route(
+            "/sessions",
+            get(api::session::list).post(api::session::open),
+        )
+        .route("/sessions/{id}", delete(api::session::close))
+        .route("/ws", get(api::session::websocket))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            api::auth,
+        ))
+        // Static page, served without a token.
         .route("/playground", get(api::playground::playground))
-        .with_state(state);
+        .with_state(state.clone());
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:8899").await.unwrap();
-    log::info!("listening on {}", listener.local_addr().unwrap());
-    axum::serve(
+    // HTTP sessions end when their client goes quiet.
+    let sweep_state = state.clone();
+    let sweeper = tokio::spawn(async move {
+        let mut tick = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            tick.tick().await;
+            sweep_state.close_idle_sessions().await;
+        }
+    });
+
+    // Long-lived event streams would otherwise hold the graceful shutdown open.
+    let shutdown = async move {
+        shutdown.await;
+        state.begin_shutdown();
+    };
+    let result = axum::serve(
         listener,
         app.into_make_service_with_connect_info::<SocketAddr>(),
     )
-    .await
-    .unwrap();
+    .with_graceful_shutdown(shutdown)
+    .await;
+    sweeper.abort();
+    result?;
+    log::info!("API service stopped");
+    Ok(())
 }
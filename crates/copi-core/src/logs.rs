@@ -0,0 +1,102 @@
+//! Log lines the firmware sends on its own, as `DeviceLog` responses with
+//! request ID 0. The service logs them, keeps the latest for `GET /logs` and
+//! publishes each one as [`crate::events::Event::Log`].
+
+use std::{
+    collections::VecDeque,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::generated::DeviceLog;
+
+/// Lines kept for `GET /logs`; older ones are dropped.
+pub const LOG_BUFFER_CAPACITY: usize = 500;
+
+/// Numbered as in `DeviceLog.level` and the `log` crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5,
+}
+
+impl LogLevel {
+    /// Levels the firmware does not define are read as `Info`.
+    pub fn from_proto(level: u32) -> LogLevel {
+        match level {
+            1 => LogLevel::Error,
+            2 => LogLevel::Warn,
+            4 => LogLevel::Debug,
+            5 => LogLevel::Trace,
+            _ => LogLevel::Info,
+        }
+    }
+}
+
+impl From<LogLevel> for log::Level {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => log::Level::Error,
+            LogLevel::Warn => log::Level::Warn,
+            LogLevel::Info => log::Level::Info,
+            LogLevel::Debug => log::Level::Debug,
+            LogLevel::Trace => log::Level::Trace,
+        }
+    }
+}
+
+/// A device log line, stamped when the host received it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogEntry {
+    /// Milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    pub level: LogLevel,
+    /// The firmware module that logged it, e.g. `peripherals`.
+    pub module: String,
+    pub message: String,
+}
+
+impl LogEntry {
+    /// A line stamped now.
+    pub fn new(level: LogLevel, module: &str, message: &str) -> LogEntry {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        LogEntry {
+            timestamp_ms,
+            level,
+            module: module.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    pub fn from_proto(log: &DeviceLog) -> LogEntry {
+        Self::new(LogLevel::from_proto(log.level), &log.module, &log.message)
+    }
+}
+
+/// The latest [`LOG_BUFFER_CAPACITY`] lines.
+#[derive(Debug, Default)]
+pub struct LogBuffer {
+    entries: VecDeque<LogEntry>,
+}
+
+impl LogBuffer {
+    pub fn push(&mut self, entry: LogEntry) {
+        if self.entries.len() == LOG_BUFFER_CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Oldest first.
+    pub fn entries(&self) -> Vec<LogEntry> {
+        self.entries.iter().cloned().collect()
+    }
+}
@@ -1,10 +1,15 @@
 use crate::MAX_USB_PACKET_SIZE;
+use crate::frame::FrameBuffer;
 use crate::generated::*;
 use nusb::transfer::{Direction, RequestBuffer};
 use prost::Message as _;
-use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::{
+    mpsc::{UnboundedReceiver, UnboundedSender},
+    watch,
+};
 
 #[allow(unreachable_code)]
+#[allow(clippy::diverging_sub_expression)]
 #[allow(unused_variables)]
 #[allow(unused_mut)]
 // https://github.com/wuwbobo2021/android-usbser-rs
@@ -14,6 +19,7 @@ pub async fn start_usb_cdc_service(
     interface_data: i32,
     mut request_rx: UnboundedReceiver<CopiRequest>,
     response_tx: UnboundedSender<CopiResponse>,
+    mut shutdown: watch::Receiver<bool>,
 ) {
     // (android_usbser)
     // Safety: `close()` is not called automatically when the JNI `AutoLocal` of `conn`
@@ -40,9 +46,9 @@ pub async fn start_usb_cdc_service(
         let endps: Vec<_> = alt.endpoints().collect();
         let endp_r = endps.iter().find(|endp| endp.direction() == Direction::In);
         let endp_w = endps.iter().find(|endp| endp.direction() == Direction::Out);
-        if endp_r.is_some() && endp_w.is_some() {
-            addr_r = Some(endp_r.unwrap().address());
-            addr_w = Some(endp_w.unwrap().address());
+        if let (Some(endp_r), Some(endp_w)) = (endp_r, endp_w) {
+            addr_r = Some(endp_r.address());
+            addr_w = Some(endp_w.address());
             break;
         }
     }
@@ -57,17 +63,24 @@ pub async fn start_usb_cdc_service(
     }
 
     let mut request_buf = [0u8; MAX_USB_PACKET_SIZE];
-    let mut response_buf = [0u8; MAX_USB_PACKET_SIZE];
+    let mut frames = FrameBuffer::new();
 
     log::info!("USB CDC service started");
-    loop {
+    'service: loop {
         tokio::select! {
+            res = shutdown.changed() => {
+                // A dropped sender is treated as a shutdown request.
+                if res.is_err() || *shutdown.borrow() {
+                    log::info!("USB CDC service shutdown requested");
+                    break;
+                }
+            }
             req = request_rx.recv() => {
                 log::info!("Received request: {:?}", req);
                 // TODO use buf
                 // TODO check size
                 if let Some(cmd) = req {
-                    writer.submit(cmd.encode_to_vec().to_vec());
+                    writer.submit(cmd.encode_length_delimited_to_vec());
                 } else {
                     log::warn!("Command receiver closed");
                     break;
@@ -80,17 +93,19 @@ pub async fn start_usb_cdc_service(
                         break;
                 }
 
-                let response = CopiResponse::decode(&response_buf[..]);
-                match response {
-                    Ok(resp) => {
-                        log::info!("Received response: {:?}", resp);
-                        if response_tx.send(resp).is_err() {
-                            log::warn!("Failed to send response to receiver");
-                            break;
+                frames.extend(&res.data);
+                while let Some(response) = frames.next_message::<CopiResponse>() {
+                    match response {
+                        Ok(resp) => {
+                            log::info!("Received response: {:?}", resp);
+                            if response_tx.send(resp).is_err() {
+                                log::warn!("Failed to send response to receiver");
+                                break 'service;
+                            }
+                        }
+                        Err(e) => {
+                            log::error!("Failed to decode response: {:#}", e);
                         }
-                    }
-                    Err(e) => {
-                        log::error!("Failed to decode response: {:?}", e);
                     }
                 }
                 reader.submit(RequestBuffer::reuse(res.data, transfer_size))
@@ -98,5 +113,11 @@ pub async fn start_usb_cdc_service(
         }
     }
 
+    // Cancel in-flight transfers before the interfaces are released on drop.
+    reader.cancel_all();
+    writer.cancel_all();
+    drop(intr_data);
+    drop(intr_comm);
+
     log::info!("USB CDC service stopped");
 }
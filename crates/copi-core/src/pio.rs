@@ -0,0 +1,35 @@
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow};
+use pio_core::{PioVersion, ProgramWithDefines};
+use pio_parser::Parser as PioParser;
+
+use crate::generated::PioLoadProgram;
+
+/// Maximum number of instructions in one PIO instruction memory.
+pub const PIO_PROGRAM_SIZE: usize = 32;
+
+/// Assemble PIO source into a `PioLoadProgram` message.
+///
+/// The assembled instructions are sent as lowercase hex, four characters per
+/// 16-bit instruction word, and `program_len` is the number of instructions.
+pub fn load_program_message(pio_num: u32, source: &str) -> Result<PioLoadProgram> {
+    let parsed: ProgramWithDefines<HashMap<String, i32>, PIO_PROGRAM_SIZE> =
+        PioParser::parse_program(source)
+            .map_err(|e| anyhow!("Failed to parse PIO program: {:?}", e))?;
+    let program = parsed.program;
+
+    let code: String = program.code.iter().map(|i| format!("{:04x}", i)).collect();
+    Ok(PioLoadProgram {
+        pio_num,
+        program: code,
+        program_len: program.code.len() as u32,
+        origin: program.origin.map(u32::from),
+        wrap_source: program.wrap.source as u32,
+        wrap_target: program.wrap.target as u32,
+        side_set_opt: program.side_set.optional(),
+        side_set_bits: program.side_set.bits() as u32,
+        side_set_pindirs: program.side_set.pindirs(),
+        pio_version_v0: program.version == PioVersion::V0,
+    })
+}
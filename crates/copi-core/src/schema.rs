@@ -0,0 +1,198 @@
+//! Request message and field names from the compiled protobuf descriptors,
+//! used to complete and validate requests typed by hand.
+
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result, bail};
+use prost::Message as _;
+use prost_types::{
+    DescriptorProto, FieldDescriptorProto, FileDescriptorSet, field_descriptor_proto::Type,
+};
+
+use crate::generated::RequestBody;
+
+const FILE_DESCRIPTOR_SET: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/copi_descriptor.bin"));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    Bool,
+    Unsigned,
+    Signed,
+    String,
+    Bytes,
+    Other,
+}
+
+#[derive(Debug, Clone)]
+pub struct FieldSchema {
+    /// Name as used in JSON (camelCase).
+    pub name: String,
+    pub kind: FieldKind,
+    pub optional: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct MessageSchema {
+    /// Name of the `RequestBody.message` variant as used in JSON (camelCase).
+    pub name: String,
+    /// Field number of the variant in `RequestBody`.
+    pub tag: u32,
+    pub fields: Vec<FieldSchema>,
+}
+
+impl MessageSchema {
+    pub fn field(&self, name: &str) -> Option<&FieldSchema> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+}
+
+/// Every message that can be sent in a `RequestBody`, in tag order.
+pub fn request_messages() -> &'static [MessageSchema] {
+    static MESSAGES: OnceLock<Vec<MessageSchema>> = OnceLock::new();
+    MESSAGES.get_or_init(|| load_request_messages().expect("Invalid file descriptor set"))
+}
+
+pub fn request_message(name: &str) -> Option<&'static MessageSchema> {
+    request_messages().iter().find(|m| m.name == name)
+}
+
+/// The schema of the message `request` carries.
+pub fn message_of(request: &RequestBody) -> Option<&'static MessageSchema> {
+    let tag = request.message.as_ref()?.tag();
+    request_messages().iter().find(|m| m.tag == tag)
+}
+
+fn load_request_messages() -> Result<Vec<MessageSchema>> {
+    let set = FileDescriptorSet::decode(FILE_DESCRIPTOR_SET)?;
+    let mut all = Vec::new();
+    for file in &set.file {
+        let package = file.package();
+        for message in &file.message_type {
+            let full_name = if package.is_empty() {
+                format!(".{}", message.name())
+            } else {
+                format!(".{}.{}", package, message.name())
+            };
+            all.push((full_name, message));
+        }
+    }
+    let find = |full_name: &str| -> Option<&DescriptorProto> {
+        all.iter()
+            .find(|(name, _)| name == full_name)
+            .map(|(_, message)| *message)
+    };
+
+    let request_body = all
+        .iter()
+        .find(|(_, message)| message.name() == "RequestBody")
+        .map(|(_, message)| *message)
+        .with_context(|| "RequestBody not found")?;
+
+    let mut messages = Vec::new();
+    for field in &request_body.field {
+        if field.oneof_index.is_none() || field.r#type() != Type::Message {
+            continue;
+        }
+        let message = find(field.type_name()).with_context(|| field.type_name().to_string())?;
+        messages.push(MessageSchema {
+            name: json_name(field),
+            tag: field.number() as u32,
+            fields: message
+                .field
+                .iter()
+                .map(|f| FieldSchema {
+                    name: json_name(f),
+                    kind: field_kind(f),
+                    optional: f.proto3_optional(),
+                })
+                .collect(),
+        });
+    }
+    Ok(messages)
+}
+
+fn json_name(field: &FieldDescriptorProto) -> String {
+    // Matches `#[serde(rename_all = "camelCase")]` on the generated types.
+    let mut name = String::new();
+    let mut upper = false;
+    for c in field.name().chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            name.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            name.push(c);
+        }
+    }
+    name
+}
+
+fn field_kind(field: &FieldDescriptorProto) -> FieldKind {
+    match field.r#type() {
+        Type::Bool => FieldKind::Bool,
+        Type::Uint32 | Type::Uint64 | Type::Fixed32 | Type::Fixed64 => FieldKind::Unsigned,
+        Type::Int32
+        | Type::Int64
+        | Type::Sint32
+        | Type::Sint64
+        | Type::Sfixed32
+        | Type::Sfixed64 => FieldKind::Signed,
+        Type::String => FieldKind::String,
+        Type::Bytes => FieldKind::Bytes,
+        _ => FieldKind::Other,
+    }
+}
+
+/// Build the JSON form of a request from its message name and `key=value`
+/// fields, checking names and value types against the schema.
+pub fn parse_fields(message: &str, fields: &[(&str, &str)]) -> Result<serde_json::Value> {
+    let Some(schema) = request_message(message) else {
+        bail!(
+            "Unknown message `{}`, expected one of: {}",
+            message,
+            names(request_messages().iter().map(|m| m.name.as_str()))
+        );
+    };
+
+    let mut map = serde_json::Map::new();
+    for (key, value) in fields {
+        let Some(field) = schema.field(key) else {
+            bail!(
+                "Unknown field `{}` for {}, expected one of: {}",
+                key,
+                message,
+                names(schema.fields.iter().map(|f| f.name.as_str()))
+            );
+        };
+        let parsed = match field.kind {
+            FieldKind::Bool => match *value {
+                "true" | "1" | "high" | "on" => serde_json::Value::Bool(true),
+                "false" | "0" | "low" | "off" => serde_json::Value::Bool(false),
+                _ => bail!("`{}` expects true or false, got `{}`", key, value),
+            },
+            FieldKind::Unsigned => value
+                .parse::<u64>()
+                .map(serde_json::Value::from)
+                .with_context(|| {
+                    format!("`{}` expects an unsigned integer, got `{}`", key, value)
+                })?,
+            FieldKind::Signed => value
+                .parse::<i64>()
+                .map(serde_json::Value::from)
+                .with_context(|| format!("`{}` expects an integer, got `{}`", key, value))?,
+            // Taken as typed, even when it looks like a number.
+            FieldKind::String | FieldKind::Bytes => serde_json::Value::String(value.to_string()),
+            FieldKind::Other => serde_json::from_str(value)
+                .unwrap_or_else(|_| serde_json::Value::String(value.to_string())),
+        };
+        if map.insert(key.to_string(), parsed).is_some() {
+            bail!("Field `{}` given more than once", key);
+        }
+    }
+    Ok(serde_json::json!({ message: map }))
+}
+
+fn names<'a>(names: impl Iterator<Item = &'a str>) -> String {
+    names.collect::<Vec<_>>().join(", ")
+}
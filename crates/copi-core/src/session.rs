@@ -0,0 +1,228 @@
+//! Sessions scope device resources to a client: pins and peripherals a
+//! session sets up are released on the device when it ends, so a crashed
+//! script does not leave them allocated. Requests outside a session, or
+//! marked persistent, keep what they set up.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    generated::{PinDeinit, PioSmDeinit, PwmDeinit, RequestBody, request_body::Message},
+    lease::{Resource, random_id},
+};
+
+/// HTTP header naming the session a request belongs to.
+pub const SESSION_HEADER: &str = "x-copi-session";
+
+/// HTTP header that, set to `true`, keeps what a request sets up after its
+/// session ends.
+pub const PERSISTENT_HEADER: &str = "x-copi-persistent";
+
+/// How long an HTTP session may go without a request before it ends.
+pub const DEFAULT_SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Who a request is made for.
+#[derive(Debug, Clone, Default)]
+pub struct Caller {
+    /// Leases presented with the request.
+    pub leases: Vec<String>,
+    /// The session that owns what the request sets up.
+    pub session: Option<String>,
+    /// Keep what the request sets up when the session ends.
+    pub persistent: bool,
+}
+
+/// What `request` sets up on the device, and so must be released later.
+/// Pins come before the peripheral driving them, so releasing in reverse
+/// stops the peripheral first.
+pub fn allocated_by(request: &RequestBody) -> Vec<Resource> {
+    match &request.message {
+        Some(Message::GpioOutputInit(m)) => vec![Resource::Pin(m.pin)],
+        Some(Message::PwmInit(m)) => {
+            m.a.into_iter()
+                .chain(m.b)
+                .map(Resource::Pin)
+                .chain(std::iter::once(Resource::PwmSlice(m.slice)))
+                .collect()
+        }
+        Some(Message::PioSmInit(m)) => vec![
+            Resource::Pin(m.pin_num),
+            Resource::PioSm {
+                block: m.pio_num,
+                sm: m.sm_num,
+            },
+        ],
+        _ => Vec::new(),
+    }
+}
+
+/// What `request` releases on the device. A reset or reboot releases
+/// everything and is handled by [`SessionTable::record`].
+pub fn released_by(request: &RequestBody) -> Vec<Resource> {
+    match &request.message {
+        Some(Message::PinDeinit(m)) => vec![Resource::Pin(m.pin)],
+        Some(Message::PwmDeinit(m)) => vec![Resource::PwmSlice(m.slice)],
+        Some(Message::PioSmDeinit(m)) => vec![Resource::PioSm {
+            block: m.pio_num,
+            sm: m.sm_num,
+        }],
+        _ => Vec::new(),
+    }
+}
+
+/// The request that releases `resource`.
+pub fn release_request(resource: Resource) -> RequestBody {
+    let message = match resource {
+        Resource::Pin(pin) => Message::PinDeinit(PinDeinit { pin }),
+        Resource::PwmSlice(slice) => Message::PwmDeinit(PwmDeinit { slice }),
+        Resource::PioSm { block, sm } => Message::PioSmDeinit(PioSmDeinit {
+            pio_num: block,
+            sm_num: sm,
+        }),
+    };
+    RequestBody {
+        message: Some(message),
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Session {
+    owner: String,
+    resources: Vec<Resource>,
+    idle_timeout: Option<Duration>,
+    last_seen: Instant,
+}
+
+/// A session as reported to clients.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionInfo {
+    pub id: String,
+    pub owner: String,
+    /// What will be released when the session ends.
+    pub resources: Vec<Resource>,
+    pub idle_timeout_ms: Option<u64>,
+}
+
+/// The body of `POST /sessions`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionRequest {
+    pub owner: String,
+    pub idle_timeout_ms: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+pub struct SessionTable {
+    sessions: HashMap<String, Session>,
+}
+
+impl SessionTable {
+    /// Start a session. Without an idle timeout it lasts until closed.
+    pub fn open(&mut self, owner: &str, idle_timeout: Option<Duration>) -> SessionInfo {
+        let id = loop {
+            let id = random_id();
+            if !self.sessions.contains_key(&id) {
+                break id;
+            }
+        };
+        self.sessions.insert(
+            id.clone(),
+            Session {
+                owner: owner.to_string(),
+                resources: Vec::new(),
+                idle_timeout,
+                last_seen: Instant::now(),
+            },
+        );
+        self.info(&id).unwrap()
+    }
+
+    /// Note activity on session `id`. Returns whether it is still open.
+    pub fn touch(&mut self, id: &str) -> bool {
+        match self.sessions.get_mut(id) {
+            Some(session) => {
+                session.last_seen = Instant::now();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Track what a request the device accepted set up or released.
+    pub fn record(&mut self, request: &RequestBody, caller: &Caller) {
+        if let Some(Message::ResetAll(_) | Message::Reboot(_)) = &request.message {
+            for session in self.sessions.values_mut() {
+                session.resources.clear();
+            }
+            return;
+        }
+        let allocated = allocated_by(request);
+        let released = released_by(request);
+        if allocated.is_empty() && released.is_empty() {
+            return;
+        }
+        // Whoever held these before has lost them, one way or another.
+        for session in self.sessions.values_mut() {
+            session
+                .resources
+                .retain(|r| !allocated.contains(r) && !released.contains(r));
+        }
+        if caller.persistent {
+            return;
+        }
+        if let Some(session) = caller
+            .session
+            .as_deref()
+            .and_then(|id| self.sessions.get_mut(id))
+        {
+            session.resources.extend(allocated);
+        }
+    }
+
+    /// End session `id`, returning what it leaves to release.
+    pub fn close(&mut self, id: &str) -> Option<SessionInfo> {
+        let info = self.info(id);
+        self.sessions.remove(id);
+        info
+    }
+
+    /// End the sessions that have been idle too long.
+    pub fn close_idle(&mut self) -> Vec<SessionInfo> {
+        let now = Instant::now();
+        let idle: Vec<String> = self
+            .sessions
+            .iter()
+            .filter(|(_, s)| {
+                s.idle_timeout
+                    .is_some_and(|timeout| now.duration_since(s.last_seen) >= timeout)
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        idle.iter().filter_map(|id| self.close(id)).collect()
+    }
+
+    pub fn list(&self) -> Vec<SessionInfo> {
+        let mut sessions: Vec<SessionInfo> = self
+            .sessions
+            .keys()
+            .filter_map(|id| self.info(id))
+            .collect();
+        sessions.sort_by(|a, b| a.owner.cmp(&b.owner).then(a.id.cmp(&b.id)));
+        sessions
+    }
+
+    fn info(&self, id: &str) -> Option<SessionInfo> {
+        let session = self.sessions.get(id)?;
+        Some(SessionInfo {
+            id: id.to_string(),
+            owner: session.owner.clone(),
+            resources: session.resources.clone(),
+            idle_timeout_ms: session.idle_timeout.map(|t| t.as_millis() as u64),
+        })
+    }
+}
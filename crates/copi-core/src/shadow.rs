@@ -0,0 +1,183 @@
+//! A host-side model of what each pin is doing, built from successful
+//! requests. Roles mirror the firmware's `PinState`, plus `Unknown` for
+//! pins the host has not touched since it attached to the device.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Rejected, common_data},
+    generated::{RequestBody, ResponseBody, request_body::Message},
+};
+
+/// GPIOs available on the RP2350A.
+pub const PIN_COUNT: usize = 30;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PinRole {
+    /// Possibly configured by an earlier session; nothing is assumed.
+    #[default]
+    Unknown,
+    /// Not configured, as after a device reset.
+    None,
+    GpioInput,
+    GpioOutput,
+    PwmOut,
+    PwmIn,
+    Pio,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PinShadow {
+    pub role: PinRole,
+    /// Last known level of a GPIO pin.
+    pub level: Option<bool>,
+    pub pwm_slice: Option<u32>,
+    pub duty_percent: Option<u32>,
+    pub pio_block: Option<u32>,
+    pub pio_sm: Option<u32>,
+}
+
+impl PinShadow {
+    /// A pin known to be unconfigured.
+    fn released() -> Self {
+        Self {
+            role: PinRole::None,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceShadow {
+    pub pins: Vec<PinShadow>,
+}
+
+impl Default for DeviceShadow {
+    fn default() -> Self {
+        Self {
+            pins: vec![PinShadow::default(); PIN_COUNT],
+        }
+    }
+}
+
+impl DeviceShadow {
+    pub fn pin(&self, pin: u32) -> Option<&PinShadow> {
+        self.pins.get(pin as usize)
+    }
+
+    fn pin_mut(&mut self, pin: u32) -> Option<&mut PinShadow> {
+        self.pins.get_mut(pin as usize)
+    }
+
+    /// Refuse requests that cannot succeed in the known state, such as
+    /// setting the level of a pin that is not an output. Pins in the
+    /// `Unknown` role are left for the device to judge.
+    pub fn check(&self, request: &RequestBody) -> Result<(), Rejected> {
+        let (pin, expected, what) = match &request.message {
+            Some(Message::GpioOutputSet(m)) => (m.pin, PinRole::GpioOutput, "a GPIO output"),
+            Some(Message::GpioOutputGet(m)) => (m.pin, PinRole::GpioOutput, "a GPIO output"),
+            Some(Message::PwmSetDutyCyclePercent(m)) => (m.pin, PinRole::PwmOut, "a PWM output"),
+            _ => return Ok(()),
+        };
+        match self.pin(pin).map(|p| p.role) {
+            Some(PinRole::Unknown) | None => Ok(()),
+            Some(role) if role == expected => Ok(()),
+            Some(role) => Err(Rejected(format!(
+                "GP{} is not configured as {} (role {:?})",
+                pin, what, role
+            ))),
+        }
+    }
+
+    /// Update the model from a request the device accepted. Requests that
+    /// failed, or do not change pin state, are ignored.
+    pub fn apply(&mut self, request: &RequestBody, response: &ResponseBody) {
+        if !matches!(common_data(response), Ok(Some(_))) {
+            return;
+        }
+        let Some(message) = &request.message else {
+            return;
+        };
+        match message {
+            Message::GpioOutputInit(m) => {
+                if let Some(pin) = self.pin_mut(m.pin) {
+                    *pin = PinShadow {
+                        role: PinRole::GpioOutput,
+                        level: Some(m.value),
+                        ..Default::default()
+                    };
+                }
+            }
+            Message::GpioOutputSet(m) => {
+                if let Some(pin) = self.pin_mut(m.pin) {
+                    pin.level = Some(m.value);
+                }
+            }
+            Message::GpioOutputGet(m) => {
+                let level = common_data(response).ok().flatten().map(|data| data != 0);
+                if let Some(pin) = self.pin_mut(m.pin) {
+                    pin.level = level;
+                }
+            }
+            Message::PinDeinit(m) => {
+                if let Some(pin) = self.pin_mut(m.pin) {
+                    *pin = PinShadow::released();
+                }
+            }
+            Message::PwmDeinit(m) => {
+                for pin in &mut self.pins {
+                    if pin.pwm_slice == Some(m.slice) {
+                        *pin = PinShadow::released();
+                    }
+                }
+            }
+            Message::PioSmDeinit(m) => {
+                for pin in &mut self.pins {
+                    if pin.pio_block == Some(m.pio_num) && pin.pio_sm == Some(m.sm_num) {
+                        *pin = PinShadow::released();
+                    }
+                }
+            }
+            Message::ResetAll(_) | Message::Reboot(_) => {
+                // The one time every pin's state is known.
+                for pin in &mut self.pins {
+                    *pin = PinShadow::released();
+                }
+            }
+            Message::PwmInit(m) => {
+                // `top` may be `u32::MAX`.
+                let period = u64::from(m.top) + 1;
+                for (pin, compare) in [(m.a, m.compare_a), (m.b, m.compare_b)] {
+                    let Some(pin) = pin.and_then(|pin| self.pin_mut(pin)) else {
+                        continue;
+                    };
+                    *pin = PinShadow {
+                        role: PinRole::PwmOut,
+                        pwm_slice: Some(m.slice),
+                        duty_percent: Some((u64::from(compare).min(period) * 100 / period) as u32),
+                        ..Default::default()
+                    };
+                }
+            }
+            Message::PwmSetDutyCyclePercent(m) => {
+                if let Some(pin) = self.pin_mut(m.pin) {
+                    pin.duty_percent = Some(m.percent);
+                }
+            }
+            Message::PioSmInit(m) => {
+                if let Some(pin) = self.pin_mut(m.pin_num) {
+                    *pin = PinShadow {
+                        role: PinRole::Pio,
+                        pio_block: Some(m.pio_num),
+                        pio_sm: Some(m.sm_num),
+                        ..Default::default()
+                    };
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use copi_core::{
+    board::BoardProfile,
+    generated::{request_body::Message, *},
+};
+use serde_json::json;
+
+#[test]
+fn test_resolve_pin_names() {
+    let pico2 = BoardProfile::builtin("pico2").unwrap();
+    assert_eq!(pico2.resolve("LED").unwrap(), 25);
+    assert_eq!(pico2.resolve("led").unwrap(), 25);
+    assert_eq!(pico2.resolve("GP15").unwrap(), 15);
+    assert_eq!(pico2.resolve("gpio15").unwrap(), 15);
+    assert_eq!(pico2.resolve("15").unwrap(), 15);
+    assert!(pico2.resolve("GP23").is_err());
+    assert!(pico2.resolve("relay1").is_err());
+
+    let pico2w = BoardProfile::builtin("pico2w").unwrap();
+    assert!(pico2w.resolve("LED").is_err());
+    assert!(pico2w.resolve("GP25").is_err());
+}
+
+#[test]
+fn test_check_request() {
+    let pico2 = BoardProfile::builtin("pico2").unwrap();
+    let request = |message| RequestBody {
+        message: Some(message),
+    };
+    assert!(
+        pico2
+            .check_request(&request(Message::GpioOutputSet(GpioOutputSet {
+                pin: 25,
+                value: true
+            })))
+            .is_ok()
+    );
+    assert!(
+        pico2
+            .check_request(&request(Message::PwmInit(PwmInit {
+                slice: 3,
+                b: Some(23),
+                ..Default::default()
+            })))
+            .is_err()
+    );
+}
+
+#[test]
+fn test_resolve_json() {
+    let mut profile = BoardProfile::builtin("pico2").unwrap();
+    profile.aliases.insert("relay1".to_string(), 16);
+
+    let mut value = json!({ "message": { "gpioOutputInit": { "pin": "relay1", "value": true } } });
+    profile.resolve_json(&mut value).unwrap();
+    assert_eq!(value["message"]["gpioOutputInit"]["pin"], 16);
+
+    let mut value = json!({ "pwmInit": { "slice": 4, "a": "GP8", "b": 9 } });
+    profile.resolve_json(&mut value).unwrap();
+    assert_eq!(value["pwmInit"]["a"], 8);
+
+    let mut value = json!({ "gpioOutputGet": { "pin": "GP29" } });
+    assert!(profile.resolve_json(&mut value).is_err());
+}
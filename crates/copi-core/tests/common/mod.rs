@@ -0,0 +1,18 @@
+//! Fixtures shared by the integration tests. Each test crate uses only some
+//! of them.
+#![allow(dead_code)]
+
+use copi_core::generated::{Common, RequestBody, ResponseBody, request_body, response_body};
+
+pub fn request(message: request_body::Message) -> RequestBody {
+    RequestBody {
+        message: Some(message),
+    }
+}
+
+/// A `Common` response with the given error code and data.
+pub fn common(error: u32, data: u64) -> ResponseBody {
+    ResponseBody {
+        message: Some(response_body::Message::Common(Common { error, data })),
+    }
+}
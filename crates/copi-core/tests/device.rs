@@ -0,0 +1,102 @@
+mod common;
+
+use copi_core::{
+    device::{
+        DEVICE_NAME_MAX_LEN, DeviceReport, PROTOCOL_VERSION, check_request, crash_from_response,
+        name_from_response,
+    },
+    generated::{response_body::Message, *},
+    schema::request_message,
+};
+
+use common::request;
+
+fn info(protocol_version: u32, tags: &[u32]) -> DeviceInfo {
+    DeviceInfo {
+        firmware_version: "0.1.0".to_string(),
+        protocol_version,
+        chip_model: "RP2350A".to_string(),
+        unique_id: "E66138935F2B2A2C".to_string(),
+        supported_messages: tags.iter().fold(0, |mask, tag| mask | 1 << tag),
+    }
+}
+
+#[test]
+fn test_reports_unsupported_messages() {
+    let gpio_init = request_message("gpioOutputInit").unwrap();
+    let pwm_init = request_message("pwmInit").unwrap();
+    let report = DeviceReport::from_info(info(PROTOCOL_VERSION, &[gpio_init.tag]));
+    assert!(report.mismatch.is_none());
+    assert!(report.unsupported_messages.contains(&pwm_init.name));
+    assert!(!report.unsupported_messages.contains(&gpio_init.name));
+}
+
+#[test]
+fn test_refuses_unsupported_messages() {
+    let gpio_init = request_message("gpioOutputInit").unwrap();
+    let report = DeviceReport::from_info(info(PROTOCOL_VERSION, &[gpio_init.tag]));
+    let pwm = request(request_body::Message::PwmInit(PwmInit::default()));
+    let rejected = report.check_request(&pwm).unwrap_err();
+    assert!(rejected.0.contains("pwmInit"));
+    let gpio = request(request_body::Message::GpioOutputInit(GpioOutputInit {
+        pin: 25,
+        value: true,
+    }));
+    assert!(report.check_request(&gpio).is_ok());
+    // Nothing is refused before the device has been asked.
+    assert!(DeviceReport::default().check_request(&pwm).is_ok());
+}
+
+#[test]
+fn test_reports_protocol_mismatch() {
+    let report = DeviceReport::from_info(info(PROTOCOL_VERSION + 1, &[]));
+    assert!(report.mismatch.unwrap().contains("protocol version"));
+
+    // Anything but `DeviceInfo`.
+    let response = ResponseBody {
+        message: Some(Message::Common(Common { error: 0, data: 0 })),
+    };
+    let report = DeviceReport::from_response(&response);
+    assert!(report.info.is_none());
+    assert!(report.mismatch.is_some());
+}
+
+#[test]
+fn test_checks_device_names() {
+    let set_name = |name: &str| RequestBody {
+        message: Some(request_body::Message::SetDeviceName(SetDeviceName {
+            name: name.to_string(),
+        })),
+    };
+    assert!(check_request(&set_name("bench-left")).is_ok());
+    assert!(check_request(&set_name("")).is_ok());
+    assert!(check_request(&set_name(&"x".repeat(DEVICE_NAME_MAX_LEN + 1))).is_err());
+    assert!(check_request(&set_name("bench\nleft")).is_err());
+
+    let response = |name: &str| ResponseBody {
+        message: Some(Message::DeviceName(DeviceName {
+            name: name.to_string(),
+        })),
+    };
+    assert_eq!(
+        name_from_response(&response("bench-left")).as_deref(),
+        Some("bench-left")
+    );
+    assert_eq!(name_from_response(&response("")), None);
+}
+
+#[test]
+fn test_reads_crash_reports() {
+    let response = |message: &str, location: &str| ResponseBody {
+        message: Some(Message::CrashReport(CrashReport {
+            message: message.to_string(),
+            location: location.to_string(),
+        })),
+    };
+    let crash = crash_from_response(&response("boom", "main.rs:42")).unwrap();
+    assert_eq!(crash.message, "boom");
+    assert_eq!(crash.location, "main.rs:42");
+    // A defmt panic only leaves its location.
+    assert!(crash_from_response(&response("", "main.rs:42")).is_some());
+    assert_eq!(crash_from_response(&response("", "")), None);
+}
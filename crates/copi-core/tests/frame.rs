@@ -0,0 +1,49 @@
+mod common;
+
+use copi_core::{
+    frame::{FrameBuffer, MAX_FRAME_LEN},
+    generated::*,
+};
+use prost::Message as _;
+
+use common::common;
+
+fn response(request_id: u32, data: u64) -> CopiResponse {
+    CopiResponse {
+        request_id,
+        payload: Some(common(0, data)),
+    }
+}
+
+#[test]
+fn test_splits_and_joins_reads() {
+    let mut stream = response(0, 7).encode_length_delimited_to_vec();
+    stream.extend(response(1, 8).encode_length_delimited_to_vec());
+
+    let mut frames = FrameBuffer::new();
+    // A message split across reads waits for the rest.
+    let (head, tail) = stream.split_at(3);
+    frames.extend(head);
+    assert!(frames.next_message::<CopiResponse>().is_none());
+    // Two messages in one read come out one at a time.
+    frames.extend(tail);
+    let first = frames.next_message::<CopiResponse>().unwrap().unwrap();
+    assert_eq!(first, response(0, 7));
+    let second = frames.next_message::<CopiResponse>().unwrap().unwrap();
+    assert_eq!(second, response(1, 8));
+    assert!(frames.next_message::<CopiResponse>().is_none());
+}
+
+#[test]
+fn test_drops_oversized_messages() {
+    let mut frames = FrameBuffer::new();
+    // A length of MAX_FRAME_LEN + 1, as a varint.
+    let len = MAX_FRAME_LEN as u16 + 1;
+    frames.extend(&[(len & 0x7f) as u8 | 0x80, (len >> 7) as u8]);
+    assert!(frames.next_message::<CopiResponse>().unwrap().is_err());
+
+    // The stream starts over with the next message.
+    frames.extend(&response(2, 9).encode_length_delimited_to_vec());
+    let next = frames.next_message::<CopiResponse>().unwrap().unwrap();
+    assert_eq!(next, response(2, 9));
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use std::time::Duration;
+
+use copi_core::{
+    board::BoardProfile,
+    generated::{request_body::Message, *},
+    lease::{LeaseTable, Resource},
+};
+
+use common::request;
+
+#[test]
+fn test_parse_resources() {
+    let board = BoardProfile::default();
+    assert_eq!(Resource::parse("LED", &board).unwrap(), Resource::Pin(25));
+    assert_eq!(
+        Resource::parse("pwm7", &board).unwrap(),
+        Resource::PwmSlice(7)
+    );
+    assert_eq!(
+        Resource::parse("PIO1.SM3", &board).unwrap(),
+        Resource::PioSm { block: 1, sm: 3 }
+    );
+    assert!(Resource::parse("pwm8", &board).is_err());
+    assert!(Resource::parse("pio0.sm4", &board).is_err());
+    assert_eq!("GP25".parse::<Resource>().unwrap(), Resource::Pin(25));
+}
+
+#[test]
+fn test_leases_guard_resources() {
+    let mut table = LeaseTable::default();
+    let lease = table
+        .acquire("fan", vec![Resource::PwmSlice(7)], Duration::from_secs(60))
+        .unwrap();
+    assert!(
+        table
+            .acquire(
+                "other",
+                vec![Resource::PwmSlice(7)],
+                Duration::from_secs(60)
+            )
+            .is_err()
+    );
+
+    // GP15 drives slice 7B.
+    let duty = request(Message::PwmSetDutyCyclePercent(PwmSetDutyCyclePercent {
+        pin: 15,
+        percent: 30,
+    }));
+    assert!(table.check(&duty, &[]).is_err());
+    assert!(table.check(&duty, std::slice::from_ref(&lease.id)).is_ok());
+
+    let other = request(Message::GpioOutputInit(GpioOutputInit {
+        pin: 2,
+        value: false,
+    }));
+    assert!(table.check(&other, &[]).is_ok());
+
+    // Rebooting releases everything, leased or not.
+    let reboot = request(Message::Reboot(Reboot { bootsel: true }));
+    assert!(table.check(&reboot, &[]).is_err());
+    assert!(table.check(&reboot, std::slice::from_ref(&lease.id)).is_ok());
+
+    assert!(table.release(&lease.id).is_some());
+    assert!(table.check(&duty, &[]).is_ok());
+}
+
+#[test]
+fn test_leases_expire() {
+    let mut table = LeaseTable::default();
+    let lease = table
+        .acquire("blink", vec![Resource::Pin(25)], Duration::ZERO)
+        .unwrap();
+    assert!(!table.is_held(&lease.id));
+    let set = request(Message::GpioOutputSet(GpioOutputSet {
+        pin: 25,
+        value: true,
+    }));
+    assert!(table.check(&set, &[]).is_ok());
+}
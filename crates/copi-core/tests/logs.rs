@@ -0,0 +1,45 @@
+use copi_core::{
+    events::Event,
+    generated::DeviceLog,
+    logs::{LOG_BUFFER_CAPACITY, LogBuffer, LogEntry, LogLevel},
+};
+
+fn entry(message: &str) -> LogEntry {
+    LogEntry::from_proto(&DeviceLog {
+        level: 2,
+        module: "peripherals".to_string(),
+        message: message.to_string(),
+    })
+}
+
+#[test]
+fn test_buffer_keeps_the_latest_lines() {
+    let mut buffer = LogBuffer::default();
+    for i in 0..LOG_BUFFER_CAPACITY + 2 {
+        buffer.push(entry(&i.to_string()));
+    }
+    let entries = buffer.entries();
+    assert_eq!(entries.len(), LOG_BUFFER_CAPACITY);
+    assert_eq!(entries[0].message, "2");
+    assert_eq!(
+        entries.last().unwrap().message,
+        (LOG_BUFFER_CAPACITY + 1).to_string()
+    );
+}
+
+#[test]
+fn test_log_event_json() {
+    let entry = entry("Reboot failed: -4");
+    assert_eq!(entry.level, LogLevel::Warn);
+    assert_eq!(LogLevel::from_proto(0), LogLevel::Info);
+    assert_eq!(log::Level::from(LogLevel::Error), log::Level::Error);
+
+    let json = serde_json::to_value(Event::Log(entry.clone())).unwrap();
+    assert_eq!(json["type"], "log");
+    assert_eq!(json["level"], "warn");
+    assert_eq!(json["module"], "peripherals");
+    let Event::Log(parsed) = serde_json::from_value(json).unwrap() else {
+        panic!("not a log event");
+    };
+    assert_eq!(parsed, entry);
+}
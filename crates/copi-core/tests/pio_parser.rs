@@ -24,3 +24,22 @@ fn test_pio_program() {
         Parser::parse_program(program).unwrap();
     println!("{:?}", program_parsed.program.code);
 }
+
+#[test]
+fn test_load_program_message() {
+    let msg = copi_core::pio::load_program_message(
+        1,
+        "
+            .wrap_target
+                set pins, 1
+                set pins, 0
+            .wrap
+        ",
+    )
+    .unwrap();
+    assert_eq!(msg.pio_num, 1);
+    assert_eq!(msg.program_len, 2);
+    assert_eq!(msg.program.len(), 8);
+    assert_eq!(msg.wrap_target, 0);
+    assert_eq!(msg.wrap_source, 1);
+}
@@ -0,0 +1,49 @@
+use copi_core::{
+    generated::{PioSmInit, RequestBody, request_body::Message},
+    schema::{FieldKind, message_of, parse_fields, request_message},
+};
+
+#[test]
+fn test_request_schema() {
+    let schema = request_message("gpioOutputInit").unwrap();
+    let pin = schema.field("pin").unwrap();
+    assert_eq!(pin.kind, FieldKind::Unsigned);
+    assert_eq!(schema.field("value").unwrap().kind, FieldKind::Bool);
+    assert!(request_message("pwmInit").unwrap().field("a").unwrap().optional);
+}
+
+#[test]
+fn test_parse_fields() {
+    let value = parse_fields("gpioOutputSet", &[("pin", "25"), ("value", "high")]).unwrap();
+    assert_eq!(
+        value,
+        serde_json::json!({ "gpioOutputSet": { "pin": 25, "value": true } })
+    );
+    assert!(parse_fields("gpioOutputSet", &[("pin", "-1")]).is_err());
+    assert!(parse_fields("gpioOutputSet", &[("state", "true")]).is_err());
+    assert!(parse_fields("gpioOutputSett", &[]).is_err());
+}
+
+#[test]
+fn test_parse_string_fields() {
+    // A name that looks like a number stays a string.
+    let value = parse_fields("setDeviceName", &[("name", "42")]).unwrap();
+    assert_eq!(
+        value,
+        serde_json::json!({ "setDeviceName": { "name": "42" } })
+    );
+    let value = parse_fields("setDeviceName", &[("name", "\"bench\"")]).unwrap();
+    assert_eq!(
+        value,
+        serde_json::json!({ "setDeviceName": { "name": "\"bench\"" } })
+    );
+}
+
+#[test]
+fn test_message_of() {
+    let request = RequestBody {
+        message: Some(Message::PioSmInit(PioSmInit::default())),
+    };
+    assert_eq!(message_of(&request).unwrap().name, "pioSmInit");
+    assert!(message_of(&RequestBody::default()).is_none());
+}
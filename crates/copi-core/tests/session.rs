@@ -0,0 +1,102 @@
+mod common;
+
+use std::time::Duration;
+
+use copi_core::{
+    generated::{request_body::Message, *},
+    lease::Resource,
+    session::{Caller, SessionTable, release_request},
+};
+
+use common::request;
+
+fn output_init(pin: u32) -> RequestBody {
+    request(Message::GpioOutputInit(GpioOutputInit { pin, value: true }))
+}
+
+fn caller(session: &str) -> Caller {
+    Caller {
+        session: Some(session.to_string()),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_session_tracks_what_it_sets_up() {
+    let mut table = SessionTable::default();
+    let session = table.open("script", None);
+
+    table.record(&output_init(25), &caller(&session.id));
+    table.record(
+        &output_init(15),
+        &Caller {
+            persistent: true,
+            ..caller(&session.id)
+        },
+    );
+    // Outside any session.
+    table.record(&output_init(2), &Caller::default());
+
+    let closed = table.close(&session.id).unwrap();
+    assert_eq!(closed.resources, vec![Resource::Pin(25)]);
+    assert!(table.close(&session.id).is_none());
+    assert!(!table.touch(&session.id));
+
+    let release = release_request(Resource::Pin(25));
+    assert_eq!(
+        release.message,
+        Some(Message::PinDeinit(PinDeinit { pin: 25 }))
+    );
+}
+
+#[test]
+fn test_released_pins_leave_the_session() {
+    let mut table = SessionTable::default();
+    let session = table.open("script", None);
+    table.record(&output_init(25), &caller(&session.id));
+    table.record(
+        &request(Message::PinDeinit(PinDeinit { pin: 25 })),
+        &Caller::default(),
+    );
+    assert!(table.close(&session.id).unwrap().resources.is_empty());
+}
+
+#[test]
+fn test_peripherals_release_before_their_pins() {
+    let mut table = SessionTable::default();
+    let session = table.open("script", None);
+    let pwm = request(Message::PwmInit(PwmInit {
+        slice: 7,
+        b: Some(15),
+        top: 999,
+        ..Default::default()
+    }));
+    table.record(&pwm, &caller(&session.id));
+    let closed = table.close(&session.id).unwrap();
+    let order: Vec<Resource> = closed.resources.into_iter().rev().collect();
+    assert_eq!(order, vec![Resource::PwmSlice(7), Resource::Pin(15)]);
+    assert_eq!(
+        release_request(order[0]).message,
+        Some(Message::PwmDeinit(PwmDeinit { slice: 7 }))
+    );
+}
+
+#[test]
+fn test_reset_empties_every_session() {
+    let mut table = SessionTable::default();
+    let session = table.open("script", None);
+    table.record(&output_init(25), &caller(&session.id));
+    table.record(&request(Message::ResetAll(ResetAll {})), &Caller::default());
+    assert!(table.close(&session.id).unwrap().resources.is_empty());
+}
+
+#[test]
+fn test_idle_sessions_close() {
+    let mut table = SessionTable::default();
+    let idle = table.open("http", Some(Duration::ZERO));
+    let socket = table.open("websocket", None);
+    let closed = table.close_idle();
+    assert_eq!(closed.len(), 1);
+    assert_eq!(closed[0].id, idle.id);
+    assert!(table.touch(&socket.id));
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use copi_core::{
+    generated::{request_body::Message, *},
+    shadow::{DeviceShadow, PinRole},
+};
+
+use common::{common, request};
+
+#[test]
+fn test_shadow_tracks_gpio_output() {
+    let mut shadow = DeviceShadow::default();
+    shadow.apply(
+        &request(Message::GpioOutputInit(GpioOutputInit {
+            pin: 25,
+            value: true,
+        })),
+        &common(0, 1),
+    );
+    assert_eq!(shadow.pin(25).unwrap().role, PinRole::GpioOutput);
+    assert_eq!(shadow.pin(25).unwrap().level, Some(true));
+
+    // Rejected requests leave the model alone.
+    shadow.apply(
+        &request(Message::GpioOutputSet(GpioOutputSet {
+            pin: 25,
+            value: false,
+        })),
+        &common(ResponseCommonErrorCode::WrongPinState as u32, 0),
+    );
+    assert_eq!(shadow.pin(25).unwrap().level, Some(true));
+}
+
+#[test]
+fn test_shadow_tracks_pwm_duty() {
+    let mut shadow = DeviceShadow::default();
+    shadow.apply(
+        &request(Message::PwmInit(PwmInit {
+            slice: 7,
+            a: None,
+            b: Some(15),
+            divider: 3,
+            compare_a: 0,
+            compare_b: 15_000,
+            top: 49_999,
+        })),
+        &common(0, 0),
+    );
+    let pin = shadow.pin(15).unwrap();
+    assert_eq!(pin.role, PinRole::PwmOut);
+    assert_eq!(pin.duty_percent, Some(30));
+
+    shadow.apply(
+        &request(Message::PwmInit(PwmInit {
+            slice: 7,
+            b: Some(15),
+            compare_b: u32::MAX,
+            top: u32::MAX,
+            ..Default::default()
+        })),
+        &common(0, 0),
+    );
+    assert_eq!(shadow.pin(15).unwrap().duty_percent, Some(99));
+}
+
+#[test]
+fn test_shadow_rejects_invalid_requests() {
+    let mut shadow = DeviceShadow::default();
+    let set = request(Message::GpioOutputSet(GpioOutputSet {
+        pin: 7,
+        value: true,
+    }));
+    // Nothing is known about a pin the host has not configured.
+    assert!(shadow.check(&set).is_ok());
+
+    shadow.apply(
+        &request(Message::PwmInit(PwmInit {
+            slice: 3,
+            b: Some(7),
+            top: 99,
+            compare_b: 50,
+            ..Default::default()
+        })),
+        &common(0, 0),
+    );
+    assert!(shadow.check(&set).is_err());
+    let duty = request(Message::PwmSetDutyCyclePercent(PwmSetDutyCyclePercent {
+        pin: 7,
+        percent: 10,
+    }));
+    assert!(shadow.check(&duty).is_ok());
+}
+
+#[test]
+fn test_shadow_tracks_release() {
+    let mut shadow = DeviceShadow::default();
+    shadow.apply(
+        &request(Message::PwmInit(PwmInit {
+            slice: 7,
+            b: Some(15),
+            top: 999,
+            ..Default::default()
+        })),
+        &common(0, 0),
+    );
+    shadow.apply(
+        &request(Message::PwmDeinit(PwmDeinit { slice: 7 })),
+        &common(0, 0),
+    );
+    assert_eq!(shadow.pin(15).unwrap().role, PinRole::None);
+    assert_eq!(shadow.pin(14).unwrap().role, PinRole::Unknown);
+
+    shadow.apply(&request(Message::ResetAll(ResetAll {})), &common(0, 0));
+    assert!(shadow.pins.iter().all(|pin| pin.role == PinRole::None));
+}
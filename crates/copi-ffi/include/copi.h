@@ -0,0 +1,215 @@
+#ifndef COPI_H
+#define COPI_H
+
+/* Generated by cbindgen from crates/copi-ffi. Do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum CopiStatus {
+  COPI_STATUS_OK = 0,
+  COPI_STATUS_INVALID_ARGUMENT = -1,
+  COPI_STATUS_NOT_FOUND = -2,
+  COPI_STATUS_IO = -3,
+  COPI_STATUS_DECODE = -4,
+  COPI_STATUS_BUFFER_TOO_SMALL = -5,
+  // The device answered with a non-zero error code, see
+  // [`copi_last_device_error`].
+  COPI_STATUS_DEVICE = -6,
+  COPI_STATUS_PANIC = -7,
+} CopiStatus;
+
+// Opaque connection handle.
+typedef struct CopiHandle CopiHandle;
+
+// Receives each event as a NUL-terminated JSON string, valid only for the
+// duration of the call.
+typedef void (*CopiEventCallback)(const char *event_json, void *user_data);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Description of the last error on this thread, empty after a success.
+//
+// The pointer stays valid until the next `copi_*` call on this thread.
+const char *copi_last_error(void);
+
+// Device error code (`ResponseCommonErrorCode`) of the last
+// `COPI_STATUS_DEVICE` failure on this thread.
+uint32_t copi_last_device_error(void);
+
+// Open a device's serial port directly. `port` may be NULL to use the
+// first Copi device found.
+//
+// # Safety
+// `port` must be NULL or a NUL-terminated string, `out` a valid pointer.
+enum CopiStatus copi_open_serial(const char *port, struct CopiHandle **out);
+
+// Connect through a running daemon. `url` may be NULL for
+// `http://127.0.0.1:8899`.
+//
+// # Safety
+// `url` must be NULL or a NUL-terminated string, `out` a valid pointer.
+enum CopiStatus copi_connect_daemon(const char *url, struct CopiHandle **out);
+
+// Disconnect and free the handle. NULL is ignored.
+//
+// # Safety
+// `handle` must come from `copi_open_serial`/`copi_connect_daemon` and not
+// be used afterwards.
+void copi_close(struct CopiHandle *handle);
+
+// Send an encoded `RequestBody` and write the encoded `ResponseBody` to
+// `response`. On `COPI_STATUS_BUFFER_TOO_SMALL`, `response_len` holds the
+// required size.
+//
+// # Safety
+// `request` must point to `request_len` readable bytes, `response` to
+// `response_cap` writable bytes and `response_len` must be valid.
+enum CopiStatus copi_query_raw(const struct CopiHandle *handle,
+                               const uint8_t *request,
+                               uintptr_t request_len,
+                               uint8_t *response,
+                               uintptr_t response_cap,
+                               uintptr_t *response_len);
+
+//
+// # Safety
+// `handle` must be valid; `data` may be NULL.
+enum CopiStatus copi_gpio_output_init(const struct CopiHandle *handle,
+                                      uint32_t pin,
+                                      bool value,
+                                      uint64_t *data);
+
+//
+// # Safety
+// `handle` must be valid; `data` may be NULL.
+enum CopiStatus copi_gpio_output_set(const struct CopiHandle *handle,
+                                     uint32_t pin,
+                                     bool value,
+                                     uint64_t *data);
+
+//
+// # Safety
+// `handle` must be valid; `data` may be NULL.
+enum CopiStatus copi_gpio_output_get(const struct CopiHandle *handle, uint32_t pin, uint64_t *data);
+
+// `a`/`b` are GPIO numbers for the slice's outputs, or -1 if unused.
+//
+// # Safety
+// `handle` must be valid; `data` may be NULL.
+enum CopiStatus copi_pwm_init(const struct CopiHandle *handle,
+                              uint32_t slice,
+                              int32_t a,
+                              int32_t b,
+                              uint32_t divider,
+                              uint32_t compare_a,
+                              uint32_t compare_b,
+                              uint32_t top,
+                              uint64_t *data);
+
+//
+// # Safety
+// `handle` must be valid; `data` may be NULL.
+enum CopiStatus copi_pwm_set_duty_cycle_percent(const struct CopiHandle *handle,
+                                                uint32_t pin,
+                                                uint32_t percent,
+                                                uint64_t *data);
+
+// Assemble the NUL-terminated PIO source `program` and load it.
+//
+// # Safety
+// `handle` must be valid; `data` may be NULL.
+enum CopiStatus copi_pio_load_program(const struct CopiHandle *handle,
+                                      uint32_t pio_num,
+                                      const char *program,
+                                      uint64_t *data);
+
+//
+// # Safety
+// `handle` must be valid; `data` may be NULL.
+enum CopiStatus copi_pio_sm_init(const struct CopiHandle *handle,
+                                 uint32_t pio_num,
+                                 uint32_t sm_num,
+                                 uint32_t pin_num,
+                                 uint64_t *data);
+
+//
+// # Safety
+// `handle` must be valid; `data` may be NULL.
+enum CopiStatus copi_pio_sm_set_enable(const struct CopiHandle *handle,
+                                       uint32_t pio_num,
+                                       uint32_t sm_num,
+                                       bool enable,
+                                       uint64_t *data);
+
+//
+// # Safety
+// `handle` must be valid; `data` may be NULL.
+enum CopiStatus copi_pio_sm_push(const struct CopiHandle *handle,
+                                 uint32_t pio_num,
+                                 uint32_t sm_num,
+                                 uint32_t instr,
+                                 uint64_t *data);
+
+//
+// # Safety
+// `handle` must be valid; `data` may be NULL.
+enum CopiStatus copi_pio_sm_exec_instr(const struct CopiHandle *handle,
+                                       uint32_t pio_num,
+                                       uint32_t sm_num,
+                                       uint32_t exec_instr,
+                                       uint64_t *data);
+
+// Return a GPIO output pin to its unconfigured state.
+//
+// # Safety
+// `handle` must be valid; `data` may be NULL.
+enum CopiStatus copi_pin_deinit(const struct CopiHandle *handle, uint32_t pin, uint64_t *data);
+
+// Stop a PWM slice and free its pins.
+//
+// # Safety
+// `handle` must be valid; `data` may be NULL.
+enum CopiStatus copi_pwm_deinit(const struct CopiHandle *handle, uint32_t slice, uint64_t *data);
+
+// Stop a state machine and free its pin.
+//
+// # Safety
+// `handle` must be valid; `data` may be NULL.
+enum CopiStatus copi_pio_sm_deinit(const struct CopiHandle *handle,
+                                   uint32_t pio_num,
+                                   uint32_t sm_num,
+                                   uint64_t *data);
+
+// Release every pin and peripheral on the device.
+//
+// # Safety
+// `handle` must be valid; `data` may be NULL.
+enum CopiStatus copi_reset_all(const struct CopiHandle *handle, uint64_t *data);
+
+// Reboot the device, into the USB bootloader if `bootsel` is set.
+//
+// # Safety
+// `handle` must be valid; `data` may be NULL.
+enum CopiStatus copi_reboot(const struct CopiHandle *handle, bool bootsel, uint64_t *data);
+
+// Register `callback` for device events, replacing any previous one. A NULL
+// callback unregisters. The callback runs on a thread of its own and may call
+// other `copi_*` functions, but no other event is delivered until it returns.
+//
+// # Safety
+// `handle` must be valid and `user_data` usable from another thread until
+// the callback is replaced or the handle closed.
+enum CopiStatus copi_set_event_callback(const struct CopiHandle *handle,
+                                        CopiEventCallback callback,
+                                        void *user_data);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* COPI_H */
@@ -0,0 +1,473 @@
+//! C ABI for driving Copi devices, see `include/copi.h`.
+//!
+//! Every function returns a [`CopiStatus`]; on failure a description is
+//! available from [`copi_last_error`] on the same thread.
+
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString, c_char, c_void},
+    panic::{AssertUnwindSafe, catch_unwind},
+    ptr, slice,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    thread,
+};
+
+use copi_core::{
+    client::{Client, DEFAULT_DAEMON_URL, EventStream},
+    error::common_data,
+    generated::{request_body::Message, *},
+};
+use once_cell::sync::Lazy;
+use prost::Message as _;
+use tokio::{runtime::Runtime, task::JoinHandle};
+
+static G_TOKIO_RUNTIME: Lazy<Runtime> =
+    Lazy::new(|| Runtime::new().expect("Failed to create Tokio runtime"));
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+    static LAST_DEVICE_ERROR: RefCell<u32> = const { RefCell::new(0) };
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopiStatus {
+    Ok = 0,
+    InvalidArgument = -1,
+    NotFound = -2,
+    Io = -3,
+    Decode = -4,
+    BufferTooSmall = -5,
+    /// The device answered with a non-zero error code, see
+    /// [`copi_last_device_error`].
+    Device = -6,
+    Panic = -7,
+}
+
+struct FfiError {
+    status: CopiStatus,
+    message: String,
+}
+
+impl FfiError {
+    fn new(status: CopiStatus, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+type FfiResult<T> = Result<T, FfiError>;
+
+fn io_err(e: anyhow::Error) -> FfiError {
+    FfiError::new(CopiStatus::Io, format!("{:#}", e))
+}
+
+/// Run `f`, record any error for `copi_last_error` and turn panics into
+/// `CopiStatus::Panic` instead of unwinding across the FFI boundary.
+fn ffi_call(f: impl FnOnce() -> FfiResult<()>) -> CopiStatus {
+    let (status, message) = match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => (CopiStatus::Ok, String::new()),
+        Ok(Err(e)) => (e.status, e.message),
+        Err(_) => (CopiStatus::Panic, "Rust panic".to_string()),
+    };
+    LAST_ERROR.with(|last| {
+        *last.borrow_mut() = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    });
+    status
+}
+
+/// Opaque connection handle.
+pub struct CopiHandle {
+    client: Client,
+    events: Mutex<Option<EventDelivery>>,
+}
+
+impl CopiHandle {
+    fn new(client: Client) -> *mut CopiHandle {
+        Box::into_raw(Box::new(CopiHandle {
+            client,
+            events: Mutex::new(None),
+        }))
+    }
+
+    fn query(&self, message: Message) -> FfiResult<ResponseBody> {
+        G_TOKIO_RUNTIME
+            .block_on(self.client.query_message(message))
+            .map_err(io_err)
+    }
+
+    fn query_common(&self, message: Message, data: *mut u64) -> FfiResult<()> {
+        let res = self.query(message)?;
+        match common_data(&res) {
+            Ok(Some(value)) => {
+                if !data.is_null() {
+                    unsafe { *data = value };
+                }
+                Ok(())
+            }
+            Ok(None) => Err(FfiError::new(
+                CopiStatus::Decode,
+                format!("Unexpected response: {:?}", res),
+            )),
+            Err(e) => {
+                LAST_DEVICE_ERROR.with(|last| *last.borrow_mut() = e.code);
+                Err(FfiError::new(CopiStatus::Device, e.to_string()))
+            }
+        }
+    }
+}
+
+impl Drop for CopiHandle {
+    fn drop(&mut self) {
+        if let Some(events) = self.events.get_mut().unwrap().take() {
+            events.stop();
+        }
+    }
+}
+
+unsafe fn handle<'a>(handle: *const CopiHandle) -> FfiResult<&'a CopiHandle> {
+    unsafe { handle.as_ref() }
+        .ok_or_else(|| FfiError::new(CopiStatus::InvalidArgument, "handle is null"))
+}
+
+unsafe fn opt_str<'a>(s: *const c_char) -> FfiResult<Option<&'a str>> {
+    if s.is_null() {
+        return Ok(None);
+    }
+    unsafe { CStr::from_ptr(s) }
+        .to_str()
+        .map(Some)
+        .map_err(|_| FfiError::new(CopiStatus::InvalidArgument, "string is not UTF-8"))
+}
+
+/// Description of the last error on this thread, empty after a success.
+///
+/// The pointer stays valid until the next `copi_*` call on this thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn copi_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+/// Device error code (`ResponseCommonErrorCode`) of the last
+/// `COPI_STATUS_DEVICE` failure on this thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn copi_last_device_error() -> u32 {
+    LAST_DEVICE_ERROR.with(|last| *last.borrow())
+}
+
+/// Open a device's serial port directly. `port` may be NULL to use the
+/// first Copi device found.
+///
+/// # Safety
+/// `port` must be NULL or a NUL-terminated string, `out` a valid pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn copi_open_serial(
+    port: *const c_char,
+    out: *mut *mut CopiHandle,
+) -> CopiStatus {
+    ffi_call(|| {
+        if out.is_null() {
+            return Err(FfiError::new(CopiStatus::InvalidArgument, "out is null"));
+        }
+        let port = unsafe { opt_str(port) }?;
+        let client = {
+            let _guard = G_TOKIO_RUNTIME.enter();
+            Client::open_serial(port)
+                .map_err(|e| FfiError::new(CopiStatus::NotFound, format!("{:#}", e)))?
+        };
+        unsafe { *out = CopiHandle::new(client) };
+        Ok(())
+    })
+}
+
+/// Connect through a running daemon. `url` may be NULL for
+/// `http://127.0.0.1:8899`.
+///
+/// # Safety
+/// `url` must be NULL or a NUL-terminated string, `out` a valid pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn copi_connect_daemon(
+    url: *const c_char,
+    out: *mut *mut CopiHandle,
+) -> CopiStatus {
+    ffi_call(|| {
+        if out.is_null() {
+            return Err(FfiError::new(CopiStatus::InvalidArgument, "out is null"));
+        }
+        let url = unsafe { opt_str(url) }?.unwrap_or(DEFAULT_DAEMON_URL);
+        unsafe { *out = CopiHandle::new(Client::connect_daemon(url)) };
+        Ok(())
+    })
+}
+
+/// Disconnect and free the handle. NULL is ignored.
+///
+/// # Safety
+/// `handle` must come from `copi_open_serial`/`copi_connect_daemon` and not
+/// be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn copi_close(handle: *mut CopiHandle) {
+    if !handle.is_null() {
+        let handle = unsafe { Box::from_raw(handle) };
+        // Dropping the client may stop the USB service, which needs the runtime.
+        let _guard = G_TOKIO_RUNTIME.enter();
+        drop(handle);
+    }
+}
+
+/// Send an encoded `RequestBody` and write the encoded `ResponseBody` to
+/// `response`. On `COPI_STATUS_BUFFER_TOO_SMALL`, `response_len` holds the
+/// required size.
+///
+/// # Safety
+/// `request` must point to `request_len` readable bytes, `response` to
+/// `response_cap` writable bytes and `response_len` must be valid.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn copi_query_raw(
+    handle: *const CopiHandle,
+    request: *const u8,
+    request_len: usize,
+    response: *mut u8,
+    response_cap: usize,
+    response_len: *mut usize,
+) -> CopiStatus {
+    ffi_call(|| {
+        let handle = unsafe { self::handle(handle) }?;
+        if request.is_null() || response_len.is_null() {
+            return Err(FfiError::new(CopiStatus::InvalidArgument, "null pointer"));
+        }
+        let request = unsafe { slice::from_raw_parts(request, request_len) };
+        let body = RequestBody::decode(request)
+            .map_err(|e| FfiError::new(CopiStatus::Decode, e.to_string()))?;
+        let res = G_TOKIO_RUNTIME
+            .block_on(handle.client.query(body))
+            .map_err(io_err)?
+            .encode_to_vec();
+
+        unsafe { *response_len = res.len() };
+        if res.len() > response_cap || (response.is_null() && !res.is_empty()) {
+            return Err(FfiError::new(
+                CopiStatus::BufferTooSmall,
+                format!("response needs {} bytes", res.len()),
+            ));
+        }
+        unsafe { ptr::copy_nonoverlapping(res.as_ptr(), response, res.len()) };
+        Ok(())
+    })
+}
+
+/// Define typed calls that send one message and return `Common.data`
+/// through the optional `data` out-pointer.
+macro_rules! common_fns {
+    ($(
+        $(#[$doc:meta])*
+        fn $name:ident($($arg:ident: $ty:ty),*) => $message:expr;
+    )*) => {
+        $(
+            $(#[$doc])*
+            ///
+            /// # Safety
+            /// `handle` must be valid; `data` may be NULL.
+            #[unsafe(no_mangle)]
+            pub unsafe extern "C" fn $name(
+                handle: *const CopiHandle,
+                $($arg: $ty,)*
+                data: *mut u64,
+            ) -> CopiStatus {
+                ffi_call(|| {
+                    let handle = unsafe { self::handle(handle) }?;
+                    let message = $message;
+                    handle.query_common(message, data)
+                })
+            }
+        )*
+    };
+}
+
+common_fns! {
+    fn copi_gpio_output_init(pin: u32, value: bool) => {
+        Message::GpioOutputInit(GpioOutputInit { pin, value })
+    };
+    fn copi_gpio_output_set(pin: u32, value: bool) => {
+        Message::GpioOutputSet(GpioOutputSet { pin, value })
+    };
+    fn copi_gpio_output_get(pin: u32) => {
+        Message::GpioOutputGet(GpioOutputGet { pin })
+    };
+    /// `a`/`b` are GPIO numbers for the slice's outputs, or -1 if unused.
+    fn copi_pwm_init(
+        slice: u32,
+        a: i32,
+        b: i32,
+        divider: u32,
+        compare_a: u32,
+        compare_b: u32,
+        top: u32
+    ) => {
+        Message::PwmInit(PwmInit {
+            slice,
+            a: u32::try_from(a).ok(),
+            b: u32::try_from(b).ok(),
+            divider,
+            compare_a,
+            compare_b,
+            top,
+        })
+    };
+    fn copi_pwm_set_duty_cycle_percent(pin: u32, percent: u32) => {
+        Message::PwmSetDutyCyclePercent(PwmSetDutyCyclePercent { pin, percent })
+    };
+    /// Assemble the NUL-terminated PIO source `program` and load it.
+    fn copi_pio_load_program(pio_num: u32, program: *const c_char) => {
+        let program = unsafe { opt_str(program) }?
+            .ok_or_else(|| FfiError::new(CopiStatus::InvalidArgument, "program is null"))?;
+        Message::PioLoadProgram(
+            copi_core::pio::load_program_message(pio_num, program)
+                .map_err(|e| FfiError::new(CopiStatus::InvalidArgument, format!("{:#}", e)))?,
+        )
+    };
+    fn copi_pio_sm_init(pio_num: u32, sm_num: u32, pin_num: u32) => {
+        Message::PioSmInit(PioSmInit { pio_num, sm_num, pin_num })
+    };
+    fn copi_pio_sm_set_enable(pio_num: u32, sm_num: u32, enable: bool) => {
+        Message::PioSmSetEnable(PioSmSetEnable { pio_num, sm_num, enable })
+    };
+    fn copi_pio_sm_push(pio_num: u32, sm_num: u32, instr: u32) => {
+        Message::PioSmPush(PioSmPush { pio_num, sm_num, instr })
+    };
+    fn copi_pio_sm_exec_instr(pio_num: u32, sm_num: u32, exec_instr: u32) => {
+        Message::PioSmExecInstr(PioSmExecInstr { pio_num, sm_num, exec_instr })
+    };
+    /// Return a GPIO output pin to its unconfigured state.
+    fn copi_pin_deinit(pin: u32) => {
+        Message::PinDeinit(PinDeinit { pin })
+    };
+    /// Stop a PWM slice and free its pins.
+    fn copi_pwm_deinit(slice: u32) => {
+        Message::PwmDeinit(PwmDeinit { slice })
+    };
+    /// Stop a state machine and free its pin.
+    fn copi_pio_sm_deinit(pio_num: u32, sm_num: u32) => {
+        Message::PioSmDeinit(PioSmDeinit { pio_num, sm_num })
+    };
+    /// Release every pin and peripheral on the device.
+    fn copi_reset_all() => {
+        Message::ResetAll(ResetAll {})
+    };
+    /// Reboot the device, into the USB bootloader if `bootsel` is set.
+    fn copi_reboot(bootsel: bool) => {
+        Message::Reboot(Reboot { bootsel })
+    };
+}
+
+/// Receives each event as a NUL-terminated JSON string, valid only for the
+/// duration of the call.
+pub type CopiEventCallback =
+    Option<unsafe extern "C" fn(event_json: *const c_char, user_data: *mut c_void)>;
+
+struct UserData(*mut c_void);
+
+// The caller promises `user_data` may be used from the callback thread.
+unsafe impl Send for UserData {}
+
+/// Events are received on the runtime and handed to a thread of their own, so
+/// the callback may call back into the library.
+struct EventDelivery {
+    task: JoinHandle<()>,
+    thread: thread::JoinHandle<()>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl EventDelivery {
+    fn start(
+        mut events: EventStream,
+        callback: unsafe extern "C" fn(*const c_char, *mut c_void),
+        user_data: UserData,
+    ) -> Self {
+        let (tx, rx) = mpsc::channel::<CString>();
+        let task = G_TOKIO_RUNTIME.spawn(async move {
+            while let Some(event) = events.next().await {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(e) => {
+                        log::warn!("Event stream failed: {:?}", e);
+                        break;
+                    }
+                };
+                let json = CString::new(serde_json::to_string(&event).unwrap()).unwrap();
+                if tx.send(json).is_err() {
+                    break;
+                }
+            }
+        });
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread_stopped = stopped.clone();
+        let thread = thread::spawn(move || {
+            let user_data = user_data;
+            for json in rx {
+                if thread_stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                unsafe { callback(json.as_ptr(), user_data.0) };
+            }
+        });
+        Self {
+            task,
+            thread,
+            stopped,
+        }
+    }
+
+    /// Stop delivering and wait for a callback in progress, unless called
+    /// from that callback.
+    fn stop(self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        self.task.abort();
+        if self.thread.thread().id() != thread::current().id() {
+            let _ = self.thread.join();
+        }
+    }
+}
+
+/// Register `callback` for device events, replacing any previous one. A NULL
+/// callback unregisters. The callback runs on a thread of its own and may call
+/// other `copi_*` functions, but no other event is delivered until it returns.
+///
+/// # Safety
+/// `handle` must be valid and `user_data` usable from another thread until
+/// the callback is replaced or the handle closed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn copi_set_event_callback(
+    handle: *const CopiHandle,
+    callback: CopiEventCallback,
+    user_data: *mut c_void,
+) -> CopiStatus {
+    ffi_call(|| {
+        let handle = unsafe { self::handle(handle) }?;
+        // Not stopped under the lock: the callback being waited for may be
+        // registering another one.
+        let previous = handle.events.lock().unwrap().take();
+        if let Some(previous) = previous {
+            previous.stop();
+        }
+        let Some(callback) = callback else {
+            return Ok(());
+        };
+
+        let events = G_TOKIO_RUNTIME
+            .block_on(handle.client.events())
+            .map_err(io_err)?;
+        let delivery = EventDelivery::start(events, callback, UserData(user_data));
+        let replaced = handle.events.lock().unwrap().replace(delivery);
+        if let Some(replaced) = replaced {
+            replaced.stop();
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,117 @@
+//! Framing on the serial link between the host and the firmware. A tty does
+//! not keep USB packet boundaries, so each `CopiRequest` and `CopiResponse`
+//! goes out with its length in front as a varint, as prost's
+//! `encode_length_delimited` writes it. Kept free of hardware dependencies
+//! so both sides share it and it can be tested on the host.
+
+#![no_std]
+
+use core::ops::Range;
+
+/// Longest message either side accepts.
+pub const MAX_FRAME_LEN: usize = 256;
+
+/// Longest length prefix of a message up to [`MAX_FRAME_LEN`].
+pub const MAX_PREFIX_LEN: usize = 2;
+
+/// Room for a message and its length prefix.
+pub const FRAME_BUF_LEN: usize = MAX_FRAME_LEN + MAX_PREFIX_LEN;
+
+const PACKET_LEN: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame {
+    /// More bytes are needed.
+    Incomplete,
+    /// Where the first message sits in the data.
+    Complete(Range<usize>),
+    /// The length is past [`MAX_FRAME_LEN`], so the stream is out of step.
+    TooLong,
+}
+
+/// Find the first message in `data`, which starts with its length.
+pub fn next_frame(data: &[u8]) -> Frame {
+    let mut len = 0;
+    for (i, byte) in data.iter().enumerate().take(MAX_PREFIX_LEN) {
+        len |= ((byte & 0x7f) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            let start = i + 1;
+            if len > MAX_FRAME_LEN {
+                return Frame::TooLong;
+            }
+            if data.len() < start + len {
+                return Frame::Incomplete;
+            }
+            return Frame::Complete(start..start + len);
+        }
+    }
+    if data.len() >= MAX_PREFIX_LEN {
+        // A longer prefix reaches past MAX_FRAME_LEN already.
+        return Frame::TooLong;
+    }
+    Frame::Incomplete
+}
+
+/// Write `len` as a varint to the front of `buf` and return how many bytes
+/// it took.
+pub fn write_len(len: usize, buf: &mut [u8]) -> usize {
+    debug_assert!(len <= MAX_FRAME_LEN);
+    if len < 0x80 {
+        buf[0] = len as u8;
+        1
+    } else {
+        buf[0] = (len & 0x7f) as u8 | 0x80;
+        buf[1] = (len >> 7) as u8;
+        2
+    }
+}
+
+/// Packets read from the other side that have not made a whole message yet,
+/// in a buffer of fixed size.
+pub struct FrameReader {
+    buf: [u8; FRAME_BUF_LEN + PACKET_LEN],
+    len: usize,
+}
+
+impl Default for FrameReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameReader {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; FRAME_BUF_LEN + PACKET_LEN],
+            len: 0,
+        }
+    }
+
+    /// Where the next packet is read to. A partial message never leaves
+    /// less than a packet of room.
+    pub fn spare(&mut self) -> &mut [u8] {
+        &mut self.buf[self.len..]
+    }
+
+    pub fn advance(&mut self, n: usize) {
+        self.len += n;
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    pub fn next_frame(&self) -> Frame {
+        next_frame(self.data())
+    }
+
+    /// Drop everything up to `end`, the end of a message from [`Self::next_frame`].
+    pub fn consume(&mut self, end: usize) {
+        self.buf.copy_within(end..self.len, 0);
+        self.len -= end;
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
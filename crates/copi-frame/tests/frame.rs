@@ -0,0 +1,55 @@
+use copi_frame::{FRAME_BUF_LEN, Frame, FrameReader, MAX_FRAME_LEN, next_frame, write_len};
+
+#[test]
+fn test_finds_messages_behind_their_length() {
+    assert_eq!(next_frame(&[]), Frame::Incomplete);
+    assert_eq!(next_frame(&[3, 1, 2]), Frame::Incomplete);
+    assert_eq!(next_frame(&[3, 1, 2, 3, 4]), Frame::Complete(1..4));
+
+    let mut buf = [0; FRAME_BUF_LEN];
+    let start = write_len(MAX_FRAME_LEN, &mut buf);
+    assert_eq!(start, 2);
+    assert_eq!(next_frame(&buf[..start]), Frame::Incomplete);
+    assert_eq!(next_frame(&buf), Frame::Complete(2..FRAME_BUF_LEN));
+}
+
+#[test]
+fn test_rejects_oversized_lengths() {
+    let len = MAX_FRAME_LEN + 1;
+    assert_eq!(
+        next_frame(&[(len & 0x7f) as u8 | 0x80, (len >> 7) as u8]),
+        Frame::TooLong
+    );
+    // A third length byte is past MAX_FRAME_LEN whatever it holds.
+    assert_eq!(next_frame(&[0x80, 0x80]), Frame::TooLong);
+}
+
+#[test]
+fn test_reader_joins_packets() {
+    let mut reader = FrameReader::new();
+    let mut packet = [0; 200];
+    packet[..2].copy_from_slice(&[0xc6, 0x01]);
+    for (i, byte) in packet[2..].iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+
+    // A message of 198 bytes arrives in 64-byte packets.
+    for chunk in packet.chunks(64) {
+        assert_eq!(reader.next_frame(), Frame::Incomplete);
+        reader.spare()[..chunk.len()].copy_from_slice(chunk);
+        reader.advance(chunk.len());
+    }
+    let range = match reader.next_frame() {
+        Frame::Complete(range) => range,
+        frame => panic!("unexpected {:?}", frame),
+    };
+    assert_eq!(&reader.data()[range.clone()], &packet[2..]);
+
+    // The start of the next message stays behind.
+    reader.consume(range.end);
+    reader.spare()[..2].copy_from_slice(&[1, 7]);
+    reader.advance(2);
+    assert_eq!(reader.next_frame(), Frame::Complete(1..2));
+    reader.consume(2);
+    assert!(reader.data().is_empty());
+}
@@ -1,14 +1,63 @@
 uniffi::include_scaffolding!("export");
 
+use std::{
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
 use copi_core::AppState;
+use copi_core::generated::{CopiRequest, CopiResponse};
 use log::LevelFilter;
 use log::info;
 use once_cell::sync::Lazy;
-use tokio::runtime::Runtime;
+use tokio::{
+    runtime::Runtime,
+    sync::{
+        mpsc::{UnboundedReceiver, UnboundedSender},
+        watch,
+    },
+    task::JoinHandle,
+};
 
 static G_TOKIO_RUNTIME: Lazy<Runtime> =
     Lazy::new(|| Runtime::new().expect("Failed to create Tokio runtime"));
 
+static G_SERVICE: Lazy<Mutex<Option<Service>>> = Lazy::new(|| Mutex::new(None));
+
+const STOP_TIMEOUT: Duration = Duration::from_secs(3);
+
+struct Service {
+    state: AppState,
+    shutdown_tx: watch::Sender<bool>,
+    usb_task: JoinHandle<()>,
+    api_task: JoinHandle<()>,
+}
+
+impl Service {
+    fn is_finished(&self) -> bool {
+        self.usb_task.is_finished() || self.api_task.is_finished()
+    }
+}
+
+enum ServiceStatus {
+    Stopped,
+    Running,
+    /// The service ended on its own, e.g. the USB device was detached.
+    Exited,
+}
+
+#[derive(Debug, thiserror::Error)]
+enum ServiceError {
+    #[error("service is already running")]
+    AlreadyRunning,
+    #[error("service is not running")]
+    NotRunning,
+    #[error("device not found: {0}")]
+    DeviceNotFound(String),
+    #[error("not supported on this platform")]
+    Unsupported,
+}
+
 enum LogLevel {
     Off,
     Error,
@@ -18,9 +67,9 @@ enum LogLevel {
     Trace,
 }
 
-impl Into<LevelFilter> for LogLevel {
-    fn into(self) -> LevelFilter {
-        match self {
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
             LogLevel::Off => LevelFilter::Off,
             LogLevel::Error => LevelFilter::Error,
             LogLevel::Warn => LevelFilter::Warn,
@@ -35,30 +84,216 @@ fn version() -> String {
     env!("CARGO_PKG_VERSION").to_string()
 }
 
-#[allow(unused_variables)]
 fn init_logger(level: LogLevel) {
     #[cfg(target_os = "android")]
     android_logger::init_once(android_logger::Config::default().with_max_level(level.into()));
+
+    #[cfg(not(target_os = "android"))]
+    let _ = env_logger::Builder::new()
+        .filter_level(level.into())
+        .try_init();
 }
 
 fn init_usb_fd(fd: i32, interface_comm: i32, interface_data: i32) {
+    if let Err(e) = start_service(fd, interface_comm, interface_data) {
+        log::warn!("Failed to start service: {}", e);
+    }
+}
+
+fn start_service(fd: i32, interface_comm: i32, interface_data: i32) -> Result<(), ServiceError> {
+    #[cfg(not(target_os = "android"))]
+    {
+        let _ = (fd, interface_comm, interface_data);
+        Err(ServiceError::Unsupported)
+    }
+
+    #[cfg(target_os = "android")]
+    {
+        info!("Connect to USB fd:{}", fd);
+        let service = claim_service()?;
+        spawn_service(service, move |request_rx, response_tx, shutdown_rx| {
+            copi_core::mobile::start_usb_cdc_service(
+                fd,
+                interface_comm,
+                interface_data,
+                request_rx,
+                response_tx,
+                shutdown_rx,
+            )
+        })
+    }
+}
+
+/// Connect through the CDC serial port, either the given one or the first
+/// device matching the Copi VID/PID.
+fn start_serial_service(port_name: Option<String>) -> Result<(), ServiceError> {
+    #[cfg(any(target_os = "macos", target_os = "linux", target_os = "windows"))]
+    {
+        // Before opening the port, which a running service holds.
+        let service = claim_service()?;
+        let port_name = match port_name {
+            Some(port_name) => port_name,
+            None => copi_core::find_copi_serial_port()
+                .map_err(|e| ServiceError::DeviceNotFound(e.to_string()))?,
+        };
+
+        info!("Connect to serial port:{}", port_name);
+        let port = {
+            let _guard = G_TOKIO_RUNTIME.enter();
+            copi_core::open_serial_port(&port_name)
+                .map_err(|e| ServiceError::DeviceNotFound(format!("{:#}", e)))?
+        };
+        spawn_service(service, move |request_rx, response_tx, shutdown_rx| {
+            copi_core::start_usb_cdc_service(port, request_rx, response_tx, shutdown_rx)
+        })
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+    {
+        let _ = port_name;
+        Err(ServiceError::Unsupported)
+    }
+}
+
+fn list_copi_ports() -> Vec<String> {
+    #[cfg(any(target_os = "macos", target_os = "linux", target_os = "windows"))]
+    {
+        copi_core::list_copi_serial_ports().unwrap_or_else(|e| {
+            log::warn!("{:?}", e);
+            Vec::new()
+        })
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+    Vec::new()
+}
+
+/// Lock the service slot, refusing if a service is still running.
+fn claim_service() -> Result<MutexGuard<'static, Option<Service>>, ServiceError> {
+    let mut service = G_SERVICE.lock().unwrap();
+    if let Some(running) = service.take() {
+        if !running.is_finished() {
+            service.replace(running);
+            return Err(ServiceError::AlreadyRunning);
+        }
+        // Tear down whatever is left of a service that exited on its own
+        // so the old interfaces and port 8899 are released first.
+        teardown(running);
+    }
+    Ok(service)
+}
+
+fn spawn_service<F, Fut>(
+    mut service: MutexGuard<'static, Option<Service>>,
+    usb_service: F,
+) -> Result<(), ServiceError>
+where
+    F: FnOnce(
+        UnboundedReceiver<CopiRequest>,
+        UnboundedSender<CopiResponse>,
+        watch::Receiver<bool>,
+    ) -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
     let (request_tx, request_rx) = tokio::sync::mpsc::unbounded_channel();
     let (response_tx, response_rx) = tokio::sync::mpsc::unbounded_channel();
 
     #[cfg(not(target_os = "android"))]
-    let state = AppState::new(request_tx, response_rx); // unreachable!();
+    let state = {
+        let _guard = G_TOKIO_RUNTIME.enter();
+        AppState::new(request_tx, response_rx)
+    };
 
     #[cfg(target_os = "android")]
     let state = AppState::new(request_tx, response_rx, &G_TOKIO_RUNTIME);
+    let api_state = state.clone();
+
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
+    let usb = usb_service(request_rx, response_tx, shutdown_rx.clone());
+    let usb_state = state.clone();
+    let usb_task = G_TOKIO_RUNTIME.spawn(async move {
+        usb_state.set_connected(true);
+        usb.await;
+        usb_state.set_connected(false);
+    });
 
-    info!("Connect to USB fd:{}", fd);
-    G_TOKIO_RUNTIME.spawn(copi_core::mobile::start_usb_cdc_service(
-        fd,
-        interface_comm,
-        interface_data,
-        request_rx,
-        response_tx,
-    ));
     info!("Start API service");
-    G_TOKIO_RUNTIME.spawn(copi_core::start_api_service(state));
+    let mut api_shutdown = shutdown_rx;
+    let api_task = G_TOKIO_RUNTIME.spawn(async move {
+        let shutdown = async move {
+            let _ = api_shutdown.wait_for(|stop| *stop).await;
+        };
+        if let Err(e) = copi_core::start_api_service_with_shutdown(api_state, shutdown).await {
+            log::error!("API service failed: {:?}", e);
+        }
+    });
+
+    service.replace(Service {
+        state,
+        shutdown_tx,
+        usb_task,
+        api_task,
+    });
+    Ok(())
+}
+
+fn stop_service() -> Result<(), ServiceError> {
+    let Some(service) = G_SERVICE.lock().unwrap().take() else {
+        return Err(ServiceError::NotRunning);
+    };
+    teardown(service);
+    Ok(())
+}
+
+fn service_status() -> ServiceStatus {
+    match G_SERVICE.lock().unwrap().as_ref() {
+        None => ServiceStatus::Stopped,
+        Some(service) if service.is_finished() => ServiceStatus::Exited,
+        Some(_) => ServiceStatus::Running,
+    }
+}
+
+fn teardown(service: Service) {
+    info!("Stop service");
+    let Service {
+        state,
+        shutdown_tx,
+        usb_task,
+        api_task,
+    } = service;
+
+    let _ = shutdown_tx.send(true);
+    state.close();
+
+    G_TOKIO_RUNTIME.block_on(async {
+        for (name, mut task) in [("USB", usb_task), ("API", api_task)] {
+            if tokio::time::timeout(STOP_TIMEOUT, &mut task).await.is_err() {
+                log::warn!("{} task did not stop in time, aborting", name);
+                task.abort();
+            }
+        }
+    });
+    info!("Service stopped");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stop_without_start() {
+        assert!(matches!(service_status(), ServiceStatus::Stopped));
+        assert!(matches!(stop_service(), Err(ServiceError::NotRunning)));
+    }
+
+    #[cfg(not(target_os = "android"))]
+    #[test]
+    fn usb_fd_is_android_only() {
+        assert!(matches!(
+            start_service(-1, 0, 1),
+            Err(ServiceError::Unsupported)
+        ));
+        assert!(matches!(service_status(), ServiceStatus::Stopped));
+    }
 }
@@ -0,0 +1,278 @@
+use std::sync::Arc;
+
+use copi_core::{
+    client::{Client, DEFAULT_DAEMON_URL, EventStream},
+    error::common_data,
+    generated::{request_body::Message, *},
+};
+use pyo3::{
+    create_exception,
+    exceptions::{PyException, PyStopAsyncIteration},
+    prelude::*,
+    types::PyDict,
+};
+use pyo3_async_runtimes::tokio::{future_into_py, get_runtime};
+
+create_exception!(copi, CopiError, PyException);
+create_exception!(copi, DeviceError, CopiError);
+
+fn copi_err(e: impl std::fmt::Display) -> PyErr {
+    CopiError::new_err(e.to_string())
+}
+
+fn json_loads(py: Python<'_>, s: &str) -> PyResult<PyObject> {
+    Ok(py.import("json")?.call_method1("loads", (s,))?.unbind())
+}
+
+async fn query_common(client: Client, message: Message) -> PyResult<u64> {
+    let res = client
+        .query_message(message)
+        .await
+        .map_err(|e| copi_err(format!("{:#}", e)))?;
+    match common_data(&res) {
+        Ok(Some(data)) => Ok(data),
+        Ok(None) => Err(copi_err(format!("Unexpected response: {:?}", res))),
+        Err(e) => Err(DeviceError::new_err((e.to_string(), e.code, e.data))),
+    }
+}
+
+async fn query_json(client: Client, request: String) -> PyResult<PyObject> {
+    let body: RequestBody = serde_json::from_str(&request).map_err(copi_err)?;
+    let res = client
+        .query(body)
+        .await
+        .map_err(|e| copi_err(format!("{:#}", e)))?;
+    let res = serde_json::to_string(&res).map_err(copi_err)?;
+    Python::with_gil(|py| json_loads(py, &res))
+}
+
+/// A connection to a Copi device, either direct or through a daemon.
+#[pyclass(frozen)]
+struct Copi {
+    client: Client,
+}
+
+#[pymethods]
+impl Copi {
+    /// Open the device's serial port directly, or the first Copi device found.
+    #[staticmethod]
+    #[pyo3(signature = (port=None))]
+    fn open(port: Option<&str>) -> PyResult<Self> {
+        let _guard = get_runtime().enter();
+        let client = Client::open_serial(port).map_err(|e| copi_err(format!("{:#}", e)))?;
+        Ok(Self { client })
+    }
+
+    /// Go through a running `copi daemon`.
+    #[staticmethod]
+    #[pyo3(signature = (url=None))]
+    fn daemon(url: Option<&str>) -> Self {
+        Self {
+            client: Client::connect_daemon(url.unwrap_or(DEFAULT_DAEMON_URL)),
+        }
+    }
+
+    /// Send any request by its JSON name, e.g.
+    /// `query("gpioOutputInit", pin=25, value=True)`, and return the
+    /// response as a dict.
+    #[pyo3(signature = (message, **fields))]
+    fn query(
+        &self,
+        py: Python<'_>,
+        message: &str,
+        fields: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<PyObject> {
+        let request = request_json(py, message, fields)?;
+        let client = self.client.clone();
+        py.allow_threads(|| get_runtime().block_on(query_json(client, request)))
+    }
+
+    #[pyo3(signature = (message, **fields))]
+    fn query_async<'py>(
+        &self,
+        py: Python<'py>,
+        message: &str,
+        fields: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let request = request_json(py, message, fields)?;
+        future_into_py(py, query_json(self.client.clone(), request))
+    }
+
+    /// Iterate over device events, with `for` or `async for`.
+    fn events(&self, py: Python<'_>) -> PyResult<Events> {
+        let client = self.client.clone();
+        let stream = py
+            .allow_threads(|| get_runtime().block_on(client.events()))
+            .map_err(|e| copi_err(format!("{:#}", e)))?;
+        Ok(Events {
+            stream: Arc::new(tokio::sync::Mutex::new(stream)),
+        })
+    }
+}
+
+fn request_json(
+    py: Python<'_>,
+    message: &str,
+    fields: Option<&Bound<'_, PyDict>>,
+) -> PyResult<String> {
+    let fields = match fields {
+        Some(fields) => py
+            .import("json")?
+            .call_method1("dumps", (fields,))?
+            .extract::<String>()?,
+        None => "{}".to_string(),
+    };
+    let fields: serde_json::Value = serde_json::from_str(&fields).map_err(copi_err)?;
+    Ok(serde_json::json!({ "message": { message: fields } }).to_string())
+}
+
+/// Define a blocking and an `_async` method per request that returns `Common`.
+macro_rules! common_methods {
+    ($(
+        $(#[$doc:meta])*
+        fn $name:ident / $async_name:ident ($($arg:ident: $ty:ty),*) => $message:expr;
+    )*) => {
+        #[pymethods]
+        impl Copi {
+            $(
+                // The arguments mirror the fields of the proto message.
+                $(#[$doc])*
+                #[allow(clippy::too_many_arguments)]
+                fn $name(&self, py: Python<'_>, $($arg: $ty),*) -> PyResult<u64> {
+                    let message = $message;
+                    let client = self.client.clone();
+                    py.allow_threads(|| get_runtime().block_on(query_common(client, message)))
+                }
+
+                #[allow(clippy::too_many_arguments)]
+                fn $async_name<'py>(
+                    &self,
+                    py: Python<'py>,
+                    $($arg: $ty),*
+                ) -> PyResult<Bound<'py, PyAny>> {
+                    let message = $message;
+                    future_into_py(py, query_common(self.client.clone(), message))
+                }
+            )*
+        }
+    };
+}
+
+common_methods! {
+    fn get_cpu_frequency / get_cpu_frequency_async() => {
+        Message::GetCpuFrequency(GetCpuFrequency {})
+    };
+    fn gpio_output_init / gpio_output_init_async(pin: u32, value: bool) => {
+        Message::GpioOutputInit(GpioOutputInit { pin, value })
+    };
+    fn gpio_output_set / gpio_output_set_async(pin: u32, value: bool) => {
+        Message::GpioOutputSet(GpioOutputSet { pin, value })
+    };
+    fn gpio_output_get / gpio_output_get_async(pin: u32) => {
+        Message::GpioOutputGet(GpioOutputGet { pin })
+    };
+    fn pwm_init / pwm_init_async(
+        slice: u32,
+        a: Option<u32>,
+        b: Option<u32>,
+        divider: u32,
+        compare_a: u32,
+        compare_b: u32,
+        top: u32
+    ) => {
+        Message::PwmInit(PwmInit { slice, a, b, divider, compare_a, compare_b, top })
+    };
+    fn pwm_set_duty_cycle_percent / pwm_set_duty_cycle_percent_async(pin: u32, percent: u32) => {
+        Message::PwmSetDutyCyclePercent(PwmSetDutyCyclePercent { pin, percent })
+    };
+    /// Assemble `program` on the host and load it into PIO block `pio_num`.
+    fn pio_load_program / pio_load_program_async(pio_num: u32, program: &str) => {
+        Message::PioLoadProgram(
+            copi_core::pio::load_program_message(pio_num, program)
+                .map_err(|e| copi_err(format!("{:#}", e)))?,
+        )
+    };
+    fn pio_sm_init / pio_sm_init_async(pio_num: u32, sm_num: u32, pin_num: u32) => {
+        Message::PioSmInit(PioSmInit { pio_num, sm_num, pin_num })
+    };
+    fn pio_sm_set_enable / pio_sm_set_enable_async(pio_num: u32, sm_num: u32, enable: bool) => {
+        Message::PioSmSetEnable(PioSmSetEnable { pio_num, sm_num, enable })
+    };
+    fn pio_sm_push / pio_sm_push_async(pio_num: u32, sm_num: u32, instr: u32) => {
+        Message::PioSmPush(PioSmPush { pio_num, sm_num, instr })
+    };
+    fn pio_sm_exec_instr / pio_sm_exec_instr_async(pio_num: u32, sm_num: u32, exec_instr: u32) => {
+        Message::PioSmExecInstr(PioSmExecInstr { pio_num, sm_num, exec_instr })
+    };
+    /// Return a GPIO output pin to its unconfigured state.
+    fn pin_deinit / pin_deinit_async(pin: u32) => {
+        Message::PinDeinit(PinDeinit { pin })
+    };
+    /// Stop a PWM slice and free its pins.
+    fn pwm_deinit / pwm_deinit_async(slice: u32) => {
+        Message::PwmDeinit(PwmDeinit { slice })
+    };
+    /// Stop a state machine and free its pin.
+    fn pio_sm_deinit / pio_sm_deinit_async(pio_num: u32, sm_num: u32) => {
+        Message::PioSmDeinit(PioSmDeinit { pio_num, sm_num })
+    };
+    /// Release every pin and peripheral on the device.
+    fn reset_all / reset_all_async() => {
+        Message::ResetAll(ResetAll {})
+    };
+    /// Reboot the device, into the USB bootloader if `bootsel` is set.
+    fn reboot / reboot_async(bootsel: bool) => {
+        Message::Reboot(Reboot { bootsel })
+    };
+}
+
+/// Device events as dicts, e.g. `{"type": "device", "body": {...}}`.
+#[pyclass]
+struct Events {
+    stream: Arc<tokio::sync::Mutex<EventStream>>,
+}
+
+#[pymethods]
+impl Events {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        let stream = self.stream.clone();
+        let event = py.allow_threads(|| get_runtime().block_on(next_event(stream)))?;
+        event.map(|event| json_loads(py, &event)).transpose()
+    }
+
+    fn __aiter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __anext__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let stream = self.stream.clone();
+        future_into_py(py, async move {
+            match next_event(stream).await? {
+                Some(event) => Python::with_gil(|py| json_loads(py, &event)),
+                None => Err(PyStopAsyncIteration::new_err(())),
+            }
+        })
+    }
+}
+
+async fn next_event(stream: Arc<tokio::sync::Mutex<EventStream>>) -> PyResult<Option<String>> {
+    match stream.lock().await.next().await {
+        Some(Ok(event)) => serde_json::to_string(&event).map(Some).map_err(copi_err),
+        Some(Err(e)) => Err(copi_err(format!("{:#}", e))),
+        None => Ok(None),
+    }
+}
+
+#[pymodule]
+fn copi(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Copi>()?;
+    m.add_class::<Events>()?;
+    m.add("CopiError", m.py().get_type::<CopiError>())?;
+    m.add("DeviceError", m.py().get_type::<DeviceError>())?;
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    Ok(())
+}
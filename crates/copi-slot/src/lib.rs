@@ -0,0 +1,63 @@
+//! Fixed-capacity storage for the drivers the firmware hands out, addressed
+//! by the index [`Slot::add`] returns. Kept free of hardware dependencies so
+//! it can be tested on the host.
+
+#![no_std]
+
+pub struct Slot<T, const N: usize> {
+    array: [Option<T>; N],
+    len: usize,
+}
+
+impl<T, const N: usize> Default for Slot<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Slot<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            array: [const { None }; N],
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Store `value` in the lowest free index and return it, or `None` if
+    /// all `N` entries are taken.
+    pub fn add(&mut self, value: T) -> Option<usize> {
+        let index = self.array.iter().position(Option::is_none)?;
+        self.array[index] = Some(value);
+        self.len += 1;
+        Some(index)
+    }
+
+    /// Take the value at `index`, freeing the index for reuse.
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        let old = self.array.get_mut(index)?.take()?;
+        self.len -= 1;
+        Some(old)
+    }
+
+    /// Drop every value.
+    pub fn clear(&mut self) {
+        self.array.iter_mut().for_each(|entry| *entry = None);
+        self.len = 0;
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.array.get(index)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.array.get_mut(index)?.as_mut()
+    }
+}
@@ -0,0 +1,46 @@
+use copi_slot::Slot;
+
+#[test]
+fn test_fills_to_capacity() {
+    let mut slot: Slot<u32, 30> = Slot::new();
+    for i in 0..30 {
+        assert_eq!(slot.add(i), Some(i as usize));
+    }
+    assert_eq!(slot.len(), 30);
+    assert_eq!(slot.add(30), None);
+}
+
+#[test]
+fn test_reuses_removed_indices() {
+    let mut slot: Slot<&str, 4> = Slot::new();
+    assert_eq!(slot.add("a"), Some(0));
+    assert_eq!(slot.add("b"), Some(1));
+    assert_eq!(slot.add("c"), Some(2));
+
+    assert_eq!(slot.remove(1), Some("b"));
+    assert_eq!(slot.remove(1), None);
+    assert_eq!(slot.get(1), None);
+    assert_eq!(slot.len(), 2);
+
+    // The hole is filled before the end.
+    assert_eq!(slot.add("d"), Some(1));
+    assert_eq!(slot.add("e"), Some(3));
+    assert_eq!(slot.add("f"), None);
+    assert_eq!(slot.get(0), Some(&"a"));
+    assert_eq!(slot.get(1), Some(&"d"));
+    assert_eq!(slot.get(2), Some(&"c"));
+}
+
+#[test]
+fn test_out_of_range_and_clear() {
+    let mut slot: Slot<u8, 2> = Slot::new();
+    assert_eq!(slot.remove(5), None);
+    assert_eq!(slot.get_mut(5), None);
+    slot.add(1);
+    slot.add(2);
+    *slot.get_mut(0).unwrap() = 7;
+    assert_eq!(slot.get(0), Some(&7));
+    slot.clear();
+    assert!(slot.is_empty());
+    assert_eq!(slot.add(3), Some(0));
+}
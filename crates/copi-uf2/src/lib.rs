@@ -0,0 +1,470 @@
+//! Reading, checking and writing UF2 images for the RP2040 and RP2350 boot
+//! drives, and converting firmware ELF files to them, so neither the build
+//! nor `copi flash` needs picotool.
+//!
+//! The format is described at <https://github.com/microsoft/uf2>.
+
+use std::{collections::BTreeMap, fmt, ops::Range};
+
+/// Every UF2 block is this long, whatever its payload.
+pub const BLOCK_SIZE: usize = 512;
+
+/// The payload written per block: one flash page.
+pub const PAGE_SIZE: u32 = 256;
+
+/// The most payload a block can carry.
+const MAX_PAYLOAD: usize = 476;
+
+const MAGIC_START0: u32 = 0x0A32_4655;
+const MAGIC_START1: u32 = 0x9E5D_5157;
+const MAGIC_END: u32 = 0x0AB1_6F30;
+
+/// The block is not meant for main flash and is skipped by the bootloader.
+pub const FLAG_NOT_MAIN_FLASH: u32 = 0x0000_0001;
+/// The block carries a family ID rather than a file size.
+pub const FLAG_FAMILY_ID_PRESENT: u32 = 0x0000_2000;
+
+const RP2040_MEMORY: [Range<u32>; 2] = [0x1000_0000..0x1100_0000, 0x2000_0000..0x2004_2000];
+const RP2350_MEMORY: [Range<u32>; 2] = [0x1000_0000..0x1200_0000, 0x2000_0000..0x2008_2000];
+
+/// The Raspberry Pi family IDs, which tell the bootloader what an image is
+/// built for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Family {
+    Rp2040,
+    /// Written at its address whatever the chip; used for errata workarounds.
+    Absolute,
+    /// Data rather than code.
+    Data,
+    Rp2350ArmSecure,
+    Rp2350RiscV,
+    Rp2350ArmNonSecure,
+}
+
+impl Family {
+    pub const ALL: [Family; 6] = [
+        Family::Rp2040,
+        Family::Absolute,
+        Family::Data,
+        Family::Rp2350ArmSecure,
+        Family::Rp2350RiscV,
+        Family::Rp2350ArmNonSecure,
+    ];
+
+    pub fn id(self) -> u32 {
+        match self {
+            Family::Rp2040 => 0xe48b_ff56,
+            Family::Absolute => 0xe48b_ff57,
+            Family::Data => 0xe48b_ff58,
+            Family::Rp2350ArmSecure => 0xe48b_ff59,
+            Family::Rp2350RiscV => 0xe48b_ff5a,
+            Family::Rp2350ArmNonSecure => 0xe48b_ff5b,
+        }
+    }
+
+    pub fn from_id(id: u32) -> Option<Family> {
+        Family::ALL.into_iter().find(|f| f.id() == id)
+    }
+
+    /// The name picotool uses for the family, e.g. `rp2350-arm-s`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Family::Rp2040 => "rp2040",
+            Family::Absolute => "absolute",
+            Family::Data => "data",
+            Family::Rp2350ArmSecure => "rp2350-arm-s",
+            Family::Rp2350RiscV => "rp2350-riscv",
+            Family::Rp2350ArmNonSecure => "rp2350-arm-ns",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Family> {
+        Family::ALL.into_iter().find(|f| f.name() == name)
+    }
+
+    /// Flash and SRAM of the chip the family runs on.
+    fn memory(self) -> &'static [Range<u32>] {
+        match self {
+            Family::Rp2040 => &RP2040_MEMORY,
+            // Absolute and data blocks are not tied to a chip, so they get
+            // the larger of the two.
+            _ => &RP2350_MEMORY,
+        }
+    }
+
+    fn contains(self, address: u32, len: u32) -> bool {
+        let end = address as u64 + len as u64;
+        self.memory()
+            .iter()
+            .any(|r| r.start <= address && end <= r.end as u64)
+    }
+}
+
+impl fmt::Display for Family {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The file is not a whole number of blocks.
+    Length(usize),
+    Empty,
+    Magic {
+        block: usize,
+    },
+    PayloadSize {
+        block: usize,
+        size: u32,
+    },
+    UnknownFamily {
+        block: usize,
+        id: u32,
+    },
+    /// A block is out of sequence.
+    Order {
+        block: usize,
+        expected: u32,
+        found: u32,
+    },
+    /// A block disagrees with the rest of its sequence on how long it is.
+    BlockCount {
+        block: usize,
+        expected: u32,
+        found: u32,
+    },
+    /// The file ends part way through a sequence.
+    Truncated {
+        expected: u32,
+        found: u32,
+    },
+    /// Data that would land outside the family's flash and SRAM.
+    Address {
+        address: u32,
+        family: Family,
+    },
+    Elf(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Length(len) => write!(
+                f,
+                "{} bytes is not a whole number of {}-byte blocks",
+                len, BLOCK_SIZE
+            ),
+            Error::Empty => write!(f, "no blocks"),
+            Error::Magic { block } => write!(f, "block {} has bad magic numbers", block),
+            Error::PayloadSize { block, size } => {
+                write!(f, "block {} has a {}-byte payload", block, size)
+            }
+            Error::UnknownFamily { block, id } => {
+                write!(f, "block {} has unknown family ID {:#010x}", block, id)
+            }
+            Error::Order {
+                block,
+                expected,
+                found,
+            } => write!(
+                f,
+                "block {} is numbered {}, expected {}",
+                block, found, expected
+            ),
+            Error::BlockCount {
+                block,
+                expected,
+                found,
+            } => write!(
+                f,
+                "block {} counts {} blocks, expected {}",
+                block, found, expected
+            ),
+            Error::Truncated { expected, found } => {
+                write!(f, "truncated after {} of {} blocks", found, expected)
+            }
+            Error::Address { address, family } => write!(
+                f,
+                "address {:#010x} is outside {} flash and SRAM",
+                address, family
+            ),
+            Error::Elf(e) => write!(f, "invalid ELF file: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// One 512-byte UF2 block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub flags: u32,
+    pub target_addr: u32,
+    pub block_no: u32,
+    pub num_blocks: u32,
+    /// The family ID, or the file size when [`FLAG_FAMILY_ID_PRESENT`] is
+    /// not set.
+    pub family_or_size: u32,
+    pub data: Vec<u8>,
+}
+
+impl Block {
+    pub fn family_id(&self) -> Option<u32> {
+        (self.flags & FLAG_FAMILY_ID_PRESENT != 0).then_some(self.family_or_size)
+    }
+
+    fn parse(index: usize, bytes: &[u8]) -> Result<Block, Error> {
+        let word = |n: usize| u32::from_le_bytes(bytes[n * 4..n * 4 + 4].try_into().unwrap());
+        if word(0) != MAGIC_START0 || word(1) != MAGIC_START1 || word(127) != MAGIC_END {
+            return Err(Error::Magic { block: index });
+        }
+        let payload_size = word(4);
+        if payload_size as usize > MAX_PAYLOAD {
+            return Err(Error::PayloadSize {
+                block: index,
+                size: payload_size,
+            });
+        }
+        Ok(Block {
+            flags: word(2),
+            target_addr: word(3),
+            block_no: word(5),
+            num_blocks: word(6),
+            family_or_size: word(7),
+            data: bytes[32..32 + payload_size as usize].to_vec(),
+        })
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        let start = out.len();
+        for word in [
+            MAGIC_START0,
+            MAGIC_START1,
+            self.flags,
+            self.target_addr,
+            self.data.len() as u32,
+            self.block_no,
+            self.num_blocks,
+            self.family_or_size,
+        ] {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+        out.extend_from_slice(&self.data);
+        out.resize(start + BLOCK_SIZE - 4, 0);
+        out.extend_from_slice(&MAGIC_END.to_le_bytes());
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Uf2 {
+    pub blocks: Vec<Block>,
+}
+
+impl Uf2 {
+    /// Parse and [validate](Uf2::validate) a UF2 file.
+    pub fn parse(bytes: &[u8]) -> Result<Uf2, Error> {
+        if !bytes.len().is_multiple_of(BLOCK_SIZE) {
+            return Err(Error::Length(bytes.len()));
+        }
+        let blocks = bytes
+            .chunks_exact(BLOCK_SIZE)
+            .enumerate()
+            .map(|(i, b)| Block::parse(i, b))
+            .collect::<Result<Vec<_>, _>>()?;
+        let uf2 = Uf2 { blocks };
+        uf2.validate()?;
+        Ok(uf2)
+    }
+
+    /// Check that every sequence of blocks is numbered in order and
+    /// complete, that family IDs are known, and that what is destined for
+    /// flash lands in the memory of its family.
+    ///
+    /// A file may hold several sequences back to back, each numbered from
+    /// zero, as picotool does for its errata workaround block.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.blocks.is_empty() {
+            return Err(Error::Empty);
+        }
+        // The next block number expected, and the length of the sequence.
+        let mut sequence: Option<(u32, u32)> = None;
+        for (i, block) in self.blocks.iter().enumerate() {
+            match sequence {
+                Some((next, len)) if next < len => {
+                    if block.block_no != next {
+                        return Err(Error::Order {
+                            block: i,
+                            expected: next,
+                            found: block.block_no,
+                        });
+                    }
+                    if block.num_blocks != len {
+                        return Err(Error::BlockCount {
+                            block: i,
+                            expected: len,
+                            found: block.num_blocks,
+                        });
+                    }
+                }
+                _ if block.block_no != 0 => {
+                    return Err(Error::Order {
+                        block: i,
+                        expected: 0,
+                        found: block.block_no,
+                    });
+                }
+                _ if block.num_blocks == 0 => {
+                    return Err(Error::BlockCount {
+                        block: i,
+                        expected: 1,
+                        found: 0,
+                    });
+                }
+                _ => {}
+            }
+            sequence = Some((block.block_no + 1, block.num_blocks));
+
+            let family = match block.family_id() {
+                Some(id) => Some(Family::from_id(id).ok_or(Error::UnknownFamily { block: i, id })?),
+                None => None,
+            };
+            if block.flags & FLAG_NOT_MAIN_FLASH != 0 {
+                continue;
+            }
+            // Without a family ID, allow anything either chip could take.
+            let family = family.unwrap_or(Family::Absolute);
+            if !family.contains(block.target_addr, block.data.len() as u32) {
+                return Err(Error::Address {
+                    address: block.target_addr,
+                    family,
+                });
+            }
+        }
+        match sequence {
+            Some((next, len)) if next < len => Err(Error::Truncated {
+                expected: len,
+                found: next,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// The families the image is built for, without repeats.
+    pub fn families(&self) -> Vec<Family> {
+        let mut families: Vec<Family> = self
+            .blocks
+            .iter()
+            .filter_map(|b| b.family_id().and_then(Family::from_id))
+            .collect();
+        families.sort();
+        families.dedup();
+        families
+    }
+
+    /// The span of memory the image writes to.
+    pub fn address_range(&self) -> Option<Range<u32>> {
+        let blocks = self
+            .blocks
+            .iter()
+            .filter(|b| b.flags & FLAG_NOT_MAIN_FLASH == 0);
+        let start = blocks.clone().map(|b| b.target_addr).min()?;
+        let end = blocks.map(|b| b.target_addr + b.data.len() as u32).max()?;
+        Some(start..end)
+    }
+
+    /// Convert the loadable segments of a 32-bit little-endian ELF file,
+    /// placed at their physical addresses, to one block per flash page.
+    pub fn from_elf(elf: &[u8], family: Family) -> Result<Uf2, Error> {
+        let mut pages: BTreeMap<u32, Vec<u8>> = BTreeMap::new();
+        for (address, data) in load_segments(elf)? {
+            if !family.contains(address, data.len() as u32) {
+                return Err(Error::Address { address, family });
+            }
+            let mut address = address;
+            let mut data = data;
+            while !data.is_empty() {
+                let page = address & !(PAGE_SIZE - 1);
+                let offset = (address - page) as usize;
+                let len = data.len().min(PAGE_SIZE as usize - offset);
+                // Gaps within a page are written as zeros.
+                let buffer = pages
+                    .entry(page)
+                    .or_insert_with(|| vec![0; PAGE_SIZE as usize]);
+                buffer[offset..offset + len].copy_from_slice(&data[..len]);
+                address += len as u32;
+                data = &data[len..];
+            }
+        }
+        if pages.is_empty() {
+            return Err(Error::Elf("no loadable segments".to_string()));
+        }
+        let num_blocks = pages.len() as u32;
+        let blocks = pages
+            .into_iter()
+            .enumerate()
+            .map(|(i, (target_addr, data))| Block {
+                flags: FLAG_FAMILY_ID_PRESENT,
+                target_addr,
+                block_no: i as u32,
+                num_blocks,
+                family_or_size: family.id(),
+                data,
+            })
+            .collect();
+        Ok(Uf2 { blocks })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.blocks.len() * BLOCK_SIZE);
+        for block in &self.blocks {
+            block.write(&mut out);
+        }
+        out
+    }
+}
+
+const PT_LOAD: u32 = 1;
+
+/// The physical address and file contents of each non-empty `PT_LOAD`
+/// segment.
+fn load_segments(elf: &[u8]) -> Result<Vec<(u32, &[u8])>, Error> {
+    let err = |e: &str| Error::Elf(e.to_string());
+    let u16_at = |n: usize| -> Result<u16, Error> {
+        let bytes = elf.get(n..n + 2).ok_or_else(|| err("truncated"))?;
+        Ok(u16::from_le_bytes(bytes.try_into().unwrap()))
+    };
+    let u32_at = |n: usize| -> Result<u32, Error> {
+        let bytes = elf.get(n..n + 4).ok_or_else(|| err("truncated"))?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+    };
+
+    if !elf.starts_with(b"\x7fELF") {
+        return Err(err("bad magic"));
+    }
+    if elf.get(4) != Some(&1) || elf.get(5) != Some(&1) {
+        return Err(err("not a 32-bit little-endian file"));
+    }
+    let phoff = u32_at(28)? as usize;
+    let phentsize = u16_at(42)? as usize;
+    let phnum = u16_at(44)? as usize;
+
+    let mut segments = Vec::new();
+    for i in 0..phnum {
+        let header = phoff + i * phentsize;
+        if u32_at(header)? != PT_LOAD {
+            continue;
+        }
+        let offset = u32_at(header + 4)? as usize;
+        let paddr = u32_at(header + 12)?;
+        let filesz = u32_at(header + 16)? as usize;
+        if filesz == 0 {
+            continue;
+        }
+        let data = elf
+            .get(offset..offset + filesz)
+            .ok_or_else(|| err("segment extends past the end of the file"))?;
+        segments.push((paddr, data));
+    }
+    Ok(segments)
+}
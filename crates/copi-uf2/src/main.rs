@@ -0,0 +1,51 @@
+//! `copi-uf2 convert ELF UF2 [--family NAME]` and `copi-uf2 info UF2`, used
+//! by `build-firmware.sh` in place of picotool.
+
+use std::{env, fs, process::ExitCode};
+
+use copi_uf2::{Family, Uf2};
+
+const USAGE: &str = "Usage:
+  copi-uf2 convert <ELF> <UF2> [--family rp2040|rp2350-arm-s|rp2350-riscv|rp2350-arm-ns]
+  copi-uf2 info <UF2>";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = match args.as_slice() {
+        ["convert", elf, uf2] => convert(elf, uf2, Family::Rp2350ArmSecure),
+        ["convert", elf, uf2, "--family", family] => match Family::from_name(family) {
+            Some(family) => convert(elf, uf2, family),
+            None => Err(format!("Unknown family: {}", family)),
+        },
+        ["info", uf2] => info(uf2),
+        _ => Err(USAGE.to_string()),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn convert(elf: &str, uf2: &str, family: Family) -> Result<(), String> {
+    let bytes = fs::read(elf).map_err(|e| format!("Failed to read {}: {}", elf, e))?;
+    let image = Uf2::from_elf(&bytes, family).map_err(|e| format!("{}: {}", elf, e))?;
+    fs::write(uf2, image.to_bytes()).map_err(|e| format!("Failed to write {}: {}", uf2, e))?;
+    println!("Wrote {} ({} blocks, {})", uf2, image.blocks.len(), family);
+    Ok(())
+}
+
+fn info(uf2: &str) -> Result<(), String> {
+    let bytes = fs::read(uf2).map_err(|e| format!("Failed to read {}: {}", uf2, e))?;
+    let image = Uf2::parse(&bytes).map_err(|e| format!("{}: {}", uf2, e))?;
+    let families: Vec<&str> = image.families().into_iter().map(Family::name).collect();
+    println!("Blocks:   {}", image.blocks.len());
+    println!("Families: {}", families.join(", "));
+    if let Some(range) = image.address_range() {
+        println!("Range:    {:#010x}..{:#010x}", range.start, range.end);
+    }
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use copi_uf2::{BLOCK_SIZE, Error, Family, PAGE_SIZE, Uf2};
+
+/// A minimal 32-bit little-endian ELF file with one `PT_LOAD` segment per
+/// `(address, data)`.
+fn elf(segments: &[(u32, &[u8])]) -> Vec<u8> {
+    let phoff = 52;
+    let mut offset = phoff + 32 * segments.len();
+    let mut out = vec![0; 52];
+    out[..6].copy_from_slice(b"\x7fELF\x01\x01");
+    out[18..20].copy_from_slice(&40u16.to_le_bytes());
+    out[28..32].copy_from_slice(&(phoff as u32).to_le_bytes());
+    out[42..44].copy_from_slice(&32u16.to_le_bytes());
+    out[44..46].copy_from_slice(&(segments.len() as u16).to_le_bytes());
+    for (address, data) in segments {
+        for word in [
+            1,
+            offset as u32,
+            *address,
+            *address,
+            data.len() as u32,
+            data.len() as u32,
+            5,
+            4,
+        ] {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+        offset += data.len();
+    }
+    for (_, data) in segments {
+        out.extend_from_slice(data);
+    }
+    out
+}
+
+#[test]
+fn test_elf_round_trip() {
+    let code = vec![0xaa; 300];
+    let data = [1, 2, 3, 4];
+    let image = Uf2::from_elf(
+        &elf(&[(0x1000_0000, &code), (0x1000_0180, &data)]),
+        Family::Rp2350ArmSecure,
+    )
+    .unwrap();
+
+    // 300 bytes from 0x10000000 and 4 more at 0x10000180 share two pages.
+    assert_eq!(image.blocks.len(), 2);
+    assert!(
+        image
+            .blocks
+            .iter()
+            .all(|b| b.data.len() == PAGE_SIZE as usize)
+    );
+    assert_eq!(&image.blocks[1].data[0x80..0x84], &data);
+    assert_eq!(image.blocks[1].data[0x84], 0);
+    assert_eq!(image.address_range(), Some(0x1000_0000..0x1000_0200));
+
+    let bytes = image.to_bytes();
+    assert_eq!(bytes.len(), 2 * BLOCK_SIZE);
+    let parsed = Uf2::parse(&bytes).unwrap();
+    assert_eq!(parsed, image);
+    assert_eq!(parsed.families(), vec![Family::Rp2350ArmSecure]);
+}
+
+#[test]
+fn test_rejects_bad_blocks() {
+    let image = Uf2::from_elf(&elf(&[(0x1000_0000, &[0; 600])]), Family::Rp2040).unwrap();
+    let bytes = image.to_bytes();
+
+    assert_eq!(Uf2::parse(&bytes[1..]), Err(Error::Length(bytes.len() - 1)));
+    assert_eq!(Uf2::parse(&[]), Err(Error::Empty));
+
+    let mut corrupt = bytes.clone();
+    corrupt[BLOCK_SIZE + 2] ^= 0xff;
+    assert_eq!(Uf2::parse(&corrupt), Err(Error::Magic { block: 1 }));
+
+    let mut swapped = image.clone();
+    swapped.blocks.swap(1, 2);
+    assert_eq!(
+        Uf2::parse(&swapped.to_bytes()),
+        Err(Error::Order {
+            block: 1,
+            expected: 1,
+            found: 2
+        })
+    );
+
+    let mut truncated = image.clone();
+    truncated.blocks.pop();
+    assert_eq!(
+        truncated.validate(),
+        Err(Error::Truncated {
+            expected: 3,
+            found: 2
+        })
+    );
+
+    let mut unknown = image.clone();
+    unknown.blocks[0].family_or_size = 0x1234_5678;
+    assert_eq!(
+        unknown.validate(),
+        Err(Error::UnknownFamily {
+            block: 0,
+            id: 0x1234_5678
+        })
+    );
+}
+
+#[test]
+fn test_checks_addresses_against_the_family() {
+    // RP2350 flash reaches past the end of RP2040 flash.
+    let elf = elf(&[(0x1100_0000, &[0; 16])]);
+    assert_eq!(
+        Uf2::from_elf(&elf, Family::Rp2040),
+        Err(Error::Address {
+            address: 0x1100_0000,
+            family: Family::Rp2040
+        })
+    );
+    let image = Uf2::from_elf(&elf, Family::Rp2350RiscV).unwrap();
+
+    let mut relabelled = image.clone();
+    relabelled.blocks[0].family_or_size = Family::Rp2040.id();
+    assert!(matches!(relabelled.validate(), Err(Error::Address { .. })));
+
+    assert!(Uf2::from_elf(b"not an elf", Family::Rp2040).is_err());
+    assert_eq!(Family::from_name("rp2350-riscv"), Some(Family::Rp2350RiscV));
+    assert_eq!(Family::from_id(0xe48b_ff56), Some(Family::Rp2040));
+}
@@ -1,7 +1,26 @@
 use defmt::info;
 use pio::{ArrayVec, PioVersion, Program, SideSet, Wrap};
 
-use crate::{generated::copi::*, peripherals::PeripheralController};
+use crate::{device_warn, generated::copi::*, peripherals::PeripheralController};
+
+/// Bumped with every change to the messages in copi-proto or how they are
+/// framed. The host checks it against its own when it connects.
+const PROTOCOL_VERSION: u32 = 4;
+
+const CHIP_MODEL: &str = "RP2350A";
+
+/// `RequestBody` tags handled by [`handle_request`], as a bit mask.
+const SUPPORTED_MESSAGES: u64 = tag_mask(&[2, 3, 12, 13, 14, 15, 16, 17, 18, 19, 20]);
+
+const fn tag_mask(tags: &[u32]) -> u64 {
+    let mut mask = 0;
+    let mut i = 0;
+    while i < tags.len() {
+        mask |= 1 << tags[i];
+        i += 1;
+    }
+    mask
+}
 
 pub fn handle_request<'d>(
     pc: &'d mut PeripheralController<'static>,
@@ -32,6 +51,82 @@ pub fn handle_request<'d>(
             info!("GpioOutputSet: {} {}", pin, value);
             pc.gpio_output_set(pin as _, value)
         }
+        Message::PinDeinit(PinDeinit {
+            pin,
+            unknown_fields: _,
+        }) => {
+            info!("PinDeinit: {}", pin);
+            pc.pin_deinit(pin as _)
+        }
+        Message::PwmDeinit(PwmDeinit {
+            slice,
+            unknown_fields: _,
+        }) => {
+            info!("PwmDeinit: {}", slice);
+            pc.pwm_deinit(slice as _)
+        }
+        Message::PioSmDeinit(PioSmDeinit {
+            pio_num,
+            sm_num,
+            unknown_fields: _,
+        }) => {
+            info!("PioSmDeinit: {} {}", pio_num, sm_num);
+            pc.pio_sm_deinit(pio_num as _, sm_num as _)
+        }
+        Message::ResetAll(_) => {
+            info!("ResetAll");
+            pc.reset_all()
+        }
+        Message::Reboot(Reboot {
+            bootsel,
+            unknown_fields: _,
+        }) => {
+            info!("Reboot: bootsel {}", bootsel);
+            pc.reboot(bootsel)
+        }
+        Message::GetDeviceInfo(_) => {
+            info!("GetDeviceInfo");
+            ResponseBody {
+                message: Some(response_body::Message::DeviceInfo(DeviceInfo {
+                    firmware_version: env!("CARGO_PKG_VERSION"),
+                    protocol_version: PROTOCOL_VERSION,
+                    chip_model: CHIP_MODEL,
+                    unique_id: pc.unique_id(),
+                    supported_messages: SUPPORTED_MESSAGES,
+                    ..Default::default()
+                })),
+                ..Default::default()
+            }
+        }
+        Message::SetDeviceName(SetDeviceName {
+            name,
+            unknown_fields: _,
+        }) => {
+            info!("SetDeviceName: {}", name);
+            pc.set_device_name(name)
+        }
+        Message::GetDeviceName(_) => {
+            info!("GetDeviceName");
+            ResponseBody {
+                message: Some(response_body::Message::DeviceName(DeviceName {
+                    name: pc.device_name(),
+                    ..Default::default()
+                })),
+                ..Default::default()
+            }
+        }
+        Message::GetCrashReport(_) => {
+            info!("GetCrashReport");
+            let report = pc.take_crash_report();
+            ResponseBody {
+                message: Some(response_body::Message::CrashReport(CrashReport {
+                    message: report.map_or("", |r| r.message()),
+                    location: report.map_or("", |r| r.location()),
+                    ..Default::default()
+                })),
+                ..Default::default()
+            }
+        }
         // TODO: Uncomment and implement these modules as needed
         // PwmInit {
         //     slice,
@@ -131,6 +226,7 @@ pub fn handle_request<'d>(
         //     unsafe { pc.pio_sm_exec_instr_unchecked(pio_num as _, sm_num as _, exec_instr) }
         // }
         _ => {
+            device_warn!("Request {} is not handled", request_id);
             let message = response_body::Message::Common(Common {
                 error: ResponseCommonErrorCode::UnknownError as _,
                 data: 0,
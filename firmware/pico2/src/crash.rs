@@ -0,0 +1,69 @@
+//! The panic handler. It keeps the panic message and location in RAM that
+//! startup leaves alone, then resets, so the next connection can tell the
+//! host what happened (`GetCrashReport`). A power cycle loses the report.
+
+use core::{fmt::Write, mem::MaybeUninit, panic::PanicInfo, ptr::addr_of_mut};
+
+use crate::utils::TextBuf;
+
+/// Together they keep a `CrashReport` inside one 64-byte packet.
+const MESSAGE_MAX_LEN: usize = 32;
+const LOCATION_MAX_LEN: usize = 20;
+
+/// Marks a record written by [`panic`]; anything else is power-on garbage.
+const MAGIC: u32 = 0xC0B1_DEAD;
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct Report {
+    magic: u32,
+    message: TextBuf<MESSAGE_MAX_LEN>,
+    /// `file.rs:line`
+    location: TextBuf<LOCATION_MAX_LEN>,
+}
+
+impl Report {
+    pub fn message(&self) -> &str {
+        self.message.as_str()
+    }
+
+    pub fn location(&self) -> &str {
+        self.location.as_str()
+    }
+}
+
+#[unsafe(link_section = ".uninit.copi_crash")]
+static mut REPORT: MaybeUninit<Report> = MaybeUninit::uninit();
+
+/// The report left by a panic before the last reset, if any. It is cleared,
+/// so each crash is reported once.
+pub fn take() -> Option<Report> {
+    // Single-threaded at startup; `TextBuf` reads are bounds checked, so
+    // garbage that happens to carry the magic is still safe to read.
+    unsafe {
+        let slot = &mut *addr_of_mut!(REPORT);
+        let report = slot.assume_init_read();
+        slot.as_mut_ptr().cast::<u32>().write_volatile(0);
+        (report.magic == MAGIC).then_some(report)
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cortex_m::interrupt::disable();
+    defmt::error!("{}", defmt::Display2Format(info));
+
+    let mut report = Report {
+        magic: MAGIC,
+        message: TextBuf::new(),
+        location: TextBuf::new(),
+    };
+    let _ = write!(report.message, "{}", info.message());
+    if let Some(location) = info.location() {
+        let file = location.file().rsplit('/').next().unwrap_or_default();
+        let _ = write!(report.location, "{}:{}", file, location.line());
+    }
+    unsafe { addr_of_mut!(REPORT).write(MaybeUninit::new(report)) };
+
+    cortex_m::peripheral::SCB::sys_reset()
+}
@@ -0,0 +1,86 @@
+//! Log lines for the host, sent over USB as `DeviceLog` responses with
+//! request ID 0. Everything logged here goes to defmt as well.
+
+use core::fmt::{self, Write};
+
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
+
+use crate::utils::TextBuf;
+
+/// Lines waiting for the host; new ones are dropped while it is full.
+const QUEUE_LEN: usize = 8;
+/// With [`MODULE_MAX_LEN`], keeps a `DeviceLog` inside one 64-byte packet.
+const MESSAGE_MAX_LEN: usize = 40;
+const MODULE_MAX_LEN: usize = 12;
+
+/// Numbered as in the host's `log` crate.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+}
+
+pub struct Record {
+    pub level: Level,
+    module: &'static str,
+    message: TextBuf<MESSAGE_MAX_LEN>,
+}
+
+impl Record {
+    pub fn module(&self) -> &'static str {
+        self.module
+    }
+
+    pub fn message(&self) -> &str {
+        self.message.as_str()
+    }
+}
+
+static QUEUE: Channel<CriticalSectionRawMutex, Record, QUEUE_LEN> = Channel::new();
+
+/// Queue a line for the host; see [`device_error!`] and friends.
+pub fn log(level: Level, module_path: &'static str, args: fmt::Arguments) {
+    // `copi_firmware_pico2::usb` is sent as `usb`.
+    let module = module_path
+        .split_once("::")
+        .map_or(module_path, |(_, module)| module);
+    let mut record = Record {
+        level,
+        module: module.get(..MODULE_MAX_LEN).unwrap_or(module),
+        message: TextBuf::new(),
+    };
+    let _ = record.message.write_fmt(args);
+    match level {
+        Level::Error => defmt::error!("{=str}: {=str}", module, record.message()),
+        Level::Warn => defmt::warn!("{=str}: {=str}", module, record.message()),
+        Level::Info => defmt::info!("{=str}: {=str}", module, record.message()),
+    }
+    let _ = QUEUE.try_send(record);
+}
+
+/// Wait for the next line to send.
+pub async fn next() -> Record {
+    QUEUE.receive().await
+}
+
+#[macro_export]
+macro_rules! device_error {
+    ($($arg:tt)*) => {
+        $crate::devlog::log($crate::devlog::Level::Error, module_path!(), format_args!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! device_warn {
+    ($($arg:tt)*) => {
+        $crate::devlog::log($crate::devlog::Level::Warn, module_path!(), format_args!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! device_info {
+    ($($arg:tt)*) => {
+        $crate::devlog::log($crate::devlog::Level::Info, module_path!(), format_args!($($arg)*))
+    };
+}
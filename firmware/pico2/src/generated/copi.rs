@@ -1,7 +1,7 @@
 #[derive(::defmt::Format)]
 #[derive(Clone, PartialEq, ::femtopb::Message)]
 pub struct RequestBody<'a> {
-    #[femtopb(oneof, tags = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11])]
+    #[femtopb(oneof, tags = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20])]
     pub message: ::core::option::Option<request_body::Message<'a>>,
     #[femtopb(unknown_fields)]
     pub unknown_fields: femtopb::UnknownFields<'a>,
@@ -34,6 +34,24 @@ pub mod request_body {
         PioSmPush(super::PioSmPush<'a>),
         #[femtopb(message, tag = 11)]
         PioSmExecInstr(super::PioSmExecInstr<'a>),
+        #[femtopb(message, tag = 12)]
+        PinDeinit(super::PinDeinit<'a>),
+        #[femtopb(message, tag = 13)]
+        PwmDeinit(super::PwmDeinit<'a>),
+        #[femtopb(message, tag = 14)]
+        PioSmDeinit(super::PioSmDeinit<'a>),
+        #[femtopb(message, tag = 15)]
+        ResetAll(super::ResetAll<'a>),
+        #[femtopb(message, tag = 16)]
+        Reboot(super::Reboot<'a>),
+        #[femtopb(message, tag = 17)]
+        GetDeviceInfo(super::GetDeviceInfo<'a>),
+        #[femtopb(message, tag = 18)]
+        SetDeviceName(super::SetDeviceName<'a>),
+        #[femtopb(message, tag = 19)]
+        GetDeviceName(super::GetDeviceName<'a>),
+        #[femtopb(message, tag = 20)]
+        GetCrashReport(super::GetCrashReport<'a>),
         #[femtopb(phantom)]
         _Phantom(::core::marker::PhantomData<&'a ()>),
     }
@@ -178,8 +196,76 @@ pub struct PioSmExecInstr<'a> {
 }
 #[derive(::defmt::Format)]
 #[derive(Clone, Copy, PartialEq, ::femtopb::Message)]
+pub struct PinDeinit<'a> {
+    #[femtopb(uint32, tag = 1)]
+    pub pin: u32,
+    #[femtopb(unknown_fields)]
+    pub unknown_fields: femtopb::UnknownFields<'a>,
+}
+#[derive(::defmt::Format)]
+#[derive(Clone, Copy, PartialEq, ::femtopb::Message)]
+pub struct PwmDeinit<'a> {
+    #[femtopb(uint32, tag = 1)]
+    pub slice: u32,
+    #[femtopb(unknown_fields)]
+    pub unknown_fields: femtopb::UnknownFields<'a>,
+}
+#[derive(::defmt::Format)]
+#[derive(Clone, Copy, PartialEq, ::femtopb::Message)]
+pub struct PioSmDeinit<'a> {
+    #[femtopb(uint32, tag = 1)]
+    pub pio_num: u32,
+    #[femtopb(uint32, tag = 2)]
+    pub sm_num: u32,
+    #[femtopb(unknown_fields)]
+    pub unknown_fields: femtopb::UnknownFields<'a>,
+}
+#[derive(::defmt::Format)]
+#[derive(Clone, Copy, PartialEq, ::femtopb::Message)]
+pub struct ResetAll<'a> {
+    #[femtopb(unknown_fields)]
+    pub unknown_fields: femtopb::UnknownFields<'a>,
+}
+#[derive(::defmt::Format)]
+#[derive(Clone, Copy, PartialEq, ::femtopb::Message)]
+pub struct Reboot<'a> {
+    #[femtopb(bool, tag = 1)]
+    pub bootsel: bool,
+    #[femtopb(unknown_fields)]
+    pub unknown_fields: femtopb::UnknownFields<'a>,
+}
+#[derive(::defmt::Format)]
+#[derive(Clone, Copy, PartialEq, ::femtopb::Message)]
+pub struct GetDeviceInfo<'a> {
+    #[femtopb(unknown_fields)]
+    pub unknown_fields: femtopb::UnknownFields<'a>,
+}
+#[derive(::defmt::Format)]
+#[derive(Clone, Copy, PartialEq, ::femtopb::Message)]
+pub struct SetDeviceName<'a> {
+    /// An empty name clears the stored one.
+    #[femtopb(string, tag = 1)]
+    pub name: &'a str,
+    #[femtopb(unknown_fields)]
+    pub unknown_fields: femtopb::UnknownFields<'a>,
+}
+#[derive(::defmt::Format)]
+#[derive(Clone, Copy, PartialEq, ::femtopb::Message)]
+pub struct GetDeviceName<'a> {
+    #[femtopb(unknown_fields)]
+    pub unknown_fields: femtopb::UnknownFields<'a>,
+}
+/// Answered once with the last panic before a reset, then cleared.
+#[derive(::defmt::Format)]
+#[derive(Clone, Copy, PartialEq, ::femtopb::Message)]
+pub struct GetCrashReport<'a> {
+    #[femtopb(unknown_fields)]
+    pub unknown_fields: femtopb::UnknownFields<'a>,
+}
+#[derive(::defmt::Format)]
+#[derive(Clone, Copy, PartialEq, ::femtopb::Message)]
 pub struct ResponseBody<'a> {
-    #[femtopb(oneof, tags = [1])]
+    #[femtopb(oneof, tags = [1, 2, 3, 4, 5])]
     pub message: ::core::option::Option<response_body::Message<'a>>,
     #[femtopb(unknown_fields)]
     pub unknown_fields: femtopb::UnknownFields<'a>,
@@ -192,6 +278,14 @@ pub mod response_body {
     pub enum Message<'a> {
         #[femtopb(message, tag = 1)]
         Common(super::Common<'a>),
+        #[femtopb(message, tag = 2)]
+        DeviceInfo(super::DeviceInfo<'a>),
+        #[femtopb(message, tag = 3)]
+        DeviceName(super::DeviceName<'a>),
+        #[femtopb(message, tag = 4)]
+        DeviceLog(super::DeviceLog<'a>),
+        #[femtopb(message, tag = 5)]
+        CrashReport(super::CrashReport<'a>),
         #[femtopb(phantom)]
         _Phantom(::core::marker::PhantomData<&'a ()>),
     }
@@ -207,6 +301,58 @@ pub struct Common<'a> {
     pub unknown_fields: femtopb::UnknownFields<'a>,
 }
 #[derive(::defmt::Format)]
+#[derive(Clone, Copy, PartialEq, ::femtopb::Message)]
+pub struct DeviceInfo<'a> {
+    #[femtopb(string, tag = 1)]
+    pub firmware_version: &'a str,
+    #[femtopb(uint32, tag = 2)]
+    pub protocol_version: u32,
+    #[femtopb(string, tag = 3)]
+    pub chip_model: &'a str,
+    #[femtopb(string, tag = 4)]
+    pub unique_id: &'a str,
+    /// Bit n is set when the firmware handles `RequestBody` tag n.
+    #[femtopb(uint64, tag = 5)]
+    pub supported_messages: u64,
+    #[femtopb(unknown_fields)]
+    pub unknown_fields: femtopb::UnknownFields<'a>,
+}
+#[derive(::defmt::Format)]
+#[derive(Clone, Copy, PartialEq, ::femtopb::Message)]
+pub struct DeviceName<'a> {
+    /// Empty when no name has been stored.
+    #[femtopb(string, tag = 1)]
+    pub name: &'a str,
+    #[femtopb(unknown_fields)]
+    pub unknown_fields: femtopb::UnknownFields<'a>,
+}
+/// A log line the firmware sends unprompted, with request ID 0.
+#[derive(::defmt::Format)]
+#[derive(Clone, Copy, PartialEq, ::femtopb::Message)]
+pub struct DeviceLog<'a> {
+    /// 1 error, 2 warn, 3 info, 4 debug, 5 trace.
+    #[femtopb(uint32, tag = 1)]
+    pub level: u32,
+    #[femtopb(string, tag = 2)]
+    pub module: &'a str,
+    #[femtopb(string, tag = 3)]
+    pub message: &'a str,
+    #[femtopb(unknown_fields)]
+    pub unknown_fields: femtopb::UnknownFields<'a>,
+}
+/// Both fields are empty when there was no crash.
+#[derive(::defmt::Format)]
+#[derive(Clone, Copy, PartialEq, ::femtopb::Message)]
+pub struct CrashReport<'a> {
+    #[femtopb(string, tag = 1)]
+    pub message: &'a str,
+    /// `file.rs:line`
+    #[femtopb(string, tag = 2)]
+    pub location: &'a str,
+    #[femtopb(unknown_fields)]
+    pub unknown_fields: femtopb::UnknownFields<'a>,
+}
+#[derive(::defmt::Format)]
 #[derive(
     Clone,
     Copy,
@@ -224,6 +370,8 @@ pub enum ResponseCommonErrorCode {
     #[default]
     UnknownError = 0,
     WrongPinState = 1,
+    InvalidArgument = 2,
+    FlashError = 3,
 }
 impl ResponseCommonErrorCode {
     /// String value of the enum field names used in the ProtoBuf definition.
@@ -234,6 +382,8 @@ impl ResponseCommonErrorCode {
         match self {
             Self::UnknownError => "UNKNOWN_ERROR",
             Self::WrongPinState => "WRONG_PIN_STATE",
+            Self::InvalidArgument => "INVALID_ARGUMENT",
+            Self::FlashError => "FLASH_ERROR",
         }
     }
     /// Creates an enum from field names used in the ProtoBuf definition.
@@ -241,6 +391,8 @@ impl ResponseCommonErrorCode {
         match value {
             "UNKNOWN_ERROR" => Some(Self::UnknownError),
             "WRONG_PIN_STATE" => Some(Self::WrongPinState),
+            "INVALID_ARGUMENT" => Some(Self::InvalidArgument),
+            "FLASH_ERROR" => Some(Self::FlashError),
             _ => None,
         }
     }
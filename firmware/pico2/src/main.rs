@@ -3,6 +3,8 @@
 #![feature(generic_arg_infer)] // https://blog.rust-lang.org/inside-rust/2025/03/05/inferred-const-generic-arguments.html
 
 mod command;
+mod crash;
+mod devlog;
 mod generated;
 mod peripherals;
 mod pio;
@@ -13,8 +15,11 @@ mod utils;
 mod rhai;
 
 use command::handle_request;
+use copi_frame::{FRAME_BUF_LEN, Frame, FrameReader, write_len};
 use defmt::info;
+use defmt_rtt as _;
 use embassy_executor::Spawner;
+use embassy_futures::select::{Either, select};
 use embassy_rp::{
     bind_interrupts,
     peripherals::{PIO0, PIO1, PIO2, USB},
@@ -23,7 +28,7 @@ use embassy_rp::{
 use embassy_usb::class::cdc_acm::CdcAcmClass;
 use femtopb::Message as _;
 use peripherals::PeripheralController;
-use {defmt_rtt as _, panic_probe as _};
+use static_cell::StaticCell;
 
 bind_interrupts!(struct Irqs {
     USBCTRL_IRQ => embassy_rp::usb::InterruptHandler<USB>;
@@ -49,9 +54,14 @@ async fn main(spawner: Spawner) {
 
     let p: embassy_rp::Peripherals = embassy_rp::init(Default::default());
 
-    let mut pc = PeripheralController::new();
+    // Read from OTP once; it is both the USB serial and the ID in DeviceInfo.
+    static UNIQUE_ID: StaticCell<[u8; 16]> = StaticCell::new();
+    let unique_id =
+        core::str::from_utf8(UNIQUE_ID.init(utils::unique_id_hex())).unwrap_or_default();
 
-    let mut class = usb::init(spawner, p);
+    let mut pc = PeripheralController::new(unique_id);
+
+    let mut class = usb::init(spawner, p, unique_id);
 
     loop {
         class.wait_connection().await;
@@ -65,24 +75,76 @@ async fn handle_class<'d, T: Instance + 'd>(
     class: &mut CdcAcmClass<'d, Driver<'d, T>>,
     pc: &mut PeripheralController<'static>,
 ) -> Result<(), usb::Disconnected> {
-    let mut request_buf = [0; 64];
-    let mut response_buf = [0; 64];
+    let mut requests = FrameReader::new();
+    let mut response_buf = [0; FRAME_BUF_LEN];
     loop {
-        let n = class.read_packet(&mut request_buf).await?;
-        let data = &request_buf[..n];
-        info!("data: {} - {:x}", n, data);
-
-        if let Ok(req) = generated::copi::CopiRequest::decode(&mut &data[..]) {
-            let response = handle_request(pc, req);
-
-            if response.request_id != 0 {
-                response
-                    .encode(&mut &mut response_buf[..response.encoded_len()])
-                    .unwrap();
-                class
-                    .write_packet(&response_buf[..response.encoded_len()])
-                    .await?;
+        let n = match select(class.read_packet(requests.spare()), devlog::next()).await {
+            Either::First(n) => n?,
+            Either::Second(record) => {
+                write_response(class, &mut response_buf, &log_response(&record)).await?;
+                continue;
             }
         };
+        requests.advance(n);
+        info!("data: {} - {:x}", n, requests.data());
+
+        loop {
+            let range = match requests.next_frame() {
+                Frame::Incomplete => break,
+                Frame::Complete(range) => range,
+                Frame::TooLong => {
+                    device_warn!("Dropped a request longer than the buffer");
+                    requests.clear();
+                    break;
+                }
+            };
+            match generated::copi::CopiRequest::decode(&mut &requests.data()[range.clone()]) {
+                Ok(req) => {
+                    let response = handle_request(pc, req);
+
+                    if response.request_id != 0 {
+                        write_response(class, &mut response_buf, &response).await?;
+                    }
+                }
+                Err(_) => device_warn!("Undecodable request of {} bytes", range.len()),
+            }
+            requests.consume(range.end);
+        }
+    }
+}
+
+/// Send `response` with its length in front, split into USB packets.
+async fn write_response<'d, T: Instance + 'd>(
+    class: &mut CdcAcmClass<'d, Driver<'d, T>>,
+    buf: &mut [u8; FRAME_BUF_LEN],
+    response: &generated::copi::CopiResponse<'_>,
+) -> Result<(), usb::Disconnected> {
+    let len = response.encoded_len();
+    let start = write_len(len, buf);
+    response.encode(&mut &mut buf[start..start + len]).unwrap();
+    for packet in buf[..start + len].chunks(64) {
+        class.write_packet(packet).await?;
+    }
+    // A full last packet does not end the transfer on its own.
+    if (start + len) % 64 == 0 {
+        class.write_packet(&[]).await?;
+    }
+    Ok(())
+}
+
+/// Wrap a log line as an unsolicited response.
+fn log_response(record: &devlog::Record) -> generated::copi::CopiResponse<'_> {
+    use generated::copi::*;
+    CopiResponse {
+        payload: Some(ResponseBody {
+            message: Some(response_body::Message::DeviceLog(DeviceLog {
+                level: record.level as u32,
+                module: record.module(),
+                message: record.message(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }),
+        ..Default::default()
     }
 }
@@ -8,10 +8,11 @@ use embassy_rp::{
 };
 
 use crate::{
+    crash, device_error,
     generated::copi::{Common, ResponseBody, ResponseCommonErrorCode, response_body},
     pio::PioControl,
     pio_run_with_program, pio_sm_invoke, pio_sm_run, sm_invoke, sm_run,
-    utils::{Slot, get_anypin_unchecked},
+    utils::{DEVICE_NAME_MAX_LEN, NameStore, Slot, get_anypin_unchecked},
 };
 
 pub struct Pin {
@@ -46,6 +47,12 @@ pub struct PeripheralController<'d> {
     gpio_outputs: Slot<Output<'d>, 30>,
     pwms: Slot<Pwm<'d>, 8>,
     pios: PioControl<'d>,
+    /// The chip ID as hex, read once at start.
+    unique_id: &'static str,
+    name: NameStore,
+    /// Left by a panic before the last reset.
+    crash: Option<crash::Report>,
+    crash_reported: bool,
 }
 
 #[macro_export]
@@ -64,6 +71,17 @@ macro_rules! check_pin_state {
     };
 }
 
+/// `REBOOT_TYPE_NORMAL` and `REBOOT_TYPE_BOOTSEL` for the bootrom `reboot`.
+const REBOOT_TYPE_NORMAL: u32 = 0x0000;
+const REBOOT_TYPE_BOOTSEL: u32 = 0x0002;
+/// Leaves time for the response to reach the host before the chip resets.
+const REBOOT_DELAY_MS: u32 = 100;
+
+/// `Pin::resource_index` of pins driven by a PIO state machine.
+fn pio_sm_index(pio_num: usize, sm_num: usize) -> usize {
+    pio_num * 4 + sm_num
+}
+
 #[inline(always)]
 fn success_response<'a>(value: u64) -> ResponseBody<'a> {
     ResponseBody {
@@ -75,21 +93,68 @@ fn success_response<'a>(value: u64) -> ResponseBody<'a> {
     }
 }
 
+#[inline(always)]
+fn error_response<'a>(code: ResponseCommonErrorCode, data: u64) -> ResponseBody<'a> {
+    ResponseBody {
+        message: Some(response_body::Message::Common(Common {
+            error: code as _,
+            data,
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+}
+
 impl<'d> PeripheralController<'d> {
-    pub fn new() -> Self {
+    pub fn new(unique_id: &'static str) -> Self {
         let this = unsafe {
-            let embassy_rp = embassy_rp::Peripherals::steal();
+            let mut embassy_rp = embassy_rp::Peripherals::steal();
+            let name = NameStore::load(&mut embassy_rp.FLASH);
             Self {
                 embassy_rp,
                 pins: Default::default(),
                 gpio_outputs: Slot::new(),
                 pwms: Slot::new(),
                 pios: PioControl::init(),
+                unique_id,
+                name,
+                crash: crash::take(),
+                crash_reported: false,
             }
         };
         this
     }
 
+    /// The chip ID as hex, e.g. for `DeviceInfo`.
+    pub fn unique_id(&self) -> &str {
+        self.unique_id
+    }
+
+    /// The crash to report; only the first call returns it.
+    pub fn take_crash_report(&mut self) -> Option<&crash::Report> {
+        if core::mem::replace(&mut self.crash_reported, true) {
+            return None;
+        }
+        self.crash.as_ref()
+    }
+
+    /// The name stored in flash, empty if none.
+    pub fn device_name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    /// Store `name` in flash; an empty one clears it.
+    pub fn set_device_name(&mut self, name: &str) -> ResponseBody {
+        if name.len() > DEVICE_NAME_MAX_LEN {
+            return error_response(ResponseCommonErrorCode::InvalidArgument, name.len() as _);
+        }
+        if let Err(e) = self.name.store(&mut self.embassy_rp.FLASH, name) {
+            device_error!("Storing the name failed: {:?}", e);
+            return error_response(ResponseCommonErrorCode::FlashError, 0);
+        }
+        success_response(0)
+    }
+
     pub fn gpio_output_init(&mut self, pin_num: usize, value: bool) -> ResponseBody {
         let pin = &mut self.pins[pin_num];
         check_pin_state!(pin, PinState::None);
@@ -124,6 +189,96 @@ impl<'d> PeripheralController<'d> {
         success_response(value as u64)
     }
 
+    /// Return a pin to `PinState::None`, dropping the driver that owns it.
+    /// Releasing a pin that is already free succeeds.
+    pub fn pin_deinit(&mut self, pin_num: usize) -> ResponseBody {
+        if pin_num >= self.pins.len() {
+            return error_response(ResponseCommonErrorCode::InvalidArgument, pin_num as _);
+        }
+        let pin = &mut self.pins[pin_num];
+        match pin.state {
+            PinState::None => {}
+            PinState::GpioOutput => {
+                // Dropping the `Output` disables the pad's output driver.
+                let removed = self.gpio_outputs.remove(pin.resource_index);
+                assert!(removed.is_some());
+            }
+            // PWM and PIO pins belong to their slice or state machine and are
+            // released with it (`pwm_deinit`, `pio_sm_deinit`).
+            _ => check_pin_state!(pin, PinState::GpioOutput),
+        }
+        *pin = Pin::default();
+        success_response(0)
+    }
+
+    /// Stop PWM slice `slice` and free the pins it drives.
+    pub fn pwm_deinit(&mut self, slice: usize) -> ResponseBody {
+        if slice > 7 {
+            return error_response(ResponseCommonErrorCode::InvalidArgument, slice as _);
+        }
+        for (pin_num, pin) in self.pins.iter_mut().enumerate() {
+            // GPIO n is wired to slice (n / 2) % 8.
+            let pwm_pin = matches!(pin.state, PinState::PwmOut | PinState::PwmIn);
+            if pwm_pin && (pin_num >> 1) & 7 == slice {
+                // Both outputs share one `Pwm`, dropped on the first removal.
+                self.pwms.remove(pin.resource_index);
+                *pin = Pin::default();
+            }
+        }
+        success_response(0)
+    }
+
+    /// Stop state machine `sm_num` of PIO block `pio_num` and free its pins.
+    pub fn pio_sm_deinit(&mut self, pio_num: usize, sm_num: usize) -> ResponseBody {
+        // `pio_sm_invoke!` panics on anything else.
+        if pio_num > 2 {
+            return error_response(ResponseCommonErrorCode::InvalidArgument, pio_num as _);
+        }
+        if sm_num > 3 {
+            return error_response(ResponseCommonErrorCode::InvalidArgument, sm_num as _);
+        }
+        pio_sm_invoke!(self.pios, pio_num, sm_num, set_enable, false);
+        let owner = pio_sm_index(pio_num, sm_num);
+        for pin in self.pins.iter_mut() {
+            if pin.state == PinState::Pio0 && pin.resource_index == owner {
+                *pin = Pin::default();
+            }
+        }
+        success_response(0)
+    }
+
+    /// Return every pin and peripheral to its power-on state. Loaded PIO
+    /// programs stay in instruction memory until replaced.
+    pub fn reset_all(&mut self) -> ResponseBody {
+        for pio_num in 0..3 {
+            for sm_num in 0..4 {
+                pio_sm_invoke!(self.pios, pio_num, sm_num, set_enable, false);
+            }
+        }
+        self.gpio_outputs.clear();
+        self.pwms.clear();
+        self.pins = Default::default();
+        success_response(0)
+    }
+
+    /// Reboot, into the USB bootloader if `bootsel` is set. The bootrom
+    /// arms the watchdog and returns, so the response still goes out.
+    pub fn reboot(&mut self, bootsel: bool) -> ResponseBody {
+        let flags = if bootsel {
+            REBOOT_TYPE_BOOTSEL
+        } else {
+            REBOOT_TYPE_NORMAL
+        };
+        // For BOOTSEL, p0 = 0 (no activity LED) and p1 = 0 (both the mass
+        // storage and PICOBOOT interfaces enabled).
+        let result = unsafe { embassy_rp::rom_data::reboot(flags, REBOOT_DELAY_MS, 0, 0) };
+        if result < 0 {
+            device_error!("Reboot failed: {}", result);
+            return error_response(ResponseCommonErrorCode::UnknownError, result as _);
+        }
+        success_response(0)
+    }
+
     // TODO: Uncomment and implement these modules as needed
 
     // pub fn pwm_init(
@@ -212,6 +367,7 @@ impl<'d> PeripheralController<'d> {
     //     check_pin_state!(pin, PinState::None);
 
     //     pin.state = PinState::Pio0;
+    //     pin.resource_index = pio_sm_index(pio_num, sm_num);
     //     let any_pin = unsafe { get_anypin_unchecked(&self.embassy_rp, pin_num) };
 
     //     #[rustfmt::skip]
@@ -69,6 +69,7 @@ macro_rules! sm_invoke {
             0 => $pio.sm0.$method(),
             1 => $pio.sm1.$method(),
             2 => $pio.sm2.$method(),
+            3 => $pio.sm3.$method(),
             _ => panic!("Invalid PIO number"),
         }
     };
@@ -77,6 +78,7 @@ macro_rules! sm_invoke {
             0 => $pio.sm0.$method($($args),*),
             1 => $pio.sm1.$method($($args),*),
             2 => $pio.sm2.$method($($args),*),
+            3 => $pio.sm3.$method($($args),*),
             _ => panic!("Invalid PIO number"),
         }
     };
@@ -89,6 +91,7 @@ macro_rules! sm_run {
             0 => $run(&mut $pio.sm0),
             1 => $run(&mut $pio.sm1),
             2 => $run(&mut $pio.sm2),
+            3 => $run(&mut $pio.sm3),
             _ => panic!("Invalid number"),
         }
     };
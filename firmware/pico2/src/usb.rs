@@ -7,11 +7,11 @@ use embassy_usb::{
     driver::EndpointError,
 };
 use static_cell::StaticCell;
-use {defmt_rtt as _, panic_probe as _};
 
 pub fn init(
     spawner: Spawner,
     p: embassy_rp::Peripherals,
+    serial_number: &'static str,
 ) -> CdcAcmClass<'static, Driver<'static, USB>> {
     // Create the driver, from the HAL.
     let driver = Driver::new(p.USB, crate::Irqs);
@@ -21,7 +21,8 @@ pub fn init(
         let mut config = embassy_usb::Config::new(0x9527, 0xacdc);
         config.manufacturer = Some("Enbop");
         config.product = Some("Copi");
-        config.serial_number = Some("88489527");
+        // Each board gets its own serial so the host can tell them apart.
+        config.serial_number = Some(serial_number);
         config.max_power = 100;
         config.max_packet_size_0 = 64;
         config
@@ -0,0 +1,11 @@
+/// The chip's unique 64-bit ID from OTP, as 16 upper-case hex digits.
+pub fn unique_id_hex() -> [u8; 16] {
+    // Only unreadable OTP fails, which leaves an all-zero ID.
+    let id = embassy_rp::otp::get_chipid().unwrap_or(0);
+    let mut hex = [0; 16];
+    for (i, digit) in hex.iter_mut().enumerate() {
+        let nibble = (id >> ((15 - i) * 4)) & 0xf;
+        *digit = b"0123456789ABCDEF"[nibble as usize];
+    }
+    hex
+}
@@ -0,0 +1,69 @@
+use embassy_rp::{
+    flash::{Blocking, ERASE_SIZE, Error, Flash, PAGE_SIZE},
+    peripherals::FLASH,
+};
+
+/// The flash size `memory.x` is written for.
+const FLASH_SIZE: usize = 2 * 1024 * 1024;
+/// The last sector, which `memory.x` keeps out of the firmware image so a
+/// new firmware does not overwrite the name.
+const NAME_OFFSET: u32 = (FLASH_SIZE - ERASE_SIZE) as u32;
+const MAGIC: [u8; 4] = *b"CPNM";
+
+/// Longest name stored, in bytes.
+pub const DEVICE_NAME_MAX_LEN: usize = 24;
+
+/// The name given to this board, kept in flash as the magic, a length byte
+/// and the name.
+pub struct NameStore {
+    name: [u8; DEVICE_NAME_MAX_LEN],
+    len: usize,
+}
+
+impl NameStore {
+    /// Read the stored name. Erased or foreign contents read as no name.
+    pub fn load(flash: &mut FLASH) -> Self {
+        let mut flash = Flash::<_, Blocking, FLASH_SIZE>::new_blocking(flash);
+        let mut record = [0; MAGIC.len() + 1 + DEVICE_NAME_MAX_LEN];
+        let mut store = Self {
+            name: [0; DEVICE_NAME_MAX_LEN],
+            len: 0,
+        };
+        if flash.blocking_read(NAME_OFFSET, &mut record).is_err() {
+            return store;
+        }
+        let len = record[MAGIC.len()] as usize;
+        let name = &record[MAGIC.len() + 1..];
+        if record[..MAGIC.len()] == MAGIC
+            && len <= DEVICE_NAME_MAX_LEN
+            && core::str::from_utf8(&name[..len]).is_ok()
+        {
+            store.name.copy_from_slice(name);
+            store.len = len;
+        }
+        store
+    }
+
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.len]).unwrap_or_default()
+    }
+
+    /// Replace the stored name; an empty one erases it. The caller checks
+    /// the length.
+    pub fn store(&mut self, flash: &mut FLASH, name: &str) -> Result<(), Error> {
+        let mut flash = Flash::<_, Blocking, FLASH_SIZE>::new_blocking(flash);
+        flash.blocking_erase(NAME_OFFSET, NAME_OFFSET + ERASE_SIZE as u32)?;
+        if !name.is_empty() {
+            // Program a whole page, padded as erased flash.
+            let mut page = [0xff; PAGE_SIZE];
+            page[..MAGIC.len()].copy_from_slice(&MAGIC);
+            page[MAGIC.len()] = name.len() as u8;
+            page[MAGIC.len() + 1..][..name.len()].copy_from_slice(name.as_bytes());
+            flash.blocking_write(NAME_OFFSET, &page)?;
+        }
+        self.name = [0; DEVICE_NAME_MAX_LEN];
+        self.name[..name.len()].copy_from_slice(name.as_bytes());
+        self.len = name.len();
+        Ok(())
+    }
+}
@@ -1,5 +1,10 @@
+mod chip_id;
+mod device_name;
 mod pin;
-mod slot;
+mod text;
 
+pub use chip_id::*;
+pub use copi_slot::Slot;
+pub use device_name::*;
 pub use pin::*;
-pub use slot::*;
+pub use text::*;
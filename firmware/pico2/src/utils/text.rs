@@ -0,0 +1,40 @@
+use core::fmt;
+
+/// A string of at most `N` bytes. Writes past the end are dropped, never
+/// splitting a character.
+#[derive(Clone, Copy)]
+pub struct TextBuf<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> TextBuf<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+        }
+    }
+
+    /// Empty if the contents are not valid, e.g. read from uninitialised RAM.
+    pub fn as_str(&self) -> &str {
+        self.buf
+            .get(..self.len)
+            .and_then(|bytes| core::str::from_utf8(bytes).ok())
+            .unwrap_or_default()
+    }
+}
+
+impl<const N: usize> fmt::Write for TextBuf<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            let end = self.len + c.len_utf8();
+            if end > N {
+                break;
+            }
+            c.encode_utf8(&mut self.buf[self.len..end]);
+            self.len = end;
+        }
+        Ok(())
+    }
+}
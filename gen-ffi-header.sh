@@ -0,0 +1,14 @@
+set -e
+
+# Regenerate crates/copi-ffi/include/copi.h with the cbindgen CLI
+# (cargo install cbindgen). It expands the crate's macros, which needs a
+# nightly toolchain. With --verify, fail instead if the checked-in header
+# is out of date.
+
+cd crates/copi-ffi
+
+if [ "$1" == "--verify" ]; then
+    RUSTUP_TOOLCHAIN=nightly cbindgen --config cbindgen.toml --verify --output include/copi.h
+else
+    RUSTUP_TOOLCHAIN=nightly cbindgen --config cbindgen.toml --output include/copi.h
+fi